@@ -0,0 +1,898 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::{env, fmt, fs, io};
+use crate::converters::{get_public_key_64_from_id, is_id_checksum_valid};
+use crate::env_names::{
+    ENV_CONFIG,
+    ENV_CPU_LIST,
+    ENV_HUGE_PAGES,
+    ENV_ID,
+    ENV_ID_SCHEDULE,
+    ENV_JOURNAL_FILE,
+    ENV_METRICS_ADDR,
+    ENV_MINING_SEED,
+    ENV_NONCE_SOURCE,
+    ENV_NUMBER_OF_THREADS,
+    ENV_PEERS,
+    ENV_RANDOM_SEED,
+    ENV_RUST_LOG,
+    ENV_SERVER_IP,
+    ENV_SERVER_PORT,
+    ENV_SIGNING_SEED,
+    ENV_SOLUTION_THRESHOLD,
+    ENV_SUBMIT_MODE,
+    ENV_VERSION,
+};
+use crate::random_seed::parse_random_seed;
+use crate::solution_threshold::parse_solution_threshold;
+use crate::types::network::Protocol;
+use crate::types::{Id, PublicKey64, Seed, Version, PORT};
+use crate::version::parse_version;
+
+pub const DEFAULT_CONFIG_FILE: &str = "qiner.toml";
+pub const DEFAULT_JOURNAL_FILE: &str = "solutions.journal";
+
+/// Every setting, named as in `.env`. The TOML file uses the same names in lower case
+pub const SETTINGS: [&str; 18] = [
+    ENV_NUMBER_OF_THREADS,
+    ENV_ID,
+    ENV_SERVER_IP,
+    ENV_SERVER_PORT,
+    ENV_VERSION,
+    ENV_RANDOM_SEED,
+    ENV_SOLUTION_THRESHOLD,
+    ENV_NONCE_SOURCE,
+    ENV_MINING_SEED,
+    ENV_HUGE_PAGES,
+    ENV_CPU_LIST,
+    ENV_JOURNAL_FILE,
+    ENV_PEERS,
+    ENV_SUBMIT_MODE,
+    ENV_SIGNING_SEED,
+    ENV_RUST_LOG,
+    ENV_ID_SCHEDULE,
+    ENV_METRICS_ADDR,
+];
+
+/// Letters of a Qubic seed
+pub const SIGNING_SEED_LENGTH: usize = 55;
+
+/// Where the miner takes random nonces from
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum NonceSourceKind {
+    /// RDRAND when the CPU supports it, otherwise the OS generator
+    #[default]
+    Auto,
+    RdRand,
+    Os,
+    /// ChaCha20 seeded once from the OS generator
+    ChaCha,
+}
+
+/// How the mining threads share their time between several IDs
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum IdSchedule {
+    /// The threads are divided between the IDs by weight, each thread mines for one ID
+    #[default]
+    Share,
+    /// Every thread goes through the IDs nonce by nonce, an ID takes as many nonces of a round as its weight
+    RoundRobin,
+}
+
+impl IdSchedule {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IdSchedule::Share => "share",
+            IdSchedule::RoundRobin => "round-robin",
+        }
+    }
+}
+
+/// An ID to mine for with its weight in the worker time
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Identity {
+    pub id: Id,
+    pub public_key: PublicKey64,
+    pub weight: u32,
+}
+
+impl Identity {
+    pub fn id_string(&self) -> String {
+        String::from_utf8_lossy(&self.id).into_owned()
+    }
+}
+
+/// How found solutions are spread over the peers
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SubmitMode {
+    /// One peer per submission, the next one takes over when it fails
+    #[default]
+    RoundRobin,
+    /// Every peer gets every solution
+    Broadcast,
+}
+
+impl SubmitMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SubmitMode::RoundRobin => "round-robin",
+            SubmitMode::Broadcast => "broadcast",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldError {
+    pub name: &'static str,
+    pub message: String,
+}
+
+impl Display for FieldError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.name, self.message)
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, io::Error),
+    Parse(String),
+    Invalid(Vec<FieldError>),
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, err) => write!(f, "failed to read {}: {err}", path.display()),
+            ConfigError::Parse(message) => write!(f, "failed to parse the config file: {message}"),
+            ConfigError::Invalid(errors) => {
+                write!(f, "invalid configuration ({} errors)", errors.len())?;
+                errors.iter().try_for_each(|error| write!(f, "\n  {error}"))
+            }
+        }
+    }
+}
+
+impl Error for ConfigError {}
+
+/// Unvalidated setting values keyed by their env name
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct RawConfig {
+    values: BTreeMap<&'static str, String>,
+}
+
+impl RawConfig {
+    pub fn from_env() -> Self {
+        let mut raw = RawConfig::default();
+        for name in SETTINGS {
+            if let Ok(value) = env::var(name) {
+                raw.set(name, value);
+            }
+        }
+
+        raw
+    }
+
+    pub fn from_toml_file(path: &Path) -> Result<Self, ConfigError> {
+        let content = fs::read_to_string(path).map_err(|err| ConfigError::Io(path.to_path_buf(), err))?;
+        RawConfig::from_toml_str(&content)
+    }
+
+    /// Numbers are taken as is and arrays are joined with commas, so `random_seed = [1, 0, 233, ...]` works too
+    pub fn from_toml_str(content: &str) -> Result<Self, ConfigError> {
+        let table = content.parse::<toml::Table>().map_err(|err| ConfigError::Parse(err.to_string()))?;
+
+        let mut raw = RawConfig::default();
+        for (key, value) in table {
+            let name = SETTINGS.into_iter()
+                .find(|name| name.eq_ignore_ascii_case(&key))
+                .ok_or_else(|| ConfigError::Parse(format!("unknown setting '{key}'")))?;
+
+            let value = match value {
+                toml::Value::Array(items) => items.iter().map(toml_scalar_to_string).collect::<Option<Vec<String>>>().map(|items| items.join(",")),
+                value => toml_scalar_to_string(&value),
+            }.ok_or_else(|| ConfigError::Parse(format!("unsupported value type for '{key}'")))?;
+
+            raw.set(name, value);
+        }
+
+        Ok(raw)
+    }
+
+    /// The settings of a `.env` file, other variables in it are left out
+    // The deprecation points to `from_path`, which would load the file into the process environment
+    #[allow(deprecated)]
+    pub fn from_dotenv_file(path: &Path) -> Result<Self, ConfigError> {
+        let items = dotenv::from_path_iter(path).map_err(|err| ConfigError::Parse(format!("{}: {err}", path.display())))?;
+
+        let mut raw = RawConfig::default();
+        for item in items {
+            let (key, value) = item.map_err(|err| ConfigError::Parse(format!("{}: {err}", path.display())))?;
+            if let Some(name) = SETTINGS.into_iter().find(|name| *name == key) {
+                raw.set(name, value);
+            }
+        }
+
+        Ok(raw)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(String::as_str)
+    }
+
+    pub fn set(&mut self, name: &'static str, value: impl Into<String>) {
+        self.values.insert(name, value.into());
+    }
+
+    /// Values of `other` take precedence
+    pub fn merge(mut self, other: RawConfig) -> Self {
+        self.values.extend(other.values);
+        self
+    }
+}
+
+/// Where the settings come from, kept so they can be read again when the files change
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigSources {
+    /// TOML file, `None` for `qiner.toml` which may be missing
+    config_file: Option<PathBuf>,
+    /// The `.env` file loaded at startup
+    dotenv_file: Option<PathBuf>,
+    /// The environment as it was before `.env` was loaded into it
+    process_env: RawConfig,
+    cli: RawConfig,
+}
+
+impl ConfigSources {
+    /// The config file is `config_file`, else `CONFIG`, else `qiner.toml` if it exists
+    pub fn new(config_file: Option<&Path>, dotenv_file: Option<PathBuf>, process_env: RawConfig, cli: RawConfig) -> Self {
+        let config_file = config_file.map(Path::to_path_buf).or_else(|| env::var_os(ENV_CONFIG).map(PathBuf::from));
+
+        ConfigSources { config_file, dotenv_file, process_env, cli }
+    }
+
+    /// Reads the files again and merges the sources with precedence
+    /// `cli` > environment > `.env` > TOML file
+    pub fn load(&self) -> Result<RawConfig, ConfigError> {
+        let file = match &self.config_file {
+            Some(path) => RawConfig::from_toml_file(path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => RawConfig::from_toml_file(Path::new(DEFAULT_CONFIG_FILE))?,
+            None => RawConfig::default(),
+        };
+        let dotenv = match &self.dotenv_file {
+            Some(path) => RawConfig::from_dotenv_file(path)?,
+            None => RawConfig::default(),
+        };
+
+        Ok(file.merge(dotenv).merge(self.process_env.clone()).merge(self.cli.clone()))
+    }
+
+    /// The files the settings are read from, whether they exist or not
+    pub fn files(&self) -> Vec<PathBuf> {
+        let config_file = self.config_file.clone().unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_FILE));
+
+        std::iter::once(config_file).chain(self.dotenv_file.clone()).collect()
+    }
+}
+
+fn toml_scalar_to_string(value: &toml::Value) -> Option<String> {
+    match value {
+        toml::Value::String(value) => Some(value.clone()),
+        toml::Value::Integer(value) => Some(value.to_string()),
+        toml::Value::Boolean(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+/// Seed of the identity signing the solutions, never printed
+#[derive(Clone, PartialEq, Eq)]
+pub struct SigningSeed(String);
+
+impl SigningSeed {
+    /// Only the signing seed, for the commands which need nothing else of the configuration
+    pub fn from_raw(raw: &RawConfig) -> Result<Option<SigningSeed>, ConfigError> {
+        let mut errors = Vec::new();
+        let signing_seed = parse_optional_field(raw, ENV_SIGNING_SEED, &mut errors, parse_signing_seed);
+
+        if !errors.is_empty() {
+            return Err(ConfigError::Invalid(errors));
+        }
+
+        Ok(signing_seed)
+    }
+
+    /// 55 lowercase letters, the error leaves the seed out
+    pub fn parse(raw: &str) -> Result<SigningSeed, String> {
+        parse_signing_seed(raw)
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for SigningSeed {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.write_str("SigningSeed(..)")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Config {
+    pub number_of_threads: usize,
+    /// The first of `identities`
+    pub id: Id,
+    pub public_key: PublicKey64,
+    /// Every ID to mine for, at least one
+    pub identities: Vec<Identity>,
+    pub id_schedule: IdSchedule,
+    /// Port of the peers given without one
+    pub server_port: u16,
+    /// `host:port` of every node to submit to, `SERVER_IP` first
+    pub peers: Vec<String>,
+    pub submit_mode: SubmitMode,
+    pub version: Version,
+    pub random_seed: Seed,
+    pub solution_threshold: usize,
+    pub nonce_source: NonceSourceKind,
+    /// Master seed of the deterministic mode, every worker derives its own nonce stream from it
+    pub mining_seed: Option<u64>,
+    /// Back the neuron buffers with transparent huge pages
+    pub huge_pages: bool,
+    /// CPUs the mining threads are pinned to, empty to leave the placement to the OS
+    pub cpu_list: Vec<usize>,
+    /// Append-only record of the found solutions, replayed on startup
+    pub journal_file: PathBuf,
+    /// Solutions are signed by this identity, they go out anonymous and unsigned without it
+    pub signing_seed: Option<SigningSeed>,
+    /// `env_logger` filter, `None` to keep the one of the environment
+    pub log_filter: Option<String>,
+    /// Where the Prometheus `/metrics` endpoint listens, off without it
+    pub metrics_addr: Option<SocketAddr>,
+}
+
+impl Config {
+    /// Validates every setting and reports all the errors together
+    pub fn from_raw(raw: &RawConfig) -> Result<Config, ConfigError> {
+        let mut errors = Vec::new();
+
+        let default_threads = std::thread::available_parallelism().map(|threads| threads.get()).unwrap_or(1);
+        let number_of_threads = parse_field(raw, ENV_NUMBER_OF_THREADS, Some(default_threads), &mut errors, parse_number_of_threads);
+        let identities = parse_field(raw, ENV_ID, None, &mut errors, parse_identities);
+        let id_schedule = parse_field(raw, ENV_ID_SCHEDULE, Some(IdSchedule::Share), &mut errors, parse_id_schedule);
+        let server_ip = parse_optional_field(raw, ENV_SERVER_IP, &mut errors, parse_server_ip);
+        let server_port = parse_field(raw, ENV_SERVER_PORT, Some(PORT), &mut errors, parse_port);
+        let peers = parse_field(raw, ENV_PEERS, Some(Vec::new()), &mut errors, parse_peers);
+        let submit_mode = parse_field(raw, ENV_SUBMIT_MODE, Some(SubmitMode::RoundRobin), &mut errors, parse_submit_mode);
+        if server_ip.is_none() && raw.get(ENV_SERVER_IP).is_none() && peers.as_ref().is_some_and(Vec::is_empty) {
+            errors.push(FieldError { name: ENV_SERVER_IP, message: format!("is required unless {ENV_PEERS} is set") });
+        }
+        let version = parse_field(raw, ENV_VERSION, None, &mut errors, parse_version);
+        let random_seed = parse_field(raw, ENV_RANDOM_SEED, None, &mut errors, parse_random_seed);
+        let solution_threshold = parse_field(raw, ENV_SOLUTION_THRESHOLD, None, &mut errors, parse_solution_threshold);
+        let nonce_source = parse_field(raw, ENV_NONCE_SOURCE, Some(NonceSourceKind::Auto), &mut errors, parse_nonce_source);
+        let mining_seed = parse_optional_field(raw, ENV_MINING_SEED, &mut errors, parse_mining_seed);
+        let huge_pages = parse_field(raw, ENV_HUGE_PAGES, Some(false), &mut errors, parse_bool);
+        let cpu_list = parse_field(raw, ENV_CPU_LIST, Some(Vec::new()), &mut errors, parse_cpu_list);
+        let journal_file = parse_field(raw, ENV_JOURNAL_FILE, Some(PathBuf::from(DEFAULT_JOURNAL_FILE)), &mut errors, parse_path);
+        let signing_seed = parse_optional_field(raw, ENV_SIGNING_SEED, &mut errors, parse_signing_seed);
+        let log_filter = parse_optional_field(raw, ENV_RUST_LOG, &mut errors, parse_log_filter);
+        let metrics_addr = parse_optional_field(raw, ENV_METRICS_ADDR, &mut errors, parse_metrics_addr);
+
+        if !errors.is_empty() {
+            return Err(ConfigError::Invalid(errors));
+        }
+
+        let identities = identities.unwrap();
+
+        let server_port = server_port.unwrap();
+        let mut peer_addrs = Vec::new();
+        for (host, port) in server_ip.into_iter().map(|host| (host, None)).chain(peers.unwrap()) {
+            let peer_addr = get_peer_addr(&host, port.unwrap_or(server_port));
+            if !peer_addrs.contains(&peer_addr) {
+                peer_addrs.push(peer_addr);
+            }
+        }
+
+        Ok(Config {
+            number_of_threads: number_of_threads.unwrap(),
+            id: identities[0].id,
+            public_key: identities[0].public_key,
+            identities,
+            id_schedule: id_schedule.unwrap(),
+            server_port,
+            peers: peer_addrs,
+            submit_mode: submit_mode.unwrap(),
+            version: version.unwrap(),
+            random_seed: random_seed.unwrap(),
+            solution_threshold: solution_threshold.unwrap(),
+            nonce_source: nonce_source.unwrap(),
+            mining_seed,
+            huge_pages: huge_pages.unwrap(),
+            cpu_list: cpu_list.unwrap(),
+            journal_file: journal_file.unwrap(),
+            signing_seed,
+            log_filter,
+            metrics_addr,
+        })
+    }
+
+    /// Names of the settings whose value differs in `other`, the peers are reported as `PEERS`
+    pub fn changed_settings(&self, other: &Config) -> Vec<&'static str> {
+        let settings = [
+            (ENV_NUMBER_OF_THREADS, self.number_of_threads != other.number_of_threads),
+            (ENV_ID, self.identities != other.identities),
+            (ENV_ID_SCHEDULE, self.id_schedule != other.id_schedule),
+            (ENV_PEERS, self.peers != other.peers),
+            (ENV_SUBMIT_MODE, self.submit_mode != other.submit_mode),
+            (ENV_VERSION, self.version != other.version),
+            (ENV_RANDOM_SEED, self.random_seed != other.random_seed),
+            (ENV_SOLUTION_THRESHOLD, self.solution_threshold != other.solution_threshold),
+            (ENV_NONCE_SOURCE, self.nonce_source != other.nonce_source),
+            (ENV_MINING_SEED, self.mining_seed != other.mining_seed),
+            (ENV_HUGE_PAGES, self.huge_pages != other.huge_pages),
+            (ENV_CPU_LIST, self.cpu_list != other.cpu_list),
+            (ENV_JOURNAL_FILE, self.journal_file != other.journal_file),
+            (ENV_SIGNING_SEED, self.signing_seed != other.signing_seed),
+            (ENV_RUST_LOG, self.log_filter != other.log_filter),
+            (ENV_METRICS_ADDR, self.metrics_addr != other.metrics_addr),
+        ];
+
+        settings.into_iter().filter(|(_, is_changed)| *is_changed).map(|(name, _)| name).collect()
+    }
+
+    pub fn id_string(&self) -> String {
+        String::from_utf8_lossy(&self.id).into_owned()
+    }
+
+    pub fn protocol(&self) -> Protocol {
+        self.version[1]
+    }
+}
+
+/// The settings needed to score nonces, so scoring works without the network settings
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScoringConfig {
+    /// Every ID of the configuration, at least one
+    pub identities: Vec<Identity>,
+    pub random_seed: Seed,
+    pub solution_threshold: usize,
+}
+
+impl ScoringConfig {
+    pub fn from_raw(raw: &RawConfig) -> Result<ScoringConfig, ConfigError> {
+        let mut errors = Vec::new();
+
+        let identities = parse_field(raw, ENV_ID, None, &mut errors, parse_identities);
+        let random_seed = parse_field(raw, ENV_RANDOM_SEED, None, &mut errors, parse_random_seed);
+        let solution_threshold = parse_field(raw, ENV_SOLUTION_THRESHOLD, None, &mut errors, parse_solution_threshold);
+
+        if !errors.is_empty() {
+            return Err(ConfigError::Invalid(errors));
+        }
+
+        Ok(ScoringConfig {
+            identities: identities.unwrap(),
+            random_seed: random_seed.unwrap(),
+            solution_threshold: solution_threshold.unwrap(),
+        })
+    }
+}
+
+fn parse_field<T>(
+    raw: &RawConfig,
+    name: &'static str,
+    default: Option<T>,
+    errors: &mut Vec<FieldError>,
+    parse: impl FnOnce(&str) -> Result<T, String>,
+) -> Option<T> {
+    let result = match (raw.get(name), default) {
+        (Some(value), _) => parse(value),
+        (None, Some(default)) => Ok(default),
+        (None, None) => Err("is required".to_string()),
+    };
+
+    result.map_err(|message| errors.push(FieldError { name, message })).ok()
+}
+
+fn parse_optional_field<T>(
+    raw: &RawConfig,
+    name: &'static str,
+    errors: &mut Vec<FieldError>,
+    parse: impl FnOnce(&str) -> Result<T, String>,
+) -> Option<T> {
+    raw.get(name)?;
+    parse_field(raw, name, None, errors, parse)
+}
+
+fn parse_number_of_threads(raw: &str) -> Result<usize, String> {
+    match raw.trim().parse::<usize>() {
+        Ok(threads) if threads > 0 => Ok(threads),
+        _ => Err(format!("'{}' is not a number greater than 0", raw.trim())),
+    }
+}
+
+fn parse_id(raw: &str) -> Result<Id, String> {
+    let raw = raw.trim();
+    let id: Id = raw.as_bytes().try_into().map_err(|_| format!("must be 60 characters long, got {}", raw.len()))?;
+    if !id.iter().all(u8::is_ascii_uppercase) {
+        return Err("must contain only uppercase letters A-Z".to_string());
+    }
+    if !is_id_checksum_valid(&id) {
+        return Err("has an invalid checksum".to_string());
+    }
+
+    Ok(id)
+}
+
+/// Comma separated IDs, each one optionally followed by `:weight`, 1 by default
+pub fn parse_identities(raw: &str) -> Result<Vec<Identity>, String> {
+    let mut identities: Vec<Identity> = Vec::new();
+    for item in raw.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+        let (id, weight) = item.split_once(':').unwrap_or((item, "1"));
+        let id = parse_id(id).map_err(|message| if identities.is_empty() { message } else { format!("ID {}: {message}", identities.len() + 1) })?;
+        let weight = match weight.trim().parse::<u32>() {
+            Ok(weight) if weight > 0 => weight,
+            _ => return Err(format!("the weight '{}' is not a number greater than 0", weight.trim())),
+        };
+        if identities.iter().any(|identity| identity.id == id) {
+            return Err(format!("{} is given twice", String::from_utf8_lossy(&id)));
+        }
+
+        let mut public_key = PublicKey64::default();
+        get_public_key_64_from_id(&id, &mut public_key);
+        identities.push(Identity { id, public_key, weight });
+    }
+
+    if identities.is_empty() {
+        return Err("is empty".to_string());
+    }
+
+    Ok(identities)
+}
+
+fn parse_id_schedule(raw: &str) -> Result<IdSchedule, String> {
+    match raw.trim().to_ascii_lowercase().as_str() {
+        "share" => Ok(IdSchedule::Share),
+        "round-robin" => Ok(IdSchedule::RoundRobin),
+        _ => Err(format!("'{}' is not one of share, round-robin", raw.trim())),
+    }
+}
+
+fn parse_server_ip(raw: &str) -> Result<String, String> {
+    let raw = raw.trim();
+    if raw.is_empty() || raw.contains(char::is_whitespace) {
+        return Err(format!("'{raw}' is not a valid host"));
+    }
+
+    Ok(raw.to_string())
+}
+
+fn parse_path(raw: &str) -> Result<PathBuf, String> {
+    match raw.trim() {
+        "" => Err("the path is empty".to_string()),
+        path => Ok(PathBuf::from(path)),
+    }
+}
+
+/// Comma separated `host` or `host:port` items, IPv6 addresses with a port go in brackets
+pub fn parse_peers(raw: &str) -> Result<Vec<(String, Option<u16>)>, String> {
+    raw.split(',').map(str::trim).filter(|peer| !peer.is_empty()).map(|peer| {
+        let (host, port) = match peer.rsplit_once(':') {
+            // A bare IPv6 address has several colons and no port
+            Some((host, port)) if !host.contains(':') || host.ends_with(']') => (host, Some(port)),
+            _ => (peer, None),
+        };
+
+        Ok((parse_server_ip(host)?, port.map(parse_port).transpose()?))
+    }).collect()
+}
+
+fn get_peer_addr(host: &str, port: u16) -> String {
+    if host.contains(':') && !host.starts_with('[') {
+        format!("[{host}]:{port}")
+    } else {
+        format!("{host}:{port}")
+    }
+}
+
+fn parse_submit_mode(raw: &str) -> Result<SubmitMode, String> {
+    match raw.trim().to_ascii_lowercase().as_str() {
+        "round-robin" => Ok(SubmitMode::RoundRobin),
+        "broadcast" => Ok(SubmitMode::Broadcast),
+        _ => Err(format!("'{}' is not one of round-robin, broadcast", raw.trim())),
+    }
+}
+
+fn parse_port(raw: &str) -> Result<u16, String> {
+    match raw.trim().parse::<u16>() {
+        Ok(port) if port > 0 => Ok(port),
+        _ => Err(format!("'{}' is not a port in 1..=65535", raw.trim())),
+    }
+}
+
+fn parse_nonce_source(raw: &str) -> Result<NonceSourceKind, String> {
+    match raw.trim().to_ascii_lowercase().as_str() {
+        "auto" => Ok(NonceSourceKind::Auto),
+        "rdrand" => Ok(NonceSourceKind::RdRand),
+        "os" => Ok(NonceSourceKind::Os),
+        "chacha" => Ok(NonceSourceKind::ChaCha),
+        _ => Err(format!("'{}' is not one of auto, rdrand, os, chacha", raw.trim())),
+    }
+}
+
+fn parse_mining_seed(raw: &str) -> Result<u64, String> {
+    raw.trim().parse::<u64>().map_err(|_| format!("'{}' is not a number in 0..=18446744073709551615", raw.trim()))
+}
+
+/// The errors leave the seed out, they end up in the logs
+fn parse_signing_seed(raw: &str) -> Result<SigningSeed, String> {
+    let seed = raw.trim();
+    if seed.chars().count() != SIGNING_SEED_LENGTH {
+        return Err(format!("has {} characters instead of {SIGNING_SEED_LENGTH}", seed.chars().count()));
+    }
+    if !seed.chars().all(|character| character.is_ascii_lowercase()) {
+        return Err("may only have lowercase letters a-z".to_string());
+    }
+
+    Ok(SigningSeed(seed.to_string()))
+}
+
+fn parse_log_filter(raw: &str) -> Result<String, String> {
+    match raw.trim() {
+        "" => Err("the filter is empty".to_string()),
+        filter => Ok(filter.to_string()),
+    }
+}
+
+fn parse_metrics_addr(raw: &str) -> Result<SocketAddr, String> {
+    raw.trim().parse().map_err(|_| format!("'{}' is not an address like 127.0.0.1:9100", raw.trim()))
+}
+
+fn parse_bool(raw: &str) -> Result<bool, String> {
+    match raw.trim().to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Ok(true),
+        "false" | "no" | "off" | "0" => Ok(false),
+        _ => Err(format!("'{}' is not a boolean", raw.trim())),
+    }
+}
+
+/// Parses a list like `0-3,8,10-11`
+pub fn parse_cpu_list(raw: &str) -> Result<Vec<usize>, String> {
+    let mut cpu_list = Vec::new();
+    for item in raw.split(',').map(str::trim).filter(|item| !item.is_empty()) {
+        let (first, last) = item.split_once('-').unwrap_or((item, item));
+        let (Ok(first), Ok(last)) = (first.trim().parse::<usize>(), last.trim().parse::<usize>()) else {
+            return Err(format!("'{item}' is not a CPU number or range"));
+        };
+        if first > last {
+            return Err(format!("'{item}' is a descending range"));
+        }
+
+        cpu_list.extend(first..=last);
+    }
+
+    Ok(cpu_list)
+}
+
+#[cfg(test)]
+const TEST_RANDOM_SEED: &str = "1,0,233,9,136,69,43,139,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0";
+
+#[cfg(test)]
+fn valid_raw_config() -> RawConfig {
+    let mut raw = RawConfig::default();
+    raw.set(ENV_NUMBER_OF_THREADS, "8");
+    raw.set(ENV_ID, "UBAZRCVPOZTDKGCBNPGYFUPLZXDDNHSEGJRTAJKWJBHJDKHMAKVVFAKCZGRI");
+    raw.set(ENV_SERVER_IP, "8.8.8.8");
+    raw.set(ENV_VERSION, "1.142.1");
+    raw.set(ENV_RANDOM_SEED, TEST_RANDOM_SEED);
+    raw.set(ENV_SOLUTION_THRESHOLD, "22");
+
+    raw
+}
+
+#[test]
+fn test_config_from_raw() {
+    let config = Config::from_raw(&valid_raw_config()).unwrap();
+
+    assert_eq!(8, config.number_of_threads);
+    assert_eq!("UBAZRCVPOZTDKGCBNPGYFUPLZXDDNHSEGJRTAJKWJBHJDKHMAKVVFAKCZGRI", config.id_string());
+    assert_eq!(PORT, config.server_port);
+    assert_eq!(vec![format!("8.8.8.8:{PORT}")], config.peers);
+    assert_eq!(SubmitMode::RoundRobin, config.submit_mode);
+    assert_eq!([1, 142, 1], config.version);
+    assert_eq!(142, config.protocol());
+    assert_eq!([1, 0, 233, 9], config.random_seed[..4]);
+    assert_eq!(22, config.solution_threshold);
+    assert_eq!(NonceSourceKind::Auto, config.nonce_source);
+    assert_eq!(None, config.mining_seed);
+    assert!(!config.huge_pages);
+    assert!(config.cpu_list.is_empty());
+    assert_eq!(Path::new(DEFAULT_JOURNAL_FILE), config.journal_file);
+    assert_eq!(None, config.signing_seed);
+    assert_eq!(None, config.log_filter);
+    assert_eq!(vec![Identity { id: config.id, public_key: config.public_key, weight: 1 }], config.identities);
+    assert_eq!(IdSchedule::Share, config.id_schedule);
+    assert_eq!(None, config.metrics_addr);
+}
+
+#[test]
+fn test_config_identities() {
+    let mut raw = valid_raw_config();
+    raw.set(ENV_ID, "UBAZRCVPOZTDKGCBNPGYFUPLZXDDNHSEGJRTAJKWJBHJDKHMAKVVFAKCZGRI:3, BZBQFLLBNCXEMGLOBHUVFTLUPLVCPQUASSILFABOFFBCADQSSUPNWLZBQEXK");
+    raw.set(ENV_ID_SCHEDULE, "round-robin");
+    let config = Config::from_raw(&raw).unwrap();
+
+    let ids = config.identities.iter().map(|identity| (identity.id_string(), identity.weight)).collect::<Vec<_>>();
+    assert_eq!(vec![
+        ("UBAZRCVPOZTDKGCBNPGYFUPLZXDDNHSEGJRTAJKWJBHJDKHMAKVVFAKCZGRI".to_string(), 3),
+        ("BZBQFLLBNCXEMGLOBHUVFTLUPLVCPQUASSILFABOFFBCADQSSUPNWLZBQEXK".to_string(), 1),
+    ], ids);
+    assert_eq!(config.identities[0].public_key, config.public_key);
+    assert_eq!(IdSchedule::RoundRobin, config.id_schedule);
+
+    assert!(parse_identities("UBAZRCVPOZTDKGCBNPGYFUPLZXDDNHSEGJRTAJKWJBHJDKHMAKVVFAKCZGRI:0").is_err());
+    assert!(parse_identities("UBAZRCVPOZTDKGCBNPGYFUPLZXDDNHSEGJRTAJKWJBHJDKHMAKVVFAKCZGRI,UBAZRCVPOZTDKGCBNPGYFUPLZXDDNHSEGJRTAJKWJBHJDKHMAKVVFAKCZGRI").is_err());
+    assert!(parse_identities(" , ").is_err());
+    assert!(parse_id_schedule("weighted").is_err());
+}
+
+#[test]
+fn test_config_changed_settings() {
+    let config = Config::from_raw(&valid_raw_config()).unwrap();
+    assert!(config.changed_settings(&config).is_empty());
+
+    let mut raw = valid_raw_config();
+    raw.set(ENV_SERVER_PORT, "21842");
+    raw.set(ENV_SOLUTION_THRESHOLD, "25");
+    raw.set(ENV_HUGE_PAGES, "true");
+    raw.set(ENV_RUST_LOG, "qiner=debug");
+    raw.set(ENV_METRICS_ADDR, "127.0.0.1:9100");
+    let changed = Config::from_raw(&raw).unwrap();
+
+    assert_eq!(vec![ENV_PEERS, ENV_SOLUTION_THRESHOLD, ENV_HUGE_PAGES, ENV_RUST_LOG, ENV_METRICS_ADDR], config.changed_settings(&changed));
+    assert_eq!(Some("qiner=debug"), changed.log_filter.as_deref());
+    assert_eq!(Some(SocketAddr::from(([127, 0, 0, 1], 9100))), changed.metrics_addr);
+    assert!(parse_metrics_addr("localhost").is_err());
+}
+
+#[test]
+fn test_config_signing_seed() {
+    let seed = "abcdefghijklmnopqrstuvwxyzabcdefghijklmnopqrstuvwxyzabc";
+    let mut raw = valid_raw_config();
+    raw.set(ENV_SIGNING_SEED, seed);
+    let config = Config::from_raw(&raw).unwrap();
+    assert_eq!(Some(seed), config.signing_seed.as_ref().map(SigningSeed::expose));
+    assert!(!format!("{config:?}").contains(seed));
+
+    raw.set(ENV_SIGNING_SEED, seed.to_ascii_uppercase());
+    let err = Config::from_raw(&raw).unwrap_err().to_string();
+    assert!(err.contains(ENV_SIGNING_SEED));
+    assert!(!err.contains(&seed.to_ascii_uppercase()));
+    raw.set(ENV_SIGNING_SEED, "abc");
+    assert!(Config::from_raw(&raw).is_err());
+    assert!(SigningSeed::from_raw(&raw).is_err());
+
+    let mut raw = RawConfig::default();
+    assert_eq!(None, SigningSeed::from_raw(&raw).unwrap());
+    raw.set(ENV_SIGNING_SEED, seed);
+    assert_eq!(Some(seed), SigningSeed::from_raw(&raw).unwrap().as_ref().map(SigningSeed::expose));
+}
+
+#[test]
+fn test_parse_cpu_list() {
+    assert_eq!(Ok(vec![0, 1, 2, 3, 8, 10, 11]), parse_cpu_list("0-3, 8,10-11"));
+    assert_eq!(Ok(vec![]), parse_cpu_list(""));
+    assert!(parse_cpu_list("3-1").is_err());
+    assert!(parse_cpu_list("a").is_err());
+}
+
+#[test]
+fn test_config_reports_all_errors() {
+    let mut raw = valid_raw_config();
+    raw.set(ENV_NUMBER_OF_THREADS, "0");
+    raw.set(ENV_ID, "UBAZRCVPOZTDKGCBNPGYFUPLZXDDNHSEGJRTAJKWJBHJDKHMAKVVFAKCZGRA");
+    raw.set(ENV_SERVER_PORT, "70000");
+    raw.set(ENV_VERSION, "1.142");
+    raw.set(ENV_MINING_SEED, "-1");
+
+    let Err(ConfigError::Invalid(errors)) = Config::from_raw(&raw) else {
+        panic!("the config must be invalid");
+    };
+    let names = errors.iter().map(|error| error.name).collect::<Vec<&str>>();
+
+    assert_eq!(vec![ENV_NUMBER_OF_THREADS, ENV_ID, ENV_SERVER_PORT, ENV_VERSION, ENV_MINING_SEED], names);
+}
+
+#[test]
+fn test_config_peers() {
+    let mut raw = valid_raw_config();
+    raw.set(ENV_SERVER_PORT, "1000");
+    raw.set(ENV_PEERS, "1.1.1.1:2000, 8.8.8.8, node.example, ::1, [::2]:3000");
+    raw.set(ENV_SUBMIT_MODE, "broadcast");
+    let config = Config::from_raw(&raw).unwrap();
+
+    assert_eq!(vec!["8.8.8.8:1000", "1.1.1.1:2000", "node.example:1000", "[::1]:1000", "[::2]:3000"], config.peers);
+    assert_eq!(SubmitMode::Broadcast, config.submit_mode);
+
+    let mut raw = RawConfig::default();
+    raw.set(ENV_ID, "UBAZRCVPOZTDKGCBNPGYFUPLZXDDNHSEGJRTAJKWJBHJDKHMAKVVFAKCZGRI");
+    raw.set(ENV_VERSION, "1.142.1");
+    raw.set(ENV_RANDOM_SEED, TEST_RANDOM_SEED);
+    raw.set(ENV_SOLUTION_THRESHOLD, "22");
+    let Err(ConfigError::Invalid(errors)) = Config::from_raw(&raw) else {
+        panic!("a peer is required");
+    };
+    assert_eq!(ENV_SERVER_IP, errors[0].name);
+
+    raw.set(ENV_PEERS, "1.1.1.1");
+    assert_eq!(vec![format!("1.1.1.1:{PORT}")], Config::from_raw(&raw).unwrap().peers);
+
+    assert!(parse_peers("1.1.1.1:0").is_err());
+    assert!(parse_peers("1.1.1.1:x").is_err());
+}
+
+#[test]
+fn test_scoring_config_without_network_settings() {
+    let mut raw = RawConfig::default();
+    raw.set(ENV_ID, "UBAZRCVPOZTDKGCBNPGYFUPLZXDDNHSEGJRTAJKWJBHJDKHMAKVVFAKCZGRI");
+    raw.set(ENV_RANDOM_SEED, TEST_RANDOM_SEED);
+    raw.set(ENV_SOLUTION_THRESHOLD, "22");
+
+    let config = ScoringConfig::from_raw(&raw).unwrap();
+
+    assert_eq!(Config::from_raw(&valid_raw_config()).unwrap().identities, config.identities);
+    assert!(Config::from_raw(&raw).is_err());
+}
+
+#[test]
+fn test_config_precedence() {
+    let file = RawConfig::from_toml_str(r#"
+        number_of_threads = 4
+        server_port = 21842
+        random_seed = [1, 2, 3]
+    "#).unwrap();
+    let mut cli = RawConfig::default();
+    cli.set(ENV_NUMBER_OF_THREADS, "2");
+
+    let config = Config::from_raw(&file.merge(valid_raw_config()).merge(cli)).unwrap();
+
+    assert_eq!(2, config.number_of_threads);
+    assert_eq!(21842, config.server_port);
+    assert_eq!([1, 0, 233, 9], config.random_seed[..4]);
+    assert!(RawConfig::from_toml_str("unknown = 1").is_err());
+}
+
+#[test]
+fn test_config_sources() {
+    let dir = env::temp_dir().join(format!("qiner-config-sources-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let config_file = dir.join("qiner.toml");
+    let dotenv_file = dir.join(".env");
+    fs::write(&config_file, "solution_threshold = 30\nnumber_of_threads = 4\n").unwrap();
+    fs::write(&dotenv_file, "SOLUTION_THRESHOLD=25\nRANDOM_SEED=1,2,3\nPATH=/nowhere\n").unwrap();
+
+    let mut process_env = RawConfig::default();
+    process_env.set(ENV_RANDOM_SEED, "4,5,6");
+    let mut cli = RawConfig::default();
+    cli.set(ENV_NUMBER_OF_THREADS, "2");
+    let sources = ConfigSources::new(Some(&config_file), Some(dotenv_file.clone()), process_env, cli);
+
+    let raw = sources.load().unwrap();
+    assert_eq!(Some("25"), raw.get(ENV_SOLUTION_THRESHOLD));
+    assert_eq!(Some("4,5,6"), raw.get(ENV_RANDOM_SEED));
+    assert_eq!(Some("2"), raw.get(ENV_NUMBER_OF_THREADS));
+    assert_eq!(None, raw.get("PATH"));
+    assert_eq!(vec![config_file.clone(), dotenv_file.clone()], sources.files());
+
+    // Read again, not cached
+    fs::write(&dotenv_file, "SOLUTION_THRESHOLD=27\n").unwrap();
+    assert_eq!(Some("27"), sources.load().unwrap().get(ENV_SOLUTION_THRESHOLD));
+
+    fs::remove_file(&config_file).unwrap();
+    assert!(matches!(sources.load(), Err(ConfigError::Io(..))));
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -5,3 +5,15 @@ pub const ENV_SERVER_PORT: &str = "SERVER_PORT";
 pub const ENV_VERSION: &str = "VERSION";
 pub const ENV_RANDOM_SEED: &str = "RANDOM_SEED";
 pub const ENV_SOLUTION_THRESHOLD: &str = "SOLUTION_THRESHOLD";
+pub const ENV_CONFIG: &str = "CONFIG";
+pub const ENV_NONCE_SOURCE: &str = "NONCE_SOURCE";
+pub const ENV_MINING_SEED: &str = "MINING_SEED";
+pub const ENV_HUGE_PAGES: &str = "HUGE_PAGES";
+pub const ENV_CPU_LIST: &str = "CPU_LIST";
+pub const ENV_JOURNAL_FILE: &str = "JOURNAL_FILE";
+pub const ENV_PEERS: &str = "PEERS";
+pub const ENV_SUBMIT_MODE: &str = "SUBMIT_MODE";
+pub const ENV_SIGNING_SEED: &str = "SIGNING_SEED";
+pub const ENV_RUST_LOG: &str = "RUST_LOG";
+pub const ENV_ID_SCHEDULE: &str = "ID_SCHEDULE";
+pub const ENV_METRICS_ADDR: &str = "METRICS_ADDR";
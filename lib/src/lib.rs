@@ -8,3 +8,7 @@ pub mod random_seed;
 pub mod env_names;
 #[cfg(feature = "solution_threshold")]
 pub mod solution_threshold;
+#[cfg(feature = "converters")]
+pub mod converters;
+#[cfg(feature = "config")]
+pub mod config;
@@ -1,21 +1,22 @@
-use std::env;
-use crate::env_names::ENV_RANDOM_SEED;
-use crate::types::{RANDOM_SEED_SPLIT_CHAR, Seed, SeedItem};
+use crate::types::{RANDOM_SEED_SPLIT_CHAR, Seed, SeedItem, SEED_ITEM_NUM};
+
+/// Parses a comma separated list of exactly 32 bytes, zeros included
+pub fn parse_random_seed(raw: &str) -> Result<Seed, String> {
+    let split = raw.split(RANDOM_SEED_SPLIT_CHAR).collect::<Vec<&str>>();
+    if split.len() != SEED_ITEM_NUM {
+        return Err(format!("expected {SEED_ITEM_NUM} items, got {}", split.len()));
+    }
 
-pub fn get_random_seed() -> Seed {
-    let random_seed_string = env::var(ENV_RANDOM_SEED).unwrap();
-    let split = random_seed_string.split(RANDOM_SEED_SPLIT_CHAR);
     let mut random_seed = Seed::default();
-    for (split_item, seed_item) in split.zip(random_seed.as_mut()) {
-        *seed_item = split_item.trim().parse::<SeedItem>().unwrap();
+    for (split_item, seed_item) in split.into_iter().zip(random_seed.iter_mut()) {
+        *seed_item = split_item.trim().parse::<SeedItem>().map_err(|_| format!("'{}' is not a number in 0..=255", split_item.trim()))?;
     }
-    
-    random_seed
+
+    Ok(random_seed)
 }
 
 #[test]
 fn test_randomseed() {
-    env::set_var(ENV_RANDOM_SEED, "  126, 27, 26, 27,    26, 27, 26, 27  ");
     let mut random_seed: Seed = Seed::default();
     random_seed[0] = 126;
     random_seed[1] = 27;
@@ -26,5 +27,22 @@ fn test_randomseed() {
     random_seed[6] = 26;
     random_seed[7] = 27;
 
-    assert_eq!(random_seed, get_random_seed());
-}
\ No newline at end of file
+    assert_eq!(Ok(random_seed), parse_random_seed(&format!("  126, 27, 26, 27,    26, 27, 26, 27  ,{}", ["0"; SEED_ITEM_NUM - 8].join(","))));
+    assert!(parse_random_seed(&format!("1,2,,3,{}", ["0"; SEED_ITEM_NUM - 4].join(","))).is_err());
+    assert_eq!(
+        Err(format!("expected {SEED_ITEM_NUM} items, got {}", SEED_ITEM_NUM + 1)),
+        parse_random_seed(&["1"; SEED_ITEM_NUM + 1].join(",")),
+    );
+}
+
+#[test]
+fn test_random_seed_length() {
+    // Nothing is padded, a short list is an error rather than a seed no node uses
+    assert_eq!(Err(format!("expected {SEED_ITEM_NUM} items, got 8")), parse_random_seed("126,27,26,27,26,27,26,27"));
+    assert!(parse_random_seed("").is_err());
+
+    let full = (1..=SEED_ITEM_NUM).map(|item| item.to_string()).collect::<Vec<String>>().join(",");
+    let random_seed = parse_random_seed(&full).unwrap();
+    assert_eq!(1, random_seed[0]);
+    assert_eq!(SEED_ITEM_NUM as SeedItem, random_seed[SEED_ITEM_NUM - 1]);
+}
@@ -1,13 +1,19 @@
-﻿use std::env;
-use crate::env_names::{ENV_SOLUTION_THRESHOLD};
+use crate::types::MAX_SCORE;
 
-pub fn get_solution_threshold() -> usize {
-    env::var(ENV_SOLUTION_THRESHOLD).unwrap().trim().parse::<usize>().unwrap()
+pub fn parse_solution_threshold(raw: &str) -> Result<usize, String> {
+    let threshold = raw.trim().parse::<usize>().map_err(|_| format!("'{}' is not a number in 0..={MAX_SCORE}", raw.trim()))?;
+    if threshold > MAX_SCORE {
+        return Err(format!("must not exceed the maximum score {MAX_SCORE}"));
+    }
+
+    Ok(threshold)
 }
 
 #[test]
-fn test_get_solution_threshold() {
-    env::set_var(ENV_SOLUTION_THRESHOLD, " 21 ");
-
-    assert_eq!(21, get_solution_threshold());
-}
\ No newline at end of file
+fn test_parse_solution_threshold() {
+    assert_eq!(Ok(21), parse_solution_threshold(" 21 "));
+    // Every nonce is a solution, which is what tests against a mock node want
+    assert_eq!(Ok(0), parse_solution_threshold("0"));
+    assert_eq!(Err(format!("'-1' is not a number in 0..={MAX_SCORE}")), parse_solution_threshold("-1"));
+    assert!(parse_solution_threshold(&(MAX_SCORE + 1).to_string()).is_err());
+}
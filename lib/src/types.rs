@@ -6,10 +6,11 @@ pub const STATE_SIZE: usize = 200;
 pub const STATE_SIZE_64: usize = 200 / size_of::<u64>();
 pub const NUMBER_OF_NEURONS: usize = 4194304;
 pub const NUMBER_OF_NEURONS_64: usize = NUMBER_OF_NEURONS * size_of::<NeuronLink>() / size_of::<u64>();
-pub const NEURON_MOD_BITS: u64 = (((NUMBER_OF_NEURONS - 1) << size_of::<NeuronLink>() * 8) | (NUMBER_OF_NEURONS - 1)) as u64;
+pub const NEURON_MOD_BITS: u64 = (((NUMBER_OF_NEURONS - 1) << NeuronLink::BITS) | (NUMBER_OF_NEURONS - 1)) as u64;
 pub const MINING_DATA_LENGTH: usize = 1024;
 pub const KECCAK_ROUND: usize = 12;
 pub const SEED_ITEM_NUM: usize = 32;
+pub const MAX_SCORE: usize = MINING_DATA_LENGTH * size_of::<MiningItemData>() * 8;
 
 pub(crate) const VERSION_SPLIT_CHAR: char = '.';
 pub(crate) const RANDOM_SEED_SPLIT_CHAR: char = ',';
@@ -65,11 +66,21 @@ pub mod network {
     pub const DEJAVU_ITEM_NUM: usize = 3;
     pub const KEY_ITEM_NUM: usize = 32;
     pub const KEY_ITEM_NUM_64: usize = KEY_ITEM_NUM / size_of::<u64>();
+    /// Size of the request/response header on the wire
+    pub const HEADER_SIZE: usize = 8;
+    /// Public peers sent in an exchange
+    pub const NUMBER_OF_EXCHANGED_PEERS: usize = 4;
 
     pub mod protocols {
         use crate::types::network::Type;
 
+        pub const EXCHANGE_PUBLIC_PEERS: Type = 0;
         pub const BROADCAST_MESSAGE: Type = 1;
+        pub const BROADCAST_COMPUTORS: Type = 2;
+        pub const BROADCAST_TICK: Type = 3;
+        pub const BROADCAST_FUTURE_TICK_DATA: Type = 8;
+        pub const BROADCAST_TRANSACTION: Type = 24;
+        pub const END_RESPONSE: Type = 35;
     }
 }
 
@@ -1,23 +1,25 @@
-﻿use std::env;
-use crate::env_names::ENV_VERSION;
 use crate::types::{VERSION_SPLIT_CHAR, Version};
 
-pub fn get_version() -> Version {
-    let found_version = env::var(ENV_VERSION).unwrap();
-    let split = found_version.split(VERSION_SPLIT_CHAR);
+pub fn parse_version(raw: &str) -> Result<Version, String> {
+    let split = raw.split(VERSION_SPLIT_CHAR).collect::<Vec<&str>>();
+    if split.len() != Version::default().len() {
+        return Err(format!("expected 3 dot-separated numbers, got {}", split.len()));
+    }
 
     let mut version: Version = Version::default();
-    split.into_iter().enumerate().for_each(|(idx, item)| {
-        version[idx] = item.trim().parse::<u8>().unwrap();
-    });
+    for (idx, item) in split.into_iter().enumerate() {
+        version[idx] = item.trim().parse::<u8>().map_err(|_| format!("'{}' is not a number in 0..=255", item.trim()))?;
+    }
 
-    version
+    Ok(version)
 }
 
 #[test]
-fn test_get_version() {
-    env::set_var(ENV_VERSION, "1. 141. 0");
+fn test_parse_version() {
     let version: Version = [1, 141, 0];
 
-    assert_eq!(version, get_version());
-}
\ No newline at end of file
+    assert_eq!(Ok(version), parse_version("1. 141. 0"));
+    assert!(parse_version("1.141").is_err());
+    assert!(parse_version("1.141.0.1").is_err());
+    assert!(parse_version("1.256.0").is_err());
+}
@@ -0,0 +1,422 @@
+//! A stand-in for a Qubic node: accepts the broadcast-message packets of the miner, decrypts and scores
+//! their nonces against its own random seed and threshold, records them and relays them to every connection
+//! like a node relays the messages it checked. It can fail on purpose,
+//! so the reconnecting and resending of the miner can be tested without a live node
+
+use std::io;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use lib::types::network::{Key, HEADER_SIZE, NUMBER_OF_EXCHANGED_PEERS};
+use lib::types::network::protocols::{BROADCAST_MESSAGE, EXCHANGE_PUBLIC_PEERS};
+use lib::types::{MiningData, Nonce64, PublicKey64, Seed};
+use qiner::converters::{get_hex_from_64, get_id_string};
+use qiner::network::frame::{Frame, FrameReader};
+use qiner::network::{decrypt_solution_nonce, get_gamming_key, Packet, RequestResponseHeader};
+use qiner::score::{get_mining_data, score_with_buffers, NeuronData};
+use qiner::signing::KeyPair;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpSocket, TcpStream};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, watch, Notify};
+use tokio::task::{JoinHandle, JoinSet};
+use tokio::time::Instant;
+
+/// Bytes read at once in [`FailureMode::SlowRead`]
+pub const SLOW_READ_CHUNK: usize = 16;
+
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Kernel receive buffer of the connections, small so a node in [`FailureMode::SlowRead`] pushes back on the sender soon
+const RECEIVE_BUFFER_SIZE: u32 = 4 * 1024;
+
+/// Relayed packets kept for a slow connection, older ones are dropped
+const RELAY_CAPACITY: usize = 64;
+
+/// Wait before binding the port again, after it was closed for [`FailureMode::Refuse`]
+const REBIND_DELAY: Duration = Duration::from_millis(100);
+
+/// How the node misbehaves. A change applies to the open connections too
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailureMode {
+	/// Accepts the connections and reads everything
+	None,
+	/// Closes the port, connections are refused
+	Refuse,
+	/// Resets every connection right after accepting it
+	Reset,
+	/// Reads [`SLOW_READ_CHUNK`] Bytes per delay, so the sender runs into its write timeout
+	SlowRead(Duration),
+}
+
+impl FailureMode {
+	/// `none`, `refuse`, `reset` or `slow-read:<milliseconds per chunk>`
+	pub fn parse(raw: &str) -> Result<FailureMode, String> {
+		match raw.trim().split_once(':') {
+			Some(("slow-read", delay)) => delay.trim().parse::<u64>()
+				.map(|delay| FailureMode::SlowRead(Duration::from_millis(delay)))
+				.map_err(|_| format!("'{}' is not a number of milliseconds", delay.trim())),
+			None if raw.trim() == "none" => Ok(FailureMode::None),
+			None if raw.trim() == "refuse" => Ok(FailureMode::Refuse),
+			None if raw.trim() == "reset" => Ok(FailureMode::Reset),
+			_ => Err(format!("'{}' is none of none, refuse, reset or slow-read:<ms>", raw.trim())),
+		}
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct NodeConfig {
+	pub random_seed: Seed,
+	pub solution_threshold: usize,
+	/// Key pair of the computor the packets are sent to, the nonces of signed packets are only readable with it
+	pub computor: Option<KeyPair>,
+	pub failure: FailureMode,
+}
+
+/// What the node made of a packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+	Solution,
+	BelowThreshold,
+	/// Signed for a computor the node has no key pair of
+	Encrypted,
+	/// The gamming key does not start with a zero Byte, a computor does not read the message as a solution
+	NotSolutionMessage,
+	InvalidSignature,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReceivedPacket {
+	pub peer: SocketAddr,
+	pub packet: Packet,
+	/// The decrypted solution nonce with its score
+	pub nonce: Option<(Nonce64, usize)>,
+	pub verdict: Verdict,
+}
+
+struct Shared {
+	solution_threshold: usize,
+	computor: Option<KeyPair>,
+	mining_data: Box<MiningData>,
+	neuron_data: Mutex<NeuronData>,
+	failure: watch::Sender<FailureMode>,
+	received: Mutex<Vec<ReceivedPacket>>,
+	packet_received: Notify,
+	connections: AtomicUsize,
+	/// Packets to write to every connection
+	relay: broadcast::Sender<Arc<[u8]>>,
+}
+
+/// The node runs until it is dropped
+pub struct MockNode {
+	local_addr: SocketAddr,
+	shared: Arc<Shared>,
+	task: JoinHandle<()>,
+}
+
+impl MockNode {
+	/// Binds `addr`, port 0 picks a free one, and serves in the background. Has to be called inside a runtime
+	pub async fn start(addr: SocketAddr, config: NodeConfig) -> io::Result<MockNode> {
+		let listener = bind(addr)?;
+		let local_addr = listener.local_addr()?;
+
+		let shared = Arc::new(Shared {
+			solution_threshold: config.solution_threshold,
+			computor: config.computor,
+			mining_data: Box::new(get_mining_data(&config.random_seed)),
+			neuron_data: Mutex::new(NeuronData::default()),
+			failure: watch::Sender::new(config.failure),
+			received: Mutex::new(Vec::new()),
+			packet_received: Notify::new(),
+			connections: AtomicUsize::new(0),
+			relay: broadcast::channel(RELAY_CAPACITY).0,
+		});
+		let task = tokio::spawn(serve(shared.clone(), listener, local_addr));
+
+		Ok(MockNode { local_addr, shared, task })
+	}
+
+	pub fn local_addr(&self) -> SocketAddr {
+		self.local_addr
+	}
+
+	pub fn set_failure(&self, failure: FailureMode) {
+		log::info!("Failure mode: {failure:?}");
+		self.shared.failure.send_replace(failure);
+	}
+
+	/// Accepted connections so far
+	pub fn connections(&self) -> usize {
+		self.shared.connections.load(Ordering::Relaxed)
+	}
+
+	pub fn received(&self) -> Vec<ReceivedPacket> {
+		self.shared.received.lock().unwrap().clone()
+	}
+
+	/// The received packets once there are `count` of them, or whatever came in until the timeout
+	pub async fn wait_for_packets(&self, count: usize, timeout: Duration) -> Vec<ReceivedPacket> {
+		let deadline = Instant::now() + timeout;
+		loop {
+			// Registered before looking, so a packet coming in between is not missed
+			let packet_received = self.shared.packet_received.notified();
+			tokio::pin!(packet_received);
+			packet_received.as_mut().enable();
+
+			let received = self.received();
+			if received.len() >= count {
+				return received;
+			}
+
+			if tokio::time::timeout_at(deadline, packet_received).await.is_err() {
+				return self.received();
+			}
+		}
+	}
+}
+
+impl Drop for MockNode {
+	fn drop(&mut self) {
+		self.task.abort();
+	}
+}
+
+/// Listens on `addr`, the accepted connections take over its receive buffer size
+fn bind(addr: SocketAddr) -> io::Result<TcpListener> {
+	let socket = if addr.is_ipv4() { TcpSocket::new_v4()? } else { TcpSocket::new_v6()? };
+	socket.set_reuseaddr(true)?;
+	socket.set_recv_buffer_size(RECEIVE_BUFFER_SIZE)?;
+	socket.bind(addr)?;
+
+	socket.listen(1024)
+}
+
+/// Accepts connections while the port is open, the connection tasks end with this one
+async fn serve(shared: Arc<Shared>, listener: TcpListener, local_addr: SocketAddr) {
+	let mut failure = shared.failure.subscribe();
+	let mut listener = Some(listener);
+	let mut connections = JoinSet::new();
+
+	loop {
+		if *failure.borrow_and_update() == FailureMode::Refuse {
+			listener = None;
+			if failure.changed().await.is_err() {
+				return;
+			}
+			continue;
+		}
+
+		let open_listener = match &listener {
+			Some(open_listener) => open_listener,
+			None => match bind(local_addr) {
+				Ok(open_listener) => listener.insert(open_listener),
+				Err(err) => {
+					log::warn!("Failed to bind {local_addr} again: {err}");
+					tokio::time::sleep(REBIND_DELAY).await;
+					continue;
+				}
+			},
+		};
+
+		tokio::select! {
+			changed = failure.changed() => if changed.is_err() {
+				return;
+			},
+			accepted = open_listener.accept() => match accepted {
+				Ok((stream, peer)) => {
+					shared.connections.fetch_add(1, Ordering::Relaxed);
+					connections.spawn(handle_connection(shared.clone(), stream, peer));
+				}
+				Err(err) => log::warn!("Failed to accept a connection: {err}"),
+			},
+			Some(_) = connections.join_next() => {}
+		}
+	}
+}
+
+/// Greets the peer like a node does and reads its frames until it leaves or the failure mode drops it
+async fn handle_connection(shared: Arc<Shared>, mut stream: TcpStream, peer: SocketAddr) {
+	let mut failure = shared.failure.subscribe();
+	let mut relayed_packets = shared.relay.subscribe();
+	log::info!("{peer} connected");
+
+	let mut greeting = Vec::with_capacity(HEADER_SIZE + NUMBER_OF_EXCHANGED_PEERS * 4);
+	RequestResponseHeader::new(&EXCHANGE_PUBLIC_PEERS, &(HEADER_SIZE + NUMBER_OF_EXCHANGED_PEERS * 4), &0).encode(&mut greeting);
+	greeting.resize(HEADER_SIZE + NUMBER_OF_EXCHANGED_PEERS * 4, 0);
+	if *failure.borrow() == FailureMode::None {
+		if let Err(err) = stream.write_all(&greeting).await {
+			log::warn!("{peer}: failed to send the public peers: {err}");
+			return;
+		}
+	}
+
+	let mut reader = FrameReader::default();
+	let mut buffer = vec![0u8; READ_BUFFER_SIZE];
+	loop {
+		let mode = *failure.borrow_and_update();
+		let read_size = match mode {
+			FailureMode::Refuse | FailureMode::Reset => {
+				log::info!("{peer}: resetting the connection");
+				let _ = stream.set_zero_linger();
+				return;
+			}
+			FailureMode::SlowRead(_) => SLOW_READ_CHUNK,
+			FailureMode::None => READ_BUFFER_SIZE,
+		};
+
+		tokio::select! {
+			changed = failure.changed() => if changed.is_err() {
+				return;
+			},
+			relayed = relayed_packets.recv() => match relayed {
+				Ok(packet) => if let Err(err) = stream.write_all(&packet).await {
+					log::info!("{peer} disconnected: {err}");
+					return;
+				},
+				Err(RecvError::Lagged(skipped)) => log::warn!("{peer}: skipped relaying {skipped} packets"),
+				Err(RecvError::Closed) => return,
+			},
+			read = stream.read(&mut buffer[..read_size]) => match read {
+				Ok(0) => {
+					log::info!("{peer} disconnected");
+					return;
+				}
+				Ok(size) => reader.extend(&buffer[..size]),
+				Err(err) => {
+					log::info!("{peer} disconnected: {err}");
+					return;
+				}
+			},
+		}
+
+		loop {
+			match reader.next_frame() {
+				Ok(Some(frame)) => receive(&shared, peer, frame).await,
+				Ok(None) => break,
+				Err(err) => {
+					log::warn!("{peer}: {err}, dropping the connection");
+					return;
+				}
+			}
+		}
+
+		if let FailureMode::SlowRead(delay) = mode {
+			tokio::time::sleep(delay).await;
+		}
+	}
+}
+
+async fn receive(shared: &Arc<Shared>, peer: SocketAddr, frame: Frame) {
+	if frame.header.get_type() != BROADCAST_MESSAGE || frame.header.get_size() != Packet::SIZE {
+		log::debug!("{peer}: skipping a message of type {} and {} Bytes", frame.header.get_type(), frame.header.get_size());
+		return;
+	}
+
+	let mut bytes = Vec::with_capacity(Packet::SIZE);
+	frame.header.encode(&mut bytes);
+	bytes.extend_from_slice(&frame.payload);
+	let packet = Packet::decode(&bytes).expect("the frame has the size of a packet");
+	let should_relay = frame.header.is_dejavu_zero();
+
+	// Scoring takes a while, it must not hold up the runtime
+	let scoring_shared = shared.clone();
+	let received = tokio::task::spawn_blocking(move || check_packet(&scoring_shared, peer, packet)).await.unwrap();
+
+	match received.nonce {
+		Some((nonce, score)) => log::info!(
+			"{peer}: {:?} for {}, nonce {} score {score}",
+			received.verdict,
+			get_id_string(&received.packet.get_message().get_destination_public_key()),
+			get_hex_from_64(&nonce),
+		),
+		None => log::info!("{peer}: {:?}", received.verdict),
+	}
+
+	// A node relays a message with a zero dejavu once the signature checks out, with a dejavu of its own
+	if should_relay && received.verdict != Verdict::InvalidSignature {
+		bytes[4..7].copy_from_slice(&[1, 2, 3]);
+		let _ = shared.relay.send(Arc::from(bytes));
+	}
+
+	shared.received.lock().unwrap().push(received);
+	shared.packet_received.notify_waiters();
+}
+
+/// Validates the packet as a computor does: the signature, the shared key and the gamming key, then scores the nonce
+fn check_packet(shared: &Shared, peer: SocketAddr, packet: Packet) -> ReceivedPacket {
+	let message = packet.get_message();
+	let source_public_key = message.get_source_public_key();
+	let destination_public_key = message.get_destination_public_key();
+	let is_anonymous = source_public_key == PublicKey64::default();
+
+	let mut received = ReceivedPacket { peer, packet, nonce: None, verdict: Verdict::Encrypted };
+	if !is_anonymous && !received.packet.is_signature_valid() {
+		received.verdict = Verdict::InvalidSignature;
+		return received;
+	}
+
+	let shared_key = match &shared.computor {
+		_ if is_anonymous => Some(Key::default()),
+		Some(computor) if computor.public_key() == destination_public_key => computor.get_shared_key(&source_public_key),
+		_ => None,
+	};
+	let Some(shared_key) = shared_key else {
+		return received;
+	};
+
+	let gamming_key = get_gamming_key(&shared_key, &message.get_gamming_nonce());
+	if gamming_key[0] != 0 {
+		received.verdict = Verdict::NotSolutionMessage;
+		return received;
+	}
+
+	let nonce = decrypt_solution_nonce(&gamming_key, &received.packet.get_solution_nonce());
+	let score = score_with_buffers(&destination_public_key, &nonce, &shared.mining_data, &mut shared.neuron_data.lock().unwrap());
+	received.nonce = Some((nonce, score));
+	received.verdict = if score >= shared.solution_threshold { Verdict::Solution } else { Verdict::BelowThreshold };
+
+	received
+}
+
+#[test]
+fn test_parse_failure_mode() {
+	assert_eq!(Ok(FailureMode::None), FailureMode::parse("none"));
+	assert_eq!(Ok(FailureMode::Refuse), FailureMode::parse(" refuse "));
+	assert_eq!(Ok(FailureMode::Reset), FailureMode::parse("reset"));
+	assert_eq!(Ok(FailureMode::SlowRead(Duration::from_millis(250))), FailureMode::parse("slow-read:250"));
+	assert!(FailureMode::parse("slow-read:fast").is_err());
+	assert!(FailureMode::parse("crash").is_err());
+}
+
+#[tokio::test]
+async fn test_mock_node_checks_packets() {
+	use qiner::entropy::ChaChaSource;
+
+	let computor = KeyPair::from_seed("aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa").unwrap();
+	let signer = KeyPair::from_seed("bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb").unwrap();
+	let config = NodeConfig { random_seed: [1; 32], solution_threshold: 0, computor: Some(computor.clone()), failure: FailureMode::None };
+	let node = MockNode::start("127.0.0.1:0".parse().unwrap(), config).await.unwrap();
+
+	let mut entropy = ChaChaSource::from_seed([3; 32]);
+	let mut data = Vec::new();
+	Packet::new(&BROADCAST_MESSAGE, &142, &computor.public_key(), &[1, 2, 3, 4], None, &mut entropy).unwrap().encode(&mut data);
+	Packet::new(&BROADCAST_MESSAGE, &142, &computor.public_key(), &[5, 6, 7, 8], Some(&signer), &mut entropy).unwrap().encode(&mut data);
+	Packet::new(&BROADCAST_MESSAGE, &142, &signer.public_key(), &[5, 6, 7, 8], Some(&computor), &mut entropy).unwrap().encode(&mut data);
+	let mut tampered = Packet::new(&BROADCAST_MESSAGE, &142, &computor.public_key(), &[5, 6, 7, 8], Some(&signer), &mut entropy).unwrap().to_bytes();
+	tampered[150] ^= 1;
+	data.extend(tampered);
+
+	let mut stream = TcpStream::connect(node.local_addr()).await.unwrap();
+	let mut greeting = [0u8; HEADER_SIZE + NUMBER_OF_EXCHANGED_PEERS * 4];
+	stream.read_exact(&mut greeting).await.unwrap();
+	assert_eq!(EXCHANGE_PUBLIC_PEERS, RequestResponseHeader::decode(&greeting).unwrap().get_type());
+	stream.write_all(&data).await.unwrap();
+
+	let received = node.wait_for_packets(4, Duration::from_secs(30)).await;
+	let verdicts = received.iter().map(|received| received.verdict).collect::<Vec<_>>();
+	assert_eq!(vec![Verdict::Solution, Verdict::Solution, Verdict::Encrypted, Verdict::InvalidSignature], verdicts);
+	assert_eq!([1, 2, 3, 4], received[0].nonce.unwrap().0);
+	assert_eq!([5, 6, 7, 8], received[1].nonce.unwrap().0);
+	assert_eq!(1, node.connections());
+}
@@ -0,0 +1,75 @@
+use std::net::SocketAddr;
+use std::process::ExitCode;
+use clap::Parser;
+use lib::random_seed::parse_random_seed;
+use lib::solution_threshold::parse_solution_threshold;
+use lib::types::{Seed, PORT};
+use mock_node::{FailureMode, MockNode, NodeConfig, Verdict};
+use qiner::signing::KeyPair;
+
+/// Seed of the computor the miner mines for, to read the nonces of signed packets.
+/// An environment variable only, like the signing seed of the miner
+const ENV_COMPUTOR_SEED: &str = "COMPUTOR_SEED";
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Mock Qubic node, records and scores the solutions sent to it")]
+struct Cli {
+    #[arg(long, default_value_t = SocketAddr::from(([0, 0, 0, 0], PORT)))]
+    listen: SocketAddr,
+
+    /// Comma separated list of 32 bytes
+    #[arg(long, value_parser = parse_random_seed)]
+    random_seed: Seed,
+
+    #[arg(long, value_parser = parse_solution_threshold)]
+    solution_threshold: usize,
+
+    /// none, refuse, reset or slow-read:<milliseconds per chunk>
+    #[arg(long, default_value = "none", value_parser = FailureMode::parse)]
+    failure: FailureMode,
+}
+
+fn main() -> ExitCode {
+    dotenv::dotenv().ok();
+
+    pretty_env_logger::init_timed();
+
+    let cli = Cli::parse();
+    let computor = match std::env::var(ENV_COMPUTOR_SEED).ok().map(|seed| KeyPair::from_seed(seed.trim())) {
+        Some(Ok(computor)) => Some(computor),
+        Some(Err(err)) => {
+            log::error!("{ENV_COMPUTOR_SEED}: {err}");
+            return ExitCode::FAILURE;
+        }
+        None => None,
+    };
+
+    let config = NodeConfig {
+        random_seed: cli.random_seed,
+        solution_threshold: cli.solution_threshold,
+        computor,
+        failure: cli.failure,
+    };
+
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build().unwrap()
+        .block_on(async move {
+            let node = match MockNode::start(cli.listen, config).await {
+                Ok(node) => node,
+                Err(err) => {
+                    log::error!("Failed to listen on {}: {err}", cli.listen);
+                    return ExitCode::FAILURE;
+                }
+            };
+            log::info!("Listening on {}, failure mode {:?}", node.local_addr(), cli.failure);
+
+            let _ = tokio::signal::ctrl_c().await;
+
+            let received = node.received();
+            let solutions = received.iter().filter(|received| received.verdict == Verdict::Solution).count();
+            log::info!("Received {} packets from {} connections, {solutions} solutions", received.len(), node.connections());
+
+            ExitCode::SUCCESS
+        })
+}
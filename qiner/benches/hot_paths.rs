@@ -0,0 +1,86 @@
+use std::hint::black_box;
+use std::mem::size_of;
+use std::time::Duration;
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use lib::types::network::protocols::BROADCAST_MESSAGE;
+use lib::types::{Id, Nonce64, MAX_SCORE, MINING_DATA_LENGTH, NUMBER_OF_NEURONS_64};
+use qiner::bench::{get_bench_public_key, BENCH_RANDOM_SEED};
+use qiner::converters::get_id_from_public_key_64;
+use qiner::entropy::ChaChaSource;
+use qiner::math::random_64;
+use qiner::miner::Miner;
+use qiner::network::Packet;
+use qiner::score::{get_mining_data, score_with_buffers, NeuronData};
+use qiner::signing::KeyPair;
+
+// Every input is fixed, so runs of different builds compare the same work
+const NONCE: Nonce64 = [0x0123456789abcdef, 0xfedcba9876543210, 1, 2];
+const SIGNING_SEED: &str = "abcdefghijklmnopqrstuvwxyzabcdefghijklmnopqrstuvwxyzabc";
+const PROTOCOL: u8 = 142;
+
+fn bench_random_64(c: &mut Criterion) {
+	let public_key = get_bench_public_key();
+	let mut group = c.benchmark_group("random_64");
+
+	let mut mining_data = vec![0u64; MINING_DATA_LENGTH];
+	group.throughput(Throughput::Bytes((mining_data.len() * size_of::<u64>()) as u64));
+	group.bench_function("mining_data", |b| b.iter(|| random_64(black_box(&public_key), black_box(&NONCE), &mut mining_data)));
+
+	// The neuron links of one nonce, about 32 MB
+	let mut neuron_links = vec![0u64; NUMBER_OF_NEURONS_64 * 2];
+	group.throughput(Throughput::Bytes((neuron_links.len() * size_of::<u64>()) as u64));
+	group.sample_size(10);
+	group.bench_function("neuron_links", |b| b.iter(|| random_64(black_box(&public_key), black_box(&NONCE), &mut neuron_links)));
+
+	group.finish();
+}
+
+fn bench_score(c: &mut Criterion) {
+	let public_key = get_bench_public_key();
+	let mining_data = get_mining_data(&BENCH_RANDOM_SEED);
+	let mut neuron_data = NeuronData::default();
+	let mut group = c.benchmark_group("score");
+	group.sample_size(10);
+	group.measurement_time(Duration::from_secs(20));
+
+	group.bench_function("score_with_buffers", |b| b.iter(|| {
+		score_with_buffers(black_box(&public_key), black_box(&NONCE), &mining_data, &mut neuron_data)
+	}));
+
+	// The threshold can not be reached, so every iteration runs the whole neuron loop
+	let miner = Miner::new(public_key, &BENCH_RANDOM_SEED, MAX_SCORE);
+	let mut nonce = Nonce64::default();
+	group.bench_function("find_solution", |b| b.iter(|| {
+		// The same nonce every iteration
+		let mut nonce_source = ChaChaSource::for_worker(0, 0);
+		miner.find_solution(0, &mut nonce, &mut neuron_data, &mut nonce_source).unwrap()
+	}));
+
+	group.finish();
+}
+
+fn bench_get_id_from_public_key_64(c: &mut Criterion) {
+	let public_key = get_bench_public_key();
+	let mut id: Id = [0; 60];
+
+	c.bench_function("get_id_from_public_key_64", |b| b.iter(|| get_id_from_public_key_64(black_box(&public_key), &mut id)));
+}
+
+fn bench_packet_new(c: &mut Criterion) {
+	let computor_public_key = get_bench_public_key();
+	let signer = KeyPair::from_seed(SIGNING_SEED).unwrap();
+	let mut group = c.benchmark_group("packet_new");
+
+	// A new source every iteration gives the same gamming nonce search every time
+	group.bench_function("anonymous", |b| b.iter(|| {
+		Packet::new(&BROADCAST_MESSAGE, &PROTOCOL, black_box(&computor_public_key), black_box(&NONCE), None, &mut ChaChaSource::from_seed([3; 32])).unwrap()
+	}));
+	group.bench_function("signed", |b| b.iter(|| {
+		Packet::new(&BROADCAST_MESSAGE, &PROTOCOL, black_box(&computor_public_key), black_box(&NONCE), Some(&signer), &mut ChaChaSource::from_seed([3; 32])).unwrap()
+	}));
+
+	group.finish();
+}
+
+criterion_group!(benches, bench_random_64, bench_score, bench_get_id_from_public_key_64, bench_packet_new);
+criterion_main!(benches);
@@ -0,0 +1,40 @@
+use core_affinity::CoreId;
+
+/// CPU of a worker, the list is reused round-robin when there are more workers than CPUs
+pub fn get_worker_cpu(cpu_list: &[usize], worker_idx: usize) -> Option<usize> {
+	if cpu_list.is_empty() {
+		return None;
+	}
+
+	Some(cpu_list[worker_idx % cpu_list.len()])
+}
+
+/// Pins the current thread to `cpu`, false when the OS refused
+pub fn pin_current_thread(cpu: usize) -> bool {
+	core_affinity::set_for_current(CoreId { id: cpu })
+}
+
+pub fn get_available_cpus() -> Vec<usize> {
+	core_affinity::get_core_ids().unwrap_or_default().into_iter().map(|core_id| core_id.id).collect()
+}
+
+/// NUMA node of a CPU as reported by sysfs
+#[cfg(target_os = "linux")]
+pub fn get_numa_node(cpu: usize) -> Option<usize> {
+	std::fs::read_dir(format!("/sys/devices/system/cpu/cpu{cpu}")).ok()?
+		.filter_map(Result::ok)
+		.find_map(|entry| entry.file_name().to_str()?.strip_prefix("node")?.parse::<usize>().ok())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn get_numa_node(_cpu: usize) -> Option<usize> {
+	None
+}
+
+#[test]
+fn test_get_worker_cpu() {
+	assert_eq!(None, get_worker_cpu(&[], 0));
+	assert_eq!(Some(4), get_worker_cpu(&[4, 6], 0));
+	assert_eq!(Some(6), get_worker_cpu(&[4, 6], 1));
+	assert_eq!(Some(4), get_worker_cpu(&[4, 6], 2));
+}
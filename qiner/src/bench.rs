@@ -0,0 +1,228 @@
+use std::fmt::Write as _;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use lib::types::{Id, PublicKey64, Seed};
+use crate::converters::get_public_key_64_from_id;
+use crate::entropy::ChaChaSource;
+use crate::miner::Miner;
+use crate::worker_state::WorkerPool;
+
+/// ID every benchmark mines for, the tests pin their scores with it too
+pub const BENCH_ID: &str = "UBAZRCVPOZTDKGCBNPGYFUPLZXDDNHSEGJRTAJKWJBHJDKHMAKVVFAKCZGRI";
+pub const BENCH_RANDOM_SEED: Seed = [
+	1, 0, 233, 9, 136, 69, 43, 139, 0, 0, 0, 0, 0, 0, 0, 0,
+	0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+];
+/// Seed of the nonce streams, every benchmark scores the same nonces
+pub const BENCH_MINING_SEED: u64 = 0;
+
+/// Public key of [`BENCH_ID`]
+pub fn get_bench_public_key() -> PublicKey64 {
+	let id: Id = BENCH_ID.as_bytes().try_into().unwrap();
+	let mut public_key = PublicKey64::default();
+	get_public_key_64_from_id(&id, &mut public_key);
+
+	public_key
+}
+
+/// When every thread of a run stops
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BenchLimit {
+	Duration(Duration),
+	/// Iterations of each thread
+	Iterations(usize),
+}
+
+impl BenchLimit {
+	fn is_reached(&self, started: Instant, iterations: usize) -> bool {
+		match self {
+			BenchLimit::Duration(duration) => started.elapsed() >= *duration,
+			BenchLimit::Iterations(limit) => iterations >= *limit,
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThreadResult {
+	pub iterations: usize,
+	pub elapsed: Duration,
+}
+
+impl ThreadResult {
+	pub fn iterations_per_second(&self) -> f64 {
+		get_rate(self.iterations, self.elapsed)
+	}
+}
+
+/// One run with a given number of threads
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchRun {
+	pub threads: Vec<ThreadResult>,
+	/// From the start of the first thread to the end of the last one
+	pub elapsed: Duration,
+}
+
+impl BenchRun {
+	pub fn iterations(&self) -> usize {
+		self.threads.iter().map(|thread| thread.iterations).sum()
+	}
+
+	/// Sum of the rates of the threads
+	pub fn iterations_per_second(&self) -> f64 {
+		self.threads.iter().map(ThreadResult::iterations_per_second).sum()
+	}
+
+	pub fn iterations_per_second_per_thread(&self) -> f64 {
+		self.iterations_per_second() / self.threads.len().max(1) as f64
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchReport {
+	pub limit: BenchLimit,
+	pub runs: Vec<BenchRun>,
+}
+
+impl BenchReport {
+	/// Rate of `run` against the single thread run times its threads, `None` without a single thread run
+	pub fn scaling_efficiency(&self, run: &BenchRun) -> Option<f64> {
+		let single_thread = self.runs.iter().find(|run| run.threads.len() == 1)?.iterations_per_second();
+		if single_thread <= 0.0 {
+			return None;
+		}
+
+		Some(run.iterations_per_second() / (single_thread * run.threads.len() as f64))
+	}
+
+	/// The report with the build and the machine it ran on
+	pub fn to_json(&self) -> String {
+		let mut json = String::new();
+		let _ = writeln!(json, "{{");
+		let _ = writeln!(json, "  \"version\": \"{}\",", env!("CARGO_PKG_VERSION"));
+		let _ = writeln!(json, "  \"profile\": \"{}\",", if cfg!(debug_assertions) { "debug" } else { "release" });
+		let _ = writeln!(json, "  \"arch\": \"{}\",", std::env::consts::ARCH);
+		let _ = writeln!(json, "  \"os\": \"{}\",", std::env::consts::OS);
+		let _ = writeln!(json, "  \"available_cpus\": {},", num_cpus::get());
+		match self.limit {
+			BenchLimit::Duration(duration) => { let _ = writeln!(json, "  \"limit\": {{ \"seconds\": {} }},", duration.as_secs_f64()); }
+			BenchLimit::Iterations(iterations) => { let _ = writeln!(json, "  \"limit\": {{ \"iterations\": {iterations} }},"); }
+		}
+
+		let runs = self.runs.iter().map(|run| {
+			let per_thread = run.threads.iter().map(|thread| format!("{:.3}", thread.iterations_per_second())).collect::<Vec<String>>();
+			let efficiency = self.scaling_efficiency(run).map_or("null".to_string(), |efficiency| format!("{efficiency:.4}"));
+			format!(
+				"    {{ \"threads\": {}, \"iterations\": {}, \"seconds\": {:.3}, \"iterations_per_second\": {:.3}, \"iterations_per_second_per_thread\": {:.3}, \"scaling_efficiency\": {efficiency}, \"per_thread\": [{}] }}",
+				run.threads.len(),
+				run.iterations(),
+				run.elapsed.as_secs_f64(),
+				run.iterations_per_second(),
+				run.iterations_per_second_per_thread(),
+				per_thread.join(", "),
+			)
+		}).collect::<Vec<String>>();
+		let _ = writeln!(json, "  \"runs\": [\n{}\n  ]", runs.join(",\n"));
+		let _ = writeln!(json, "}}");
+
+		json
+	}
+}
+
+fn get_rate(iterations: usize, elapsed: Duration) -> f64 {
+	let seconds = elapsed.as_secs_f64();
+	if seconds > 0.0 { iterations as f64 / seconds } else { 0.0 }
+}
+
+/// `max_threads` alone, or the powers of two up to it and `max_threads` itself for a scaling run
+pub fn get_thread_counts(max_threads: usize, scaling: bool) -> Vec<usize> {
+	let max_threads = max_threads.max(1);
+	if !scaling {
+		return vec![max_threads];
+	}
+
+	let mut thread_counts = (0..).map(|exponent| 1 << exponent).take_while(|threads| *threads < max_threads).collect::<Vec<usize>>();
+	thread_counts.push(max_threads);
+
+	thread_counts
+}
+
+/// Runs `find_solution` on `threads` threads until `limit`, thread `i` takes the nonces of the stream `i`
+pub fn run(miner: &Arc<Miner>, worker_pool: &Arc<WorkerPool>, threads: usize, limit: BenchLimit) -> BenchRun {
+	let started = Instant::now();
+
+	let handles = (0..threads).map(|idx| {
+		let miner = miner.clone();
+		let worker_pool = worker_pool.clone();
+		thread::spawn(move || {
+			let mut state = worker_pool.acquire();
+			let mut nonce_source = ChaChaSource::for_worker(BENCH_MINING_SEED, idx);
+			let thread_started = Instant::now();
+			let mut iterations = 0usize;
+			while !limit.is_reached(started, iterations) {
+				miner.find_solution(0, &mut state.nonce, &mut state.neuron_data, &mut nonce_source).unwrap();
+				iterations += 1;
+			}
+			let elapsed = thread_started.elapsed();
+			worker_pool.release(state);
+
+			ThreadResult { iterations, elapsed }
+		})
+	}).collect::<Vec<_>>();
+
+	let threads = handles.into_iter().map(|handle| handle.join().unwrap()).collect();
+
+	BenchRun { threads, elapsed: started.elapsed() }
+}
+
+#[cfg(test)]
+fn new_test_run(rates: &[usize]) -> BenchRun {
+	let threads = rates.iter().map(|rate| ThreadResult { iterations: rate * 2, elapsed: Duration::from_secs(2) }).collect();
+
+	BenchRun { threads, elapsed: Duration::from_secs(2) }
+}
+
+#[test]
+fn test_get_thread_counts() {
+	assert_eq!(vec![6], get_thread_counts(6, false));
+	assert_eq!(vec![1, 2, 4, 6], get_thread_counts(6, true));
+	assert_eq!(vec![1, 2, 4, 8], get_thread_counts(8, true));
+	assert_eq!(vec![1], get_thread_counts(0, true));
+}
+
+#[test]
+fn test_bench_report() {
+	let report = BenchReport {
+		limit: BenchLimit::Duration(Duration::from_secs(2)),
+		runs: vec![new_test_run(&[10]), new_test_run(&[9, 9, 9, 9])],
+	};
+
+	assert_eq!(72, report.runs[1].iterations());
+	assert_eq!(36.0, report.runs[1].iterations_per_second());
+	assert_eq!(9.0, report.runs[1].iterations_per_second_per_thread());
+	assert_eq!(Some(1.0), report.scaling_efficiency(&report.runs[0]));
+	assert_eq!(Some(0.9), report.scaling_efficiency(&report.runs[1]));
+
+	let json = report.to_json();
+	assert!(json.starts_with("{\n  \"version\": "));
+	assert!(json.contains("  \"limit\": { \"seconds\": 2 },\n"));
+	assert!(json.contains("{ \"threads\": 1, \"iterations\": 20, \"seconds\": 2.000, \"iterations_per_second\": 10.000, \"iterations_per_second_per_thread\": 10.000, \"scaling_efficiency\": 1.0000, \"per_thread\": [10.000] },\n"));
+	assert!(json.contains("\"scaling_efficiency\": 0.9000, \"per_thread\": [9.000, 9.000, 9.000, 9.000] }\n  ]\n}\n"));
+
+	let without_single_thread = BenchReport { limit: BenchLimit::Iterations(5), runs: vec![new_test_run(&[1, 1])] };
+	assert!(without_single_thread.to_json().contains("\"scaling_efficiency\": null"));
+	assert!(without_single_thread.to_json().contains("\"limit\": { \"iterations\": 5 },"));
+}
+
+#[test]
+fn test_bench_run() {
+	let miner = Arc::new(Miner::new([7; 4], &[0; 32], lib::types::MAX_SCORE));
+	let worker_pool = Arc::new(WorkerPool::new(false));
+
+	let run = run(&miner, &worker_pool, 2, BenchLimit::Iterations(1));
+
+	assert_eq!(2, run.threads.len());
+	assert_eq!(2, run.iterations());
+	assert_eq!(2, miner.get_score_histogram().distribution().unwrap().count());
+	assert!(run.threads.iter().all(|thread| thread.elapsed <= run.elapsed));
+}
@@ -0,0 +1,184 @@
+use std::path::PathBuf;
+use clap::{Args, Parser, Subcommand};
+use lib::config::RawConfig;
+use lib::env_names::{
+	ENV_CPU_LIST,
+	ENV_HUGE_PAGES,
+	ENV_ID,
+	ENV_ID_SCHEDULE,
+	ENV_JOURNAL_FILE,
+	ENV_METRICS_ADDR,
+	ENV_MINING_SEED,
+	ENV_NONCE_SOURCE,
+	ENV_NUMBER_OF_THREADS,
+	ENV_PEERS,
+	ENV_RANDOM_SEED,
+	ENV_SERVER_IP,
+	ENV_SERVER_PORT,
+	ENV_SOLUTION_THRESHOLD,
+	ENV_SUBMIT_MODE,
+	ENV_VERSION,
+};
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Qubic miner")]
+pub struct Cli {
+	#[command(subcommand)]
+	pub command: Option<Command>,
+
+	#[command(flatten)]
+	pub config: ConfigArgs,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+	/// Mine and send found solutions to the peers (default)
+	Mine,
+	/// Measure the mining speed on a fixed ID and random seed.
+	/// The number of threads is given with --threads [default: number of available cores]
+	Bench {
+		/// Duration of each run
+		#[arg(long, default_value_t = 10)]
+		seconds: u64,
+
+		/// Stop each thread after this many iterations instead of after --seconds
+		#[arg(long, conflicts_with = "seconds", value_parser = clap::value_parser!(u64).range(1..))]
+		iterations: Option<u64>,
+
+		/// Run with 1, 2, 4... threads up to --threads and report the scaling efficiency
+		#[arg(long)]
+		scaling: bool,
+
+		/// Print the results as JSON
+		#[arg(long)]
+		json: bool,
+	},
+	/// Compute the scores of nonces and check them against the threshold.
+	/// Needs only ID, RANDOM_SEED and SOLUTION_THRESHOLD
+	#[command(alias = "verify-nonce")]
+	Verify {
+		/// 32 bytes nonces in hex
+		#[arg(required = true)]
+		nonces: Vec<String>,
+	},
+	/// Print the public key of an ID in hex
+	IdToPubkey {
+		id: String,
+	},
+	/// Print the ID of a public key given in hex
+	PubkeyToId {
+		public_key: String,
+	},
+	/// Validate and print the configuration
+	CheckConfig,
+	/// Send one broadcast-message packet with a zero solution nonce to every peer
+	SendTestPacket,
+	/// Print the broadcast-message packets of a pcap file or hex dump and score their solution nonces.
+	/// Needs the settings of verify, and SIGNING_SEED for the packets it signed
+	Decode {
+		/// pcap file or hex dump, - for stdin
+		input: PathBuf,
+	},
+}
+
+/// Settings given on the command line override the environment, `.env` and the config file
+#[derive(Debug, Args)]
+pub struct ConfigArgs {
+	/// TOML config file [default: qiner.toml if it exists]
+	#[arg(long, global = true)]
+	pub config: Option<PathBuf>,
+
+	/// Number of mining threads
+	#[arg(long, global = true)]
+	pub threads: Option<String>,
+
+	/// 60 characters long IDs to mine for, each with an optional weight, e.g. ID1:3,ID2
+	#[arg(long, global = true)]
+	pub id: Option<String>,
+
+	/// How the threads share their time between the IDs: share or round-robin
+	#[arg(long, global = true)]
+	pub id_schedule: Option<String>,
+
+	#[arg(long, global = true)]
+	pub server_ip: Option<String>,
+
+	/// Port of the peers given without one
+	#[arg(long, global = true)]
+	pub server_port: Option<String>,
+
+	/// More nodes to send solutions to, e.g. 1.2.3.4,5.6.7.8:21841
+	#[arg(long, global = true)]
+	pub peers: Option<String>,
+
+	/// How solutions are spread over the peers: round-robin or broadcast
+	#[arg(long, global = true)]
+	pub submit_mode: Option<String>,
+
+	/// Qubic version, e.g. 1.142.1
+	#[arg(long, global = true)]
+	pub qubic_version: Option<String>,
+
+	/// Comma separated list of up to 32 bytes
+	#[arg(long, global = true)]
+	pub random_seed: Option<String>,
+
+	#[arg(long, global = true)]
+	pub solution_threshold: Option<String>,
+
+	/// Where random nonces come from: auto, rdrand, os or chacha
+	#[arg(long, global = true)]
+	pub nonce_source: Option<String>,
+
+	/// Master seed of the deterministic mode, overrides the nonce source
+	#[arg(long, global = true)]
+	pub mining_seed: Option<String>,
+
+	/// Back the neuron buffers with transparent huge pages: true or false
+	#[arg(long, global = true)]
+	pub huge_pages: Option<String>,
+
+	/// CPUs to pin the mining threads to, e.g. 0-3,8
+	#[arg(long, global = true)]
+	pub cpu_list: Option<String>,
+
+	/// Journal of the found solutions [default: solutions.journal]
+	#[arg(long, global = true)]
+	pub journal_file: Option<String>,
+
+	/// Serve Prometheus metrics on http://ADDR/metrics, e.g. 127.0.0.1:9100
+	#[arg(long, global = true)]
+	pub metrics_addr: Option<String>,
+}
+
+impl ConfigArgs {
+	pub fn to_raw_config(&self) -> RawConfig {
+		let mut raw = RawConfig::default();
+		let settings = [
+			(ENV_NUMBER_OF_THREADS, &self.threads),
+			(ENV_ID, &self.id),
+			(ENV_ID_SCHEDULE, &self.id_schedule),
+			(ENV_SERVER_IP, &self.server_ip),
+			(ENV_SERVER_PORT, &self.server_port),
+			(ENV_PEERS, &self.peers),
+			(ENV_SUBMIT_MODE, &self.submit_mode),
+			(ENV_VERSION, &self.qubic_version),
+			(ENV_RANDOM_SEED, &self.random_seed),
+			(ENV_SOLUTION_THRESHOLD, &self.solution_threshold),
+			(ENV_NONCE_SOURCE, &self.nonce_source),
+			(ENV_MINING_SEED, &self.mining_seed),
+			(ENV_HUGE_PAGES, &self.huge_pages),
+			(ENV_CPU_LIST, &self.cpu_list),
+			(ENV_JOURNAL_FILE, &self.journal_file),
+			(ENV_METRICS_ADDR, &self.metrics_addr),
+		];
+
+		for (name, value) in settings {
+			if let Some(value) = value {
+				raw.set(name, value.as_str());
+			}
+		}
+
+		raw
+	}
+}
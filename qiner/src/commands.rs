@@ -0,0 +1,312 @@
+use std::io::Read;
+use std::path::Path;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::Duration;
+use lib::config::{Config, ScoringConfig, SigningSeed};
+use lib::types::network::protocols::BROADCAST_MESSAGE;
+use lib::types::network::Key;
+use lib::types::{Id, Nonce64, PublicKey64, MAX_SCORE};
+use qiner::converters::{get_64_from_hex, get_hex_from_64, get_id_string, get_public_key_64_from_id, is_id_checksum_valid};
+use qiner::bench::{self, get_bench_public_key, get_thread_counts, BenchLimit, BenchReport, BENCH_RANDOM_SEED};
+use qiner::entropy::OsSource;
+use qiner::miner::Miner;
+use qiner::score::{get_mining_data, score_with_buffers, NeuronData};
+use qiner::network::{decrypt_solution_nonce, get_gamming_key, Packet};
+use qiner::network::capture::{read_flows, TcpFlow};
+use qiner::network::frame::FrameReader;
+use qiner::signing::KeyPair;
+use qiner::worker_state::WorkerPool;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+
+pub fn bench(threads: Option<usize>, seconds: u64, iterations: Option<u64>, scaling: bool, json: bool) -> ExitCode {
+	let max_threads = threads.unwrap_or_else(num_cpus::get).max(1);
+	let limit = match iterations {
+		Some(iterations) => BenchLimit::Iterations(iterations as usize),
+		None => BenchLimit::Duration(Duration::from_secs(seconds)),
+	};
+	// The threshold can not be reached, so every iteration runs the whole neuron loop
+	let miner = Arc::new(Miner::new(get_bench_public_key(), &BENCH_RANDOM_SEED, MAX_SCORE));
+	let worker_pool = Arc::new(WorkerPool::new(false));
+
+	let mut report = BenchReport { limit, runs: Vec::new() };
+	for threads in get_thread_counts(max_threads, scaling) {
+		match limit {
+			BenchLimit::Duration(duration) => log::info!("Benchmarking {threads} threads for {} s", duration.as_secs()),
+			BenchLimit::Iterations(iterations) => log::info!("Benchmarking {threads} threads for {iterations} iterations each"),
+		}
+		report.runs.push(bench::run(&miner, &worker_pool, threads, limit));
+	}
+
+	if json {
+		print!("{}", report.to_json());
+		return ExitCode::SUCCESS;
+	}
+
+	for run in &report.runs {
+		println!("{} threads:", run.threads.len());
+		for (idx, thread) in run.threads.iter().enumerate() {
+			println!("  thread {idx}: {:.2} it/s", thread.iterations_per_second());
+		}
+		let efficiency = report.scaling_efficiency(run).map_or(String::new(), |efficiency| format!(", scaling efficiency {:.1}%", efficiency * 100.0));
+		println!("  total: {:.2} it/s, {:.2} it/s per thread{efficiency}", run.iterations_per_second(), run.iterations_per_second_per_thread());
+	}
+
+	ExitCode::SUCCESS
+}
+
+/// Prints the score of every nonce, succeeds when all of them reach the threshold.
+/// With several IDs a nonce is scored for each of them and counts as a solution when one of them reaches the threshold
+pub fn verify(config: ScoringConfig, nonces: &[String]) -> ExitCode {
+	let mining_data = get_mining_data(&config.random_seed);
+	let mut neuron_data = NeuronData::default();
+	let mut all_solutions = true;
+
+	for identity in &config.identities {
+		println!("Id: {}", identity.id_string());
+	}
+	println!("Solution threshold: {}", config.solution_threshold);
+	for raw_nonce in nonces {
+		let Some(nonce) = get_64_from_hex::<4>(raw_nonce) else {
+			log::error!("{raw_nonce}: the nonce must be 64 hex characters");
+			all_solutions = false;
+			continue;
+		};
+
+		let mut is_solution = false;
+		for identity in &config.identities {
+			let score = score_with_buffers(&identity.public_key, &nonce, &mining_data, &mut neuron_data);
+			is_solution |= score >= config.solution_threshold;
+
+			let id_label = if config.identities.len() > 1 { format!(" {}", identity.id_string()) } else { String::new() };
+			println!(
+				"{}{id_label} score {score} {}",
+				get_hex_from_64(&nonce),
+				if score >= config.solution_threshold { "solution" } else { "below threshold" },
+			);
+		}
+		all_solutions &= is_solution;
+	}
+
+	if all_solutions { ExitCode::SUCCESS } else { ExitCode::FAILURE }
+}
+
+pub fn id_to_pubkey(id: &str) -> ExitCode {
+	let Ok(id) = Id::try_from(id.trim().as_bytes()) else {
+		log::error!("The ID must be 60 characters long");
+		return ExitCode::FAILURE;
+	};
+
+	let mut public_key = PublicKey64::default();
+	if !get_public_key_64_from_id(&id, &mut public_key) {
+		log::error!("The ID is invalid!");
+		return ExitCode::FAILURE;
+	}
+	if !is_id_checksum_valid(&id) {
+		log::warn!("The checksum of the ID is invalid");
+	}
+
+	println!("{}", get_hex_from_64(&public_key));
+
+	ExitCode::SUCCESS
+}
+
+pub fn pubkey_to_id(public_key: &str) -> ExitCode {
+	let Some(public_key) = get_64_from_hex::<4>(public_key) else {
+		log::error!("The public key must be 64 hex characters");
+		return ExitCode::FAILURE;
+	};
+
+	println!("{}", get_id_string(&public_key));
+
+	ExitCode::SUCCESS
+}
+
+/// The key pair of the configured signing seed
+pub fn get_signer(config: &Config) -> Option<KeyPair> {
+	config.signing_seed.as_ref().map(KeyPair::from_signing_seed)
+}
+
+pub fn check_config(config: &Config) -> ExitCode {
+	println!("Number of threads: {}", config.number_of_threads);
+	for identity in &config.identities {
+		println!("Id: {} weight {}", identity.id_string(), identity.weight);
+		println!("Public key: {}", get_hex_from_64(&identity.public_key));
+	}
+	println!("Id schedule: {}", config.id_schedule.as_str());
+	println!("Peers: {}", config.peers.join(", "));
+	println!("Submit mode: {}", config.submit_mode.as_str());
+	println!("Version: {:?}", config.version);
+	println!("Random seed: {:?}", config.random_seed);
+	println!("Solution threshold: {}", config.solution_threshold);
+	match get_signer(config) {
+		Some(signer) => println!("Signing identity: {}", get_id_string(&signer.public_key())),
+		None => println!("Signing identity: none, solutions are sent anonymous"),
+	}
+	if let Some(metrics_addr) = config.metrics_addr {
+		println!("Metrics: http://{metrics_addr}/metrics");
+	}
+	println!("The configuration is valid");
+
+	ExitCode::SUCCESS
+}
+
+/// Sends one harmless packet to every peer, succeeds when all of them accept it.
+/// Refuses when the zero nonce of the packet reaches the threshold, the node would take it for a solution
+pub fn send_test_packet(config: &Config) -> ExitCode {
+	let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+
+	let nonce = Nonce64::default();
+	let zero_nonce_score = qiner::score::score(&config.public_key, &nonce, &get_mining_data(&config.random_seed));
+	if zero_nonce_score >= config.solution_threshold {
+		log::error!("The zero nonce scores {zero_nonce_score} and reaches the threshold {}, refusing to send it as a test", config.solution_threshold);
+		return ExitCode::FAILURE;
+	}
+
+	let packet = match Packet::new(&BROADCAST_MESSAGE, &config.protocol(), &config.public_key, &nonce, get_signer(config).as_ref(), &mut OsSource) {
+		Ok(packet) => packet,
+		Err(err) => {
+			log::error!("Failed to build the packet: {err}");
+			return ExitCode::FAILURE;
+		}
+	};
+	let data = packet.to_bytes();
+
+	let mut all_sent = true;
+	for addr in &config.peers {
+		let result = runtime.block_on(async {
+			log::info!("Connecting to {addr}");
+			let mut stream = TcpStream::connect(addr).await?;
+			stream.write_all(&data).await?;
+			stream.shutdown().await
+		});
+
+		match result {
+			Ok(()) => log::info!("Sent {} Bytes to {addr}", data.len()),
+			Err(err) => {
+				log::error!("Failed to send the packet to {addr}: {:?}", err);
+				all_sent = false;
+			}
+		}
+	}
+
+	if all_sent { ExitCode::SUCCESS } else { ExitCode::FAILURE }
+}
+
+/// Prints every broadcast-message packet of a pcap file or hex dump, `-` reads stdin.
+/// The solution nonce of an anonymous packet, or of one signed by the configured signing identity, is decrypted and scored
+pub fn decode(config: ScoringConfig, signing_seed: Option<SigningSeed>, input: &Path) -> ExitCode {
+	let signer = signing_seed.as_ref().map(KeyPair::from_signing_seed);
+	let mut bytes = Vec::new();
+	let read_result = match input.to_str() {
+		Some("-") => std::io::stdin().read_to_end(&mut bytes).map(|_| ()),
+		_ => std::fs::read(input).map(|content| bytes = content),
+	};
+	if let Err(err) = read_result {
+		log::error!("Failed to read {}: {err}", input.display());
+		return ExitCode::FAILURE;
+	}
+
+	let flows = match read_flows(&bytes) {
+		Ok(flows) => flows,
+		Err(err) => {
+			log::error!("{}: {err}", input.display());
+			return ExitCode::FAILURE;
+		}
+	};
+
+	let mining_data = get_mining_data(&config.random_seed);
+	let mut neuron_data = NeuronData::default();
+	let mut number_of_packets = 0;
+	let mut all_read = true;
+	for flow in &flows {
+		let mut reader = FrameReader::default();
+		reader.extend(&flow.data);
+		loop {
+			let frame = match reader.next_frame() {
+				Ok(Some(frame)) => frame,
+				Ok(None) => break,
+				Err(err) => {
+					log::error!("Stream{}: {err}, the rest of it is skipped", get_flow_label(flow));
+					all_read = false;
+					break;
+				}
+			};
+			if frame.header.get_type() != BROADCAST_MESSAGE || frame.header.get_size() != Packet::SIZE {
+				continue;
+			}
+
+			let mut packet_bytes = Vec::with_capacity(Packet::SIZE);
+			frame.header.encode(&mut packet_bytes);
+			packet_bytes.extend_from_slice(&frame.payload);
+			let packet = Packet::decode(&packet_bytes).expect("the frame has the size of a packet");
+
+			number_of_packets += 1;
+			println!("Packet {number_of_packets}{}", get_flow_label(flow));
+			print_packet(&packet, signer.as_ref(), &config, |public_key, nonce| score_with_buffers(public_key, nonce, &mining_data, &mut neuron_data));
+		}
+	}
+
+	if number_of_packets == 0 {
+		log::error!("No broadcast-message packet found in {}", input.display());
+		return ExitCode::FAILURE;
+	}
+
+	if all_read { ExitCode::SUCCESS } else { ExitCode::FAILURE }
+}
+
+fn get_flow_label(flow: &TcpFlow) -> String {
+	match flow.endpoints {
+		Some((source, destination)) => format!(" {source} -> {destination}"),
+		None => String::new(),
+	}
+}
+
+fn print_packet(packet: &Packet, signer: Option<&KeyPair>, config: &ScoringConfig, mut score: impl FnMut(&PublicKey64, &Nonce64) -> usize) {
+	let header = packet.get_header();
+	let message = packet.get_message();
+	let source_public_key = message.get_source_public_key();
+	let destination_public_key = message.get_destination_public_key();
+	let is_anonymous = source_public_key == PublicKey64::default();
+
+	println!(
+		"  Header: size {}, protocol {}, dejavu {}, type {}",
+		header.get_size(),
+		header.get_protocol(),
+		hex::encode(header.get_dejavu()),
+		header.get_type(),
+	);
+	println!("  Source: {}", if is_anonymous { "anonymous".to_string() } else { get_id_string(&source_public_key) });
+	println!("  Destination: {}", get_id_string(&destination_public_key));
+	println!("  Gamming nonce: {}", get_hex_from_64(&message.get_gamming_nonce()));
+	println!("  Signature: {}", match (is_anonymous, packet.is_signature_valid()) {
+		(true, _) => "none, random Bytes",
+		(false, true) => "valid",
+		(false, false) => "invalid",
+	});
+
+	// The sender agreed on the key with the computor, so its own identity can decrypt the nonce too
+	let shared_key = match signer {
+		_ if is_anonymous => Some(Key::default()),
+		Some(signer) if signer.public_key() == source_public_key => signer.get_shared_key(&destination_public_key),
+		_ => None,
+	};
+	let Some(shared_key) = shared_key else {
+		println!("  Solution nonce: encrypted, only the computor or the source identity can decrypt it");
+		return;
+	};
+
+	let gamming_key = get_gamming_key(&shared_key, &message.get_gamming_nonce());
+	if gamming_key[0] != 0 {
+		println!("  Gamming key: {}, the computor does not read it as a solution", hex::encode(gamming_key));
+	}
+
+	let nonce = decrypt_solution_nonce(&gamming_key, &packet.get_solution_nonce());
+	let score = score(&destination_public_key, &nonce);
+	println!("  Solution nonce: {}", get_hex_from_64(&nonce));
+	println!(
+		"  Score: {score} {}",
+		if score >= config.solution_threshold { "solution" } else { "below threshold" },
+	);
+}
@@ -1,70 +1,40 @@
-﻿use k12::digest::{ExtendableOutput, Update};
-use k12::KangarooTwelve;
-use lib::types::{Id, PublicKey, PublicKey64};
+use std::mem::size_of;
+use lib::types::{Id, PublicKey64};
+pub use lib::converters::{get_id_from_public_key_64, get_public_key_64_from_id, is_id_checksum_valid};
 
-const A: u8 = 'A' as u8;
+pub fn get_id_string(public_key: &PublicKey64) -> String {
+	let mut id: Id = [0; 60];
+	get_id_from_public_key_64(public_key, &mut id);
 
-pub fn get_public_key_64_from_id(id: &Id, public_key: &mut PublicKey64) -> bool {
-	*public_key = Default::default();
-
-	for i in 0..4 {
-		for j in (0..14).rev() {
-			let id_value = id[i * 14 + j];
-			if id_value < 'A' as u8 || id_value > 'Z' as u8 {
-				*public_key = Default::default();
-
-				return false;
-			}
-
-			let delta_id_value = (id_value - A) as u64;
-
-			public_key[i] = public_key[i] * 26u64 + delta_id_value;
-		}
-	}
+	String::from_utf8_lossy(&id).into_owned()
+}
 
-	true
+/// Hex of the little-endian bytes, the layout public keys and nonces have in memory and on the wire
+pub fn get_hex_from_64<const S: usize>(value: &[u64; S]) -> String {
+	value.iter().map(|item| hex::encode(item.to_le_bytes())).collect()
 }
 
-pub fn get_id_from_public_key_64(public_key: &PublicKey64, id: &mut Id) {
-	for i in 0..4 {
-		let mut public_key_fragment = public_key[i];
-		for j in 0..14 {
-			let id_idx = i * 14usize + j;
-			id[id_idx] = (public_key_fragment % 26u64 + ('A' as u64)) as u8;
-			public_key_fragment /= 26;
-		}
+pub fn get_64_from_hex<const S: usize>(value: &str) -> Option<[u64; S]> {
+	let bytes = hex::decode(value.trim()).ok()?;
+	if bytes.len() != S * size_of::<u64>() {
+		return None;
 	}
 
-	// Get Identity Bytes Checksum
-	let mut identity_bytes_checksum: u32;
-	{
-		let mut kangaroo_twelve = KangarooTwelve::default();
-		let ptr_public_key_8 = public_key.as_ptr() as *const PublicKey;
-		unsafe {
-			kangaroo_twelve.update(&ptr_public_key_8.read());
-
-			let mut result: [u8; 3] = Default::default();
-			kangaroo_twelve.finalize_xof_into(&mut result);
-			identity_bytes_checksum = result[0] as u32 | (result[1] as u32) << 8 | (result[2] as u32) << 16;
-		}
+	let mut result = [0u64; S];
+	for (item, chunk) in result.iter_mut().zip(bytes.chunks_exact(size_of::<u64>())) {
+		*item = u64::from_le_bytes(chunk.try_into().unwrap());
 	}
 
-	identity_bytes_checksum &= 0x3FFFF;
-	for i in 0..4 {
-		id[56 + i] = (identity_bytes_checksum % 26 + 'A' as u32) as u8;
-		identity_bytes_checksum /= 26;
-	}
+	Some(result)
 }
 
 #[test]
-fn test_public_key_converters() {
-	let id: Id = "UBAZRCVPOZTDKGCBNPGYFUPLZXDDNHSEGJRTAJKWJBHJDKHMAKVVFAKCZGRI".as_bytes().try_into().unwrap();
-
-	let mut public_key: PublicKey64 = Default::default();
-	get_public_key_64_from_id(&id, &mut public_key);
-
-	let mut id_from_key: Id = unsafe { std::mem::zeroed::<Id>() };
-	get_id_from_public_key_64(&public_key, &mut id_from_key);
-
-	assert_eq!(id, id_from_key);
-}
\ No newline at end of file
+fn test_hex_converters() {
+	let nonce = [1u64, 0x0102030405060708, 0, u64::MAX];
+	let hex = get_hex_from_64(&nonce);
+
+	assert_eq!("01000000000000000807060504030201", &hex[..32]);
+	assert_eq!(Some(nonce), get_64_from_hex(&hex));
+	assert_eq!(None, get_64_from_hex::<4>(&hex[2..]));
+	assert_eq!(None, get_64_from_hex::<4>("zz"));
+}
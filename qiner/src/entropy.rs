@@ -0,0 +1,218 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::fmt;
+use std::mem::{size_of, size_of_val};
+use k12::digest::{ExtendableOutput, Update};
+use k12::KangarooTwelve;
+use lib::config::NonceSourceKind;
+use rand_chacha::ChaCha20Rng;
+use rand_chacha::rand_core::{RngCore, SeedableRng};
+
+/// Intel recommends giving up after 10 failed RDRAND calls in a row
+pub const RDRAND_RETRIES: usize = 10;
+
+#[derive(Debug)]
+pub enum EntropyError {
+	RdRandUnsupported,
+	RdRandExhausted,
+	Os(getrandom::Error),
+}
+
+impl Display for EntropyError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self {
+			EntropyError::RdRandUnsupported => write!(f, "RDRAND is not supported by this CPU"),
+			EntropyError::RdRandExhausted => write!(f, "RDRAND failed {RDRAND_RETRIES} times in a row"),
+			EntropyError::Os(err) => write!(f, "the OS random generator failed: {err}"),
+		}
+	}
+}
+
+impl Error for EntropyError {}
+
+/// Source of random nonces and of the other random parts of a packet
+pub trait NonceSource: Send {
+	fn fill(&mut self, dest: &mut [u64]) -> Result<(), EntropyError>;
+
+	fn name(&self) -> &'static str;
+
+	fn next_u64(&mut self) -> Result<u64, EntropyError> {
+		let mut value = [0u64];
+		self.fill(&mut value)?;
+
+		Ok(value[0])
+	}
+}
+
+pub fn new_nonce_source(kind: NonceSourceKind) -> Result<Box<dyn NonceSource>, EntropyError> {
+	Ok(match kind {
+		NonceSourceKind::Auto => match RdRandSource::new() {
+			Ok(source) => Box::new(source),
+			Err(_) => Box::new(OsSource),
+		},
+		NonceSourceKind::RdRand => Box::new(RdRandSource::new()?),
+		NonceSourceKind::Os => Box::new(OsSource),
+		NonceSourceKind::ChaCha => Box::new(ChaChaSource::from_os()?),
+	})
+}
+
+/// One source per worker, derived from `mining_seed` in the deterministic mode
+pub fn new_worker_nonce_sources(kind: NonceSourceKind, mining_seed: Option<u64>, number_of_workers: usize) -> Result<Vec<Box<dyn NonceSource>>, EntropyError> {
+	(0..number_of_workers).map(|worker_idx| new_worker_nonce_source(kind, mining_seed, worker_idx)).collect()
+}
+
+/// The source of the worker `worker_idx`, see [`new_worker_nonce_sources`]
+pub fn new_worker_nonce_source(kind: NonceSourceKind, mining_seed: Option<u64>, worker_idx: usize) -> Result<Box<dyn NonceSource>, EntropyError> {
+	match mining_seed {
+		Some(mining_seed) => Ok(Box::new(ChaChaSource::for_worker(mining_seed, worker_idx))),
+		None => new_nonce_source(kind),
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RdRandSource {
+	_private: (),
+}
+
+impl RdRandSource {
+	pub fn new() -> Result<Self, EntropyError> {
+		#[cfg(target_arch = "x86_64")]
+		if std::arch::is_x86_feature_detected!("rdrand") {
+			return Ok(RdRandSource { _private: () });
+		}
+
+		Err(EntropyError::RdRandUnsupported)
+	}
+}
+
+impl NonceSource for RdRandSource {
+	#[cfg(target_arch = "x86_64")]
+	fn fill(&mut self, dest: &mut [u64]) -> Result<(), EntropyError> {
+		#[target_feature(enable = "rdrand")]
+		unsafe fn rdrand64(item: &mut u64) -> bool {
+			(0..RDRAND_RETRIES).any(|_| std::arch::x86_64::_rdrand64_step(item) == 1)
+		}
+
+		// Safety: the source is only created when the CPU supports RDRAND
+		if dest.iter_mut().all(|item| unsafe { rdrand64(item) }) {
+			Ok(())
+		} else {
+			Err(EntropyError::RdRandExhausted)
+		}
+	}
+
+	#[cfg(not(target_arch = "x86_64"))]
+	fn fill(&mut self, _dest: &mut [u64]) -> Result<(), EntropyError> {
+		Err(EntropyError::RdRandUnsupported)
+	}
+
+	fn name(&self) -> &'static str {
+		"rdrand"
+	}
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OsSource;
+
+impl NonceSource for OsSource {
+	fn fill(&mut self, dest: &mut [u64]) -> Result<(), EntropyError> {
+		let mut bytes = vec![0u8; size_of_val(dest)];
+		getrandom::fill(&mut bytes).map_err(EntropyError::Os)?;
+		for (item, chunk) in dest.iter_mut().zip(bytes.chunks_exact(size_of::<u64>())) {
+			*item = u64::from_le_bytes(chunk.try_into().unwrap());
+		}
+
+		Ok(())
+	}
+
+	fn name(&self) -> &'static str {
+		"os"
+	}
+}
+
+#[derive(Debug, Clone)]
+pub struct ChaChaSource {
+	rng: ChaCha20Rng,
+}
+
+impl ChaChaSource {
+	pub fn from_seed(seed: [u8; 32]) -> Self {
+		ChaChaSource {
+			rng: ChaCha20Rng::from_seed(seed),
+		}
+	}
+
+	/// Independent stream of a worker in the deterministic mode.
+	/// The key is the K12 hash of the master seed and the stream is the worker index
+	pub fn for_worker(master_seed: u64, worker_idx: usize) -> Self {
+		let mut key = [0u8; 32];
+		let mut kangaroo_twelve = KangarooTwelve::default();
+		kangaroo_twelve.update(&master_seed.to_le_bytes());
+		kangaroo_twelve.finalize_xof_into(&mut key);
+
+		let mut source = ChaChaSource::from_seed(key);
+		source.rng.set_stream(worker_idx as u64);
+
+		source
+	}
+
+	pub fn from_os() -> Result<Self, EntropyError> {
+		let mut seed = [0u8; 32];
+		getrandom::fill(&mut seed).map_err(EntropyError::Os)?;
+
+		Ok(ChaChaSource::from_seed(seed))
+	}
+}
+
+impl NonceSource for ChaChaSource {
+	fn fill(&mut self, dest: &mut [u64]) -> Result<(), EntropyError> {
+		dest.iter_mut().for_each(|item| *item = self.rng.next_u64());
+
+		Ok(())
+	}
+
+	fn name(&self) -> &'static str {
+		"chacha"
+	}
+}
+
+#[test]
+fn test_chacha_source_is_deterministic() {
+	let mut first = ChaChaSource::from_seed([7; 32]);
+	let mut second = ChaChaSource::from_seed([7; 32]);
+	let mut first_values = [0u64; 8];
+	let mut second_values = [0u64; 8];
+	first.fill(&mut first_values).unwrap();
+	second.fill(&mut second_values).unwrap();
+
+	assert_eq!(first_values, second_values);
+	assert_ne!(first_values, [0u64; 8]);
+}
+
+#[test]
+fn test_worker_streams() {
+	let mut values = [[0u64; 4]; 3];
+	ChaChaSource::for_worker(42, 0).fill(&mut values[0]).unwrap();
+	ChaChaSource::for_worker(42, 1).fill(&mut values[1]).unwrap();
+	ChaChaSource::for_worker(43, 0).fill(&mut values[2]).unwrap();
+
+	assert_ne!(values[0], values[1]);
+	assert_ne!(values[0], values[2]);
+
+	let mut same = [0u64; 4];
+	ChaChaSource::for_worker(42, 1).fill(&mut same).unwrap();
+	assert_eq!(values[1], same);
+}
+
+#[test]
+fn test_new_nonce_source() {
+	let mut values = [0u64; 4];
+	for kind in [NonceSourceKind::Auto, NonceSourceKind::Os, NonceSourceKind::ChaCha] {
+		new_nonce_source(kind).unwrap().fill(&mut values).unwrap();
+		assert_ne!(values, [0u64; 4]);
+	}
+
+	if let Ok(mut source) = new_nonce_source(NonceSourceKind::RdRand) {
+		source.fill(&mut values).unwrap();
+	}
+}
@@ -0,0 +1,146 @@
+use std::time::Duration;
+
+/// Nonces needed at or above the threshold before their share is used as the probability,
+/// below that the normal approximation of the scores is used
+const MIN_TAIL_SAMPLES: u64 = 16;
+
+/// The scores reached so far, see [`ScoreHistogram::distribution`](crate::miner::ScoreHistogram::distribution)
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScoreDistribution {
+	counts: Vec<u64>,
+	count: u64,
+	mean: f64,
+	std_dev: f64,
+}
+
+impl ScoreDistribution {
+	/// `None` before the first nonce
+	pub fn from_counts(counts: Vec<u64>) -> Option<Self> {
+		let count = counts.iter().sum::<u64>();
+		if count == 0 {
+			return None;
+		}
+
+		let mean = counts.iter().enumerate().map(|(score, n)| score as f64 * *n as f64).sum::<f64>() / count as f64;
+		let variance = counts.iter().enumerate().map(|(score, n)| (score as f64 - mean).powi(2) * *n as f64).sum::<f64>() / count as f64;
+
+		Some(ScoreDistribution { counts, count, mean, std_dev: variance.sqrt() })
+	}
+
+	pub fn count(&self) -> u64 {
+		self.count
+	}
+
+	pub fn best_score(&self) -> usize {
+		self.counts.iter().rposition(|n| *n > 0).unwrap_or_default()
+	}
+
+	pub fn mean(&self) -> f64 {
+		self.mean
+	}
+
+	pub fn std_dev(&self) -> f64 {
+		self.std_dev
+	}
+
+	/// Probability of a nonce to score at least `threshold`
+	pub fn probability_at_least(&self, threshold: usize) -> f64 {
+		let tail = self.counts.iter().skip(threshold).sum::<u64>();
+		if tail >= MIN_TAIL_SAMPLES {
+			return tail as f64 / self.count as f64;
+		}
+
+		if self.std_dev == 0.0 {
+			return if threshold as f64 <= self.mean { 1.0 } else { 0.0 };
+		}
+
+		// Scores are integers, the normal tail starts half a score lower
+		let z = (threshold as f64 - 0.5 - self.mean) / self.std_dev;
+		0.5 * erfc(z / std::f64::consts::SQRT_2)
+	}
+
+	/// Expected time to the next solution at `iterations_per_second`, `None` when it can not be told
+	pub fn expected_time_to_solution(&self, threshold: usize, iterations_per_second: f64) -> Option<Duration> {
+		let solutions_per_second = self.probability_at_least(threshold) * iterations_per_second;
+		if solutions_per_second <= 0.0 {
+			return None;
+		}
+
+		Duration::try_from_secs_f64(1.0 / solutions_per_second).ok()
+	}
+}
+
+/// Complementary error function, the approximation of Numerical Recipes with a relative error below 1.2e-7
+fn erfc(x: f64) -> f64 {
+	let z = x.abs();
+	let t = 1.0 / (1.0 + 0.5 * z);
+	let coefficients = [
+		-1.26551223, 1.00002368, 0.37409196, 0.09678418, -0.18628806,
+		0.27886807, -1.13520398, 1.48851587, -0.82215223, 0.17087277,
+	];
+	let polynomial = coefficients.iter().rev().fold(0.0, |sum, coefficient| coefficient + t * sum);
+	let erfc = t * (-z * z + polynomial).exp();
+
+	if x >= 0.0 { erfc } else { 2.0 - erfc }
+}
+
+/// Rounds to the two largest units, e.g. `3h 12m`
+pub fn format_duration(duration: Duration) -> String {
+	let seconds = duration.as_secs();
+	let (days, hours, minutes) = (seconds / 86400, seconds / 3600 % 24, seconds / 60 % 60);
+
+	if days >= 365 * 100 {
+		"over 100 years".to_string()
+	} else if days > 0 {
+		format!("{days}d {hours}h")
+	} else if hours > 0 {
+		format!("{hours}h {minutes}m")
+	} else if minutes > 0 {
+		format!("{minutes}m {}s", seconds % 60)
+	} else {
+		format!("{seconds}s")
+	}
+}
+
+#[test]
+fn test_score_distribution() {
+	assert_eq!(None, ScoreDistribution::from_counts(vec![0, 0]));
+
+	// 20 nonces at 2, 20 at 4 and 10 at 5
+	let distribution = ScoreDistribution::from_counts(vec![0, 0, 20, 0, 20, 10]).unwrap();
+	assert_eq!(50, distribution.count());
+	assert_eq!(5, distribution.best_score());
+	assert_eq!(3.4, distribution.mean());
+
+	// Enough nonces reached 4 to count them
+	assert_eq!(0.6, distribution.probability_at_least(4));
+	assert_eq!(1.0, distribution.probability_at_least(2));
+	assert_eq!(Some(Duration::from_millis(250)), distribution.expected_time_to_solution(2, 4.0));
+
+	// Too few reached 5 and none 6, the normal approximation takes over
+	let above_best = distribution.probability_at_least(6);
+	assert!(above_best > 0.0 && above_best < distribution.probability_at_least(5));
+	assert_eq!(None, distribution.expected_time_to_solution(4, 0.0));
+
+	let constant = ScoreDistribution::from_counts(vec![0, 0, 0, 5]).unwrap();
+	assert_eq!(1.0, constant.probability_at_least(3));
+	assert_eq!(0.0, constant.probability_at_least(4));
+	assert_eq!(None, constant.expected_time_to_solution(4, 100.0));
+}
+
+#[test]
+fn test_erfc() {
+	assert!((erfc(0.0) - 1.0).abs() < 1e-7);
+	assert!((erfc(1.0) - 0.157_299_207).abs() < 1e-7);
+	assert!((erfc(-1.0) - 1.842_700_793).abs() < 1e-7);
+	assert!((erfc(5.0) / 1.537_459_794_4e-12 - 1.0).abs() < 1e-6);
+}
+
+#[test]
+fn test_format_duration() {
+	assert_eq!("42s", format_duration(Duration::from_secs(42)));
+	assert_eq!("2m 5s", format_duration(Duration::from_secs(125)));
+	assert_eq!("3h 12m", format_duration(Duration::from_secs(3 * 3600 + 12 * 60 + 59)));
+	assert_eq!("2d 1h", format_duration(Duration::from_secs(49 * 3600)));
+	assert_eq!("over 100 years", format_duration(Duration::from_secs(u64::MAX)));
+}
@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use lib::types::{Nonce64, PublicKey64, Seed};
+use crate::converters::{get_64_from_hex, get_hex_from_64};
+use crate::miner::Solution;
+#[cfg(test)]
+use crate::test_utils::test_solution;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SolutionState {
+	Found,
+	Sent,
+	/// No packet could be built for the solution, it is never sent
+	Dropped,
+	/// A peer relayed the sent packet back, so a node read and checked it
+	Acknowledged,
+}
+
+impl SolutionState {
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			SolutionState::Found => "found",
+			SolutionState::Sent => "sent",
+			SolutionState::Dropped => "dropped",
+			SolutionState::Acknowledged => "acknowledged",
+		}
+	}
+
+	pub fn parse(raw: &str) -> Option<SolutionState> {
+		match raw {
+			"found" => Some(SolutionState::Found),
+			"sent" => Some(SolutionState::Sent),
+			"dropped" => Some(SolutionState::Dropped),
+			"acknowledged" => Some(SolutionState::Acknowledged),
+			_ => None,
+		}
+	}
+}
+
+/// One line of the journal:
+/// `<unix time in ms> <state> <random seed hex> <public key hex> <nonce hex> <score>`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JournalEntry {
+	pub timestamp: u64,
+	pub state: SolutionState,
+	pub random_seed: Seed,
+	pub public_key: PublicKey64,
+	pub nonce: Nonce64,
+	pub score: usize,
+}
+
+impl JournalEntry {
+	pub fn new(state: SolutionState, solution: &Solution) -> Self {
+		let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map(|time| time.as_millis() as u64).unwrap_or(0);
+
+		JournalEntry {
+			timestamp,
+			state,
+			random_seed: solution.random_seed,
+			public_key: solution.public_key,
+			nonce: solution.nonce,
+			score: solution.score,
+		}
+	}
+
+	pub fn solution(&self) -> Solution {
+		Solution {
+			random_seed: self.random_seed,
+			public_key: self.public_key,
+			nonce: self.nonce,
+			score: self.score,
+		}
+	}
+
+	pub fn to_line(&self) -> String {
+		format!(
+			"{} {} {} {} {} {}",
+			self.timestamp,
+			self.state.as_str(),
+			hex::encode(self.random_seed),
+			get_hex_from_64(&self.public_key),
+			get_hex_from_64(&self.nonce),
+			self.score,
+		)
+	}
+
+	pub fn from_line(line: &str) -> Result<Self, String> {
+		let fields = line.split_whitespace().collect::<Vec<&str>>();
+		let [timestamp, state, random_seed, public_key, nonce, score] = fields[..] else {
+			return Err(format!("expected 6 fields, found {}", fields.len()));
+		};
+
+		let mut seed = Seed::default();
+		hex::decode_to_slice(random_seed, &mut seed).map_err(|_| format!("'{random_seed}' is not a random seed"))?;
+
+		Ok(JournalEntry {
+			timestamp: timestamp.parse().map_err(|_| format!("'{timestamp}' is not a timestamp"))?,
+			state: SolutionState::parse(state).ok_or_else(|| format!("'{state}' is not a solution state"))?,
+			random_seed: seed,
+			public_key: get_64_from_hex(public_key).ok_or_else(|| format!("'{public_key}' is not a public key"))?,
+			nonce: get_64_from_hex(nonce).ok_or_else(|| format!("'{nonce}' is not a nonce"))?,
+			score: score.parse().map_err(|_| format!("'{score}' is not a score"))?,
+		})
+	}
+
+	fn key(&self) -> (Seed, PublicKey64, Nonce64) {
+		(self.random_seed, self.public_key, self.nonce)
+	}
+}
+
+/// Append-only record of every found solution and of its later states.
+/// Each entry is flushed to the disk before `append` returns, so a crash loses nothing written
+#[derive(Debug)]
+pub struct Journal {
+	path: PathBuf,
+	file: Mutex<File>,
+}
+
+impl Journal {
+	/// Rewrites the file with the solutions still to be sent, so it does not grow with every start.
+	/// The other solutions and a last line torn by a crash are left out
+	pub fn open(path: &Path) -> io::Result<Self> {
+		let entries = Journal::replay(path)?;
+		let found = entries.iter().filter(|entry| entry.state == SolutionState::Found).collect::<Vec<_>>();
+
+		// Written aside and renamed, so a crash meanwhile leaves the old file whole
+		let mut compacted_path = path.as_os_str().to_owned();
+		compacted_path.push(".compacted");
+		let mut compacted = File::create(&compacted_path)?;
+		for entry in &found {
+			writeln!(compacted, "{}", entry.to_line())?;
+		}
+		compacted.sync_all()?;
+		fs::rename(&compacted_path, path)?;
+		if found.len() < entries.len() {
+			log::info!("{}: left out {} solutions which need nothing more", path.display(), entries.len() - found.len());
+		}
+
+		let file = OpenOptions::new().append(true).open(path)?;
+
+		Ok(Journal {
+			path: path.to_path_buf(),
+			file: Mutex::new(file),
+		})
+	}
+
+	pub fn path(&self) -> &Path {
+		&self.path
+	}
+
+	pub fn append(&self, entry: &JournalEntry) -> io::Result<()> {
+		let mut file = self.file.lock().unwrap();
+		writeln!(file, "{}", entry.to_line())?;
+
+		file.sync_data()
+	}
+
+	pub fn record(&self, state: SolutionState, solution: &Solution) -> io::Result<()> {
+		self.append(&JournalEntry::new(state, solution))
+	}
+
+	/// The latest entry of every solution, in the order they were found.
+	/// Unreadable lines, e.g. one torn by a crash, are logged and skipped
+	pub fn replay(path: &Path) -> io::Result<Vec<JournalEntry>> {
+		let file = match File::open(path) {
+			Ok(file) => file,
+			Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+			Err(err) => return Err(err),
+		};
+
+		let mut entries: Vec<JournalEntry> = Vec::new();
+		let mut positions = HashMap::new();
+		for (line_idx, line) in BufReader::new(file).lines().enumerate() {
+			let line = line?;
+			if line.trim().is_empty() {
+				continue;
+			}
+
+			match JournalEntry::from_line(&line) {
+				Ok(entry) => match positions.get(&entry.key()) {
+					Some(&position) => entries[position] = entry,
+					None => {
+						positions.insert(entry.key(), entries.len());
+						entries.push(entry);
+					}
+				},
+				Err(err) => log::warn!("{}:{}: skipping the entry, {err}", path.display(), line_idx + 1),
+			}
+		}
+
+		Ok(entries)
+	}
+}
+
+#[test]
+fn test_journal_entry_line() {
+	let entry = JournalEntry::new(SolutionState::Sent, &test_solution(1));
+	assert_eq!(Ok(entry.clone()), JournalEntry::from_line(&entry.to_line()));
+
+	assert!(JournalEntry::from_line("1 found 00").is_err());
+	assert!(JournalEntry::from_line(&entry.to_line().replace("sent", "lost")).is_err());
+}
+
+#[test]
+fn test_journal_replay() {
+	let journal_file = crate::test_utils::TestFile::new("replay.journal");
+	let path = journal_file.path();
+	// The same nonce for another random seed is another solution
+	let other_seed_solution = Solution { random_seed: [2; 32], ..test_solution(1) };
+	let journal = Journal::open(path).unwrap();
+	journal.record(SolutionState::Found, &test_solution(1)).unwrap();
+	journal.record(SolutionState::Found, &test_solution(2)).unwrap();
+	journal.record(SolutionState::Sent, &test_solution(1)).unwrap();
+	journal.record(SolutionState::Found, &other_seed_solution).unwrap();
+	journal.record(SolutionState::Acknowledged, &test_solution(1)).unwrap();
+	drop(journal);
+
+	// A torn last line
+	let mut file = OpenOptions::new().append(true).open(path).unwrap();
+	write!(file, "1700000000000 fou").unwrap();
+	drop(file);
+
+	let entries = Journal::replay(path).unwrap();
+	let states = entries.iter().map(|entry| (entry.solution(), entry.state)).collect::<Vec<_>>();
+	assert_eq!(vec![
+		(test_solution(1), SolutionState::Acknowledged),
+		(test_solution(2), SolutionState::Found),
+		(other_seed_solution, SolutionState::Found),
+	], states);
+
+	// Opening again keeps only the unsent solutions and leaves the torn line out,
+	// so the next entry is not joined to it
+	let journal = Journal::open(path).unwrap();
+	assert_eq!(2, std::fs::read_to_string(path).unwrap().lines().count());
+	journal.record(SolutionState::Found, &test_solution(3)).unwrap();
+	drop(journal);
+
+	let entries = Journal::replay(path).unwrap();
+	let solutions = entries.iter().map(JournalEntry::solution).collect::<Vec<_>>();
+	assert_eq!(vec![test_solution(2), other_seed_solution, test_solution(3)], solutions);
+	assert!(entries.iter().all(|entry| entry.state == SolutionState::Found));
+	assert!(std::fs::read_to_string(path).unwrap().lines().all(|line| JournalEntry::from_line(line).is_ok()));
+
+	std::fs::remove_file(path).unwrap();
+	assert!(Journal::replay(path).unwrap().is_empty());
+}
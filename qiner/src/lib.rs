@@ -1,4 +1,19 @@
 ﻿pub mod miner;
 pub mod math;
 pub mod converters;
-pub mod network;
\ No newline at end of file
+pub mod network;
+pub mod entropy;
+pub mod score;
+pub mod worker_state;
+pub mod affinity;
+pub mod journal;
+pub mod submission;
+pub mod peers;
+pub mod signing;
+pub mod reload;
+pub mod logger;
+pub mod metrics;
+pub mod estimate;
+pub mod bench;
+#[cfg(test)]
+mod test_utils;
@@ -0,0 +1,67 @@
+use std::env;
+use std::sync::{OnceLock, RwLock};
+use log::{Log, Metadata, Record, SetLoggerError};
+use lib::env_names::ENV_RUST_LOG;
+use pretty_env_logger::env_logger::Logger;
+
+static LOGGER: OnceLock<ReloadableLogger> = OnceLock::new();
+
+/// The logger of `pretty_env_logger`, whose filter can be replaced while running
+struct ReloadableLogger {
+	logger: RwLock<Logger>,
+}
+
+impl Log for ReloadableLogger {
+	fn enabled(&self, metadata: &Metadata) -> bool {
+		self.logger.read().unwrap().enabled(metadata)
+	}
+
+	fn log(&self, record: &Record) {
+		self.logger.read().unwrap().log(record)
+	}
+
+	fn flush(&self) {
+		self.logger.read().unwrap().flush()
+	}
+}
+
+/// A timed logger like `pretty_env_logger::init_timed`, only errors pass an empty filter
+fn build_logger(filter: &str) -> Logger {
+	pretty_env_logger::formatted_timed_builder().parse_filters(filter).build()
+}
+
+/// Installs the logger with the filter of `RUST_LOG`, like `pretty_env_logger::init_timed`
+pub fn init_timed() -> Result<(), SetLoggerError> {
+	let logger = build_logger(&env::var(ENV_RUST_LOG).unwrap_or_default());
+	let max_level = logger.filter();
+
+	log::set_logger(LOGGER.get_or_init(|| ReloadableLogger { logger: RwLock::new(logger) }))?;
+	log::set_max_level(max_level);
+
+	Ok(())
+}
+
+/// Replaces the filter of the logger installed by [`init_timed`], does nothing without it
+pub fn set_filter(filter: &str) {
+	let Some(reloadable) = LOGGER.get() else {
+		return;
+	};
+
+	let logger = build_logger(filter);
+	log::set_max_level(logger.filter());
+	*reloadable.logger.write().unwrap() = logger;
+}
+
+#[test]
+fn test_build_logger() {
+	let metadata = |level, target| Metadata::builder().level(level).target(target).build();
+
+	let logger = build_logger("");
+	assert!(logger.enabled(&metadata(log::Level::Error, "qiner")));
+	assert!(!logger.enabled(&metadata(log::Level::Warn, "qiner")));
+
+	let logger = build_logger("warn,qiner::miner=debug");
+	assert!(logger.enabled(&metadata(log::Level::Debug, "qiner::miner")));
+	assert!(!logger.enabled(&metadata(log::Level::Debug, "qiner::submission")));
+	assert_eq!(log::LevelFilter::Debug, logger.filter());
+}
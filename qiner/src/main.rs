@@ -1,170 +1,317 @@
-use qiner::miner::{Miner};
-use tokio;
-use lib::types::{Id, PublicKey64, STACK_SIZE};
-use std::{env};
-use std::mem::{size_of, transmute};
-use std::sync::Arc;
+mod cli;
+mod commands;
+
+use qiner::miner::{Miner, Solution};
+use qiner::affinity::get_available_cpus;
+use std::io;
+use std::path::Path;
+use std::process::ExitCode;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+use clap::Parser;
 use tokio::runtime::Builder;
-use qiner::converters::get_public_key_64_from_id;
-use lib::env_names::{ENV_ID, ENV_NUMBER_OF_THREADS, ENV_SERVER_IP, ENV_SERVER_PORT};
-use qiner::network::Packet;
-use lib::types::network::protocols::BROADCAST_MESSAGE;
-use tokio::io::AsyncWriteExt;
-use tokio::net::TcpStream;
-use lib::random_seed::get_random_seed;
-use lib::solution_threshold::get_solution_threshold;
-use lib::version::get_version;
-
-fn get_number_of_thread() -> usize {
-    env::var(ENV_NUMBER_OF_THREADS).unwrap().parse::<usize>().unwrap()
-}
+use qiner::entropy::{new_worker_nonce_sources, NonceSource, OsSource};
+use qiner::worker_state::WorkerPool;
+use qiner::journal::{Journal, JournalEntry, SolutionState};
+use qiner::submission::{submission_queue, Submitter};
+use qiner::peers::PeerSet;
+use lib::config::{Config, ConfigError, ConfigSources, IdSchedule, RawConfig, ScoringConfig, SigningSeed};
+use qiner::logger;
+use qiner::metrics::{serve, Metrics};
+use qiner::estimate::format_duration;
+use qiner::reload::{watch_config, LiveConfig, RELOAD_INTERVAL};
+use qiner::converters::{get_hex_from_64, get_id_string};
+use crate::cli::{Cli, Command};
 
-fn get_server_ip() -> String {
-    env::var(ENV_SERVER_IP).unwrap_or_default()
-}
+/// Mining runs on its own OS threads, the runtime only drives the display and network tasks
+const NETWORK_THREADS: usize = 2;
 
-fn get_server_port() -> String {
-    env::var(ENV_SERVER_PORT).unwrap_or_default()
-}
+/// Time for the last submission on shutdown
+const SHUTDOWN_SEND_TIMEOUT: Duration = Duration::from_secs(10);
 
-fn get_id() -> String {
-    env::var(ENV_ID).unwrap_or_default()
-}
+/// Exit code when some solutions are left unsent in the journal
+const EXIT_UNSENT_SOLUTIONS: u8 = 2;
+
+fn main() -> ExitCode {
+    // Taken before .env is loaded into the environment, so that .env can be read again on changes
+    let process_env = RawConfig::from_env();
 
-fn main() {
     // Init dotenv
-    dotenv::dotenv().ok();
+    let dotenv_file = dotenv::dotenv().ok();
 
-    pretty_env_logger::init_timed();
+    logger::init_timed().expect("the logger is installed once");
 
-    let number_of_threads = get_number_of_thread() + 1;
-    let stack_size = STACK_SIZE * number_of_threads;
+    let cli = Cli::parse();
+    let sources = ConfigSources::new(cli.config.config.as_deref(), dotenv_file, process_env, cli.config.to_raw_config());
+    match cli.command.unwrap_or(Command::Mine) {
+        Command::Mine => with_config(&sources, |config| mine(config, sources.clone())),
+        Command::Bench { seconds, iterations, scaling, json } => match cli.config.threads.as_deref().map(|threads| threads.trim().parse::<usize>()).transpose() {
+            Ok(threads) => commands::bench(threads, seconds, iterations, scaling, json),
+            Err(_) => {
+                log::error!("--threads must be a number");
+                ExitCode::FAILURE
+            }
+        },
+        Command::Verify { nonces } => with_raw_config(&sources, |raw| Ok(commands::verify(ScoringConfig::from_raw(&raw)?, &nonces))),
+        Command::IdToPubkey { id } => commands::id_to_pubkey(&id),
+        Command::PubkeyToId { public_key } => commands::pubkey_to_id(&public_key),
+        Command::CheckConfig => with_config(&sources, |config| commands::check_config(&config)),
+        Command::SendTestPacket => with_config(&sources, |config| commands::send_test_packet(&config)),
+        Command::Decode { input } => with_raw_config(&sources, |raw| Ok(commands::decode(ScoringConfig::from_raw(&raw)?, SigningSeed::from_raw(&raw)?, &input))),
+    }
+}
+
+fn with_config(sources: &ConfigSources, command: impl FnOnce(Config) -> ExitCode) -> ExitCode {
+    with_raw_config(sources, |raw| Ok(command(Config::from_raw(&raw)?)))
+}
+
+fn with_raw_config(sources: &ConfigSources, command: impl FnOnce(RawConfig) -> Result<ExitCode, ConfigError>) -> ExitCode {
+    match sources.load().and_then(command) {
+        Ok(exit_code) => exit_code,
+        Err(err) => {
+            log::error!("{err}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn mine(config: Config, sources: ConfigSources) -> ExitCode {
+    let nonce_sources = match new_worker_nonce_sources(config.nonce_source, config.mining_seed, config.number_of_threads) {
+        Ok(nonce_sources) => nonce_sources,
+        Err(err) => {
+            log::error!("Failed to create the nonce source: {err}");
+            return ExitCode::FAILURE;
+        }
+    };
 
     Builder::new_multi_thread()
-        .worker_threads(number_of_threads)
-        .thread_stack_size(stack_size)
+        .worker_threads(NETWORK_THREADS)
         .enable_all()
         .build().unwrap()
-        .block_on(async {
-            async_main().await;
-        });
+        .block_on(async_main(config, nonce_sources, sources))
 }
 
-async fn async_main() {
-
-    // Grab info
-    let number_of_threads = get_number_of_thread();
-    let ip_raw = get_server_ip();
-    let port_raw = get_server_port();
-    let id_raw = get_id();
-    let version = get_version();
-    let random_seed = get_random_seed();
-    let solution_threshold = get_solution_threshold();
+async fn async_main(config: Config, nonce_sources: Vec<Box<dyn NonceSource>>, sources: ConfigSources) -> ExitCode {
+    // RUST_LOG may come from the TOML file as well
+    if let Some(log_filter) = &config.log_filter {
+        logger::set_filter(log_filter);
+    }
 
     // Display info
-    log::info!("Version: {:?}", version);
-    log::info!("Random seed: {:?}", random_seed);
-    log::info!("Solution threshold: {:?}", solution_threshold);
-    log::info!("IP address: {ip_raw}");
-    log::info!("Port: {port_raw}");
-    log::info!("Id: {id_raw}");
+    log::info!("Version: {:?}", config.version);
+    log::info!("Random seed: {:?}", config.random_seed);
+    log::info!("Solution threshold: {:?}", config.solution_threshold);
+    log::info!("Peers: {}", config.peers.join(", "));
+    log::info!("Submit mode: {}", config.submit_mode.as_str());
+    for identity in &config.identities {
+        log::info!("Id: {} (weight {})", identity.id_string(), identity.weight);
+    }
+    if config.identities.len() > 1 {
+        log::info!("Id schedule: {}", config.id_schedule.as_str());
+        if config.id_schedule == IdSchedule::Share && config.number_of_threads < config.identities.len() {
+            log::warn!("{} threads for {} IDs, some IDs get no thread", config.number_of_threads, config.identities.len());
+        }
+    }
     log::info!("Available cores: {}", num_cpus::get());
-    log::info!("Number of threads: {}", number_of_threads);
+    log::info!("Number of threads: {}", config.number_of_threads);
+    log::info!("Nonce source: {}", nonce_sources[0].name());
+    if let Some(mining_seed) = config.mining_seed {
+        log::info!("Deterministic mode, mining seed: {mining_seed}");
+    }
+    if !config.cpu_list.is_empty() {
+        log::info!("CPU list: {:?}", config.cpu_list);
+        if config.number_of_threads > config.cpu_list.len() {
+            log::warn!("{} threads share {} CPUs", config.number_of_threads, config.cpu_list.len());
+        }
 
-    // Convert ID
-    let id: Id = id_raw.as_bytes().try_into().unwrap();
+        let available_cpus = get_available_cpus();
+        if let Some(cpu) = config.cpu_list.iter().find(|cpu| !available_cpus.contains(cpu)) {
+            log::warn!("CPU {cpu} is not available to this process");
+        }
+    }
 
-    // Get Public key
-    let mut public_key: PublicKey64 = Default::default();
-    if get_public_key_64_from_id(&id, &mut public_key) == false {
-        log::error!("The Id is invalid!");
-        return;
+    let signer = commands::get_signer(&config);
+    match &signer {
+        Some(signer) => log::info!("Solutions are signed by {}", get_id_string(&signer.public_key())),
+        None => log::info!("Solutions are sent anonymous, set {} to sign them", lib::env_names::ENV_SIGNING_SEED),
     }
 
-    let arc_miner = Arc::new(Miner::new(public_key, number_of_threads));
-    Miner::run(&arc_miner);
+    let protocol = config.protocol();
 
-    // Display task
-    let sent_score_counter = Arc::new(tokio::sync::Mutex::new(0usize));
+    // Every worker allocates its buffers after pinning
+    let worker_pool = Arc::new(WorkerPool::new(config.huge_pages));
+    log::info!(
+        "Worker buffers: {} MiB per thread{}",
+        worker_pool.state_size() / (1024 * 1024),
+        if worker_pool.is_huge_pages() { " (huge pages)" } else { "" },
+    );
+
+    let arc_miner = Arc::new(Miner::with_identities(&config.identities, config.id_schedule, &config.random_seed, config.solution_threshold));
+
+    let (journal, replayed_solutions) = match open_journal(&config.journal_file, &arc_miner) {
+        Ok((journal, replayed_solutions)) => (Arc::new(journal), replayed_solutions),
+        Err(err) => {
+            log::error!("Failed to open the journal {}: {err}", config.journal_file.display());
+            return ExitCode::FAILURE;
+        }
+    };
 
+    let (solution_sender, solution_receiver) = submission_queue(journal.clone());
+    let mut submitter = Submitter::new(PeerSet::new(&config.peers, config.submit_mode), protocol, journal.clone(), solution_receiver, Box::new(OsSource), signer);
+    submitter.follow_epochs(arc_miner.subscribe_epoch());
+    replayed_solutions.into_iter().for_each(|solution| submitter.enqueue(solution));
+
+    if let Some(metrics_addr) = config.metrics_addr {
+        let listener = match tokio::net::TcpListener::bind(metrics_addr).await {
+            Ok(listener) => listener,
+            Err(err) => {
+                log::error!("Failed to listen for metrics on {metrics_addr}: {err}");
+                return ExitCode::FAILURE;
+            }
+        };
+        let metrics = Metrics::new(arc_miner.clone(), submitter.stats().clone(), submitter.subscribe_peer_status(), worker_pool.clone());
+        log::info!("Metrics: http://{metrics_addr}/metrics");
+        tokio::spawn(serve(listener, Arc::new(metrics)));
+    }
+
+    let workers = Arc::new(Mutex::new(Miner::run(&arc_miner, nonce_sources, &worker_pool, &config.cpu_list, solution_sender)));
+
+    let mut live_config = LiveConfig::new(config, arc_miner.clone(), workers.clone());
+    submitter.follow_peers(live_config.subscribe_peers());
+    tokio::spawn(watch_config(sources, RELOAD_INTERVAL, move |raw| live_config.apply(&raw)));
+
+    // Display task
     let arc_miner_clone = arc_miner.clone();
-    let sent_score_counter_clone = sent_score_counter.clone();
+    let submission_stats = submitter.stats().clone();
+    let peer_status = submitter.subscribe_peer_status();
     let display_info_future = async move {
         let mut prev_iter_value: usize = 0;
+        let started = Instant::now();
 
         loop {
-            log::info!("{} scores | sent scores {} | {} it/s", arc_miner_clone.get_score(), sent_score_counter_clone.lock().await, arc_miner_clone.get_iter_counter() - prev_iter_value);
+            let peers = peer_status.borrow().iter().map(|peer| format!(
+                "{} {}/{}/{}{}",
+                peer.addr,
+                peer.stats.sent.load(Ordering::Relaxed),
+                peer.stats.failures.load(Ordering::Relaxed),
+                peer.received.frames.load(Ordering::Relaxed),
+                if peer.stats.is_down.load(Ordering::Relaxed) { " down" } else { "" },
+            )).collect::<Vec<String>>();
+
+            let identity_stats = arc_miner_clone.get_identity_stats();
+            let identities = if identity_stats.len() > 1 {
+                let scores = identity_stats.iter().map(|stats| stats.solutions.load(Ordering::Relaxed).to_string()).collect::<Vec<String>>();
+                format!(" | scores by ID {}", scores.join("/"))
+            } else {
+                String::new()
+            };
+
+            // The ETA follows the average rate, the one of the last second is too noisy for it
+            let solution_threshold = arc_miner_clone.get_solution_threshold();
+            let iterations_per_second = arc_miner_clone.get_iter_counter() as f64 / started.elapsed().as_secs_f64();
+            let estimate = match arc_miner_clone.get_score_histogram().distribution() {
+                Some(distribution) => format!(
+                    " | best {}/{solution_threshold} | ETA {}",
+                    distribution.best_score(),
+                    distribution.expected_time_to_solution(solution_threshold, iterations_per_second).map_or("unknown".to_string(), format_duration),
+                ),
+                None => String::new(),
+            };
+
+            log::info!(
+                "{} scores{identities}{estimate} | sent scores {} | pending {} | {} it/s | peers sent/failed/received: {}",
+                arc_miner_clone.get_score(),
+                submission_stats.sent.load(Ordering::Relaxed),
+                submission_stats.pending.load(Ordering::Relaxed),
+                arc_miner_clone.get_iter_counter() - prev_iter_value,
+                peers.join(", "),
+            );
             prev_iter_value = arc_miner_clone.get_iter_counter();
 
             tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
         }
     };
 
-    // TCP client task
-    let arc_miner_clone = arc_miner.clone();
-    let sent_score_counter_clone = sent_score_counter.clone();
-    let send_solution_future = async move {
-        loop {
-            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-            
-            let is_nonce_exists;
-            {
-                is_nonce_exists = !arc_miner_clone.found_nonce.lock().await.is_empty();
-            }
+    tokio::select!(
+        _ = display_info_future => {}
+        _ = submitter.run() => log::error!("Every mining thread has stopped"),
+        signal = wait_for_shutdown_signal() => log::info!("Received {signal}, stopping the workers"),
+    );
 
-            if is_nonce_exists {
-                let addr = format!("{ip_raw}:{port_raw}");
-
-                log::info!("Connecting to {addr}");
-                let mut stream_result = TcpStream::connect(addr).await;
-
-                match stream_result.as_mut() {
-                    Err(err) => {
-                        log::error!("Failed to connect: {:?}", err);
-                    }
-                    Ok(stream) => {
-                        // Wait for the socket to be writable
-                        if let Err(err) = stream.writable().await {
-                            log::error!("Writable: {:?}", err);
-                        } else {
-                            // Grab data
-                            let data_for_send;
-                            {
-                                let found_nonce = arc_miner_clone.found_nonce.lock().await;
-                                data_for_send = found_nonce.iter().map(|nonce| {
-                                    let packet = Packet::new(&BROADCAST_MESSAGE, &public_key, &nonce);
-                                    unsafe { transmute::<Packet, [u8; size_of::<Packet>()]>(packet) }
-                                }).collect::<Vec<[u8; size_of::<Packet>()]>>().into_iter().flatten().collect::<Vec<u8>>();
-                            }
-
-                            let packet_num = data_for_send.len() / size_of::<Packet>();
-                            log::info!("TCP: will be sent {packet_num} packets({} Bytes)", data_for_send.len());
-
-                            // Send data
-                            log::info!("TCP: send data...");
-                            let write_result = stream.write_all(data_for_send.as_slice()).await;
-                            if let Err(err) = write_result {
-                                log::error!("Failed to send data: {:?}", err);
-                            } else {
-                                let mut lock = sent_score_counter_clone.lock().await;
-                                *lock += packet_num;
-                            }
-
-                            // Deleting nonce that have been sent
-                            arc_miner_clone.found_nonce.lock().await.drain(0..packet_num);
-                        }
-                    }
-                }
-            }
+    // A second signal skips the flushing
+    tokio::spawn(async {
+        let signal = wait_for_shutdown_signal().await;
+        log::warn!("Received {signal} again, exiting without flushing the solutions");
+        std::process::exit(1);
+    });
 
-            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
+    // A worker may be blocked on a full channel, which nothing empties once run() is left behind
+    arc_miner.stop();
+    let joining_workers = tokio::task::spawn_blocking(move || workers.lock().unwrap().join());
+    let panicked_workers = submitter.drain_until(joining_workers).await.unwrap();
+    if panicked_workers > 0 {
+        log::error!("{panicked_workers} mining threads panicked");
+    }
+
+    let unsent_solutions = submitter.flush(SHUTDOWN_SEND_TIMEOUT).await;
+    if unsent_solutions == 0 {
+        log::info!("Sent {} solutions, no solution is pending", submitter.stats().sent.load(Ordering::Relaxed));
+        return ExitCode::SUCCESS;
+    }
+
+    // They are in the journal already, recording them again covers a failed write when they were found
+    let mut exit_code = ExitCode::from(EXIT_UNSENT_SOLUTIONS);
+    for solution in submitter.pending_solutions() {
+        if let Err(err) = journal.record(SolutionState::Found, solution) {
+            log::error!("Failed to write the unsent solution {} to the journal: {err}", get_hex_from_64(&solution.nonce));
+            exit_code = ExitCode::FAILURE;
         }
-    };
+    }
+    log::warn!("{unsent_solutions} solutions were not sent, they stay in {} and are sent on the next start", journal.path().display());
+
+    exit_code
+}
+
+/// Opens the journal and returns the solutions of the current random seed and IDs, which were never sent
+fn open_journal(path: &Path, miner: &Miner) -> io::Result<(Journal, Vec<Solution>)> {
+    let entries = Journal::replay(path)?;
+    let (pending, stale): (Vec<_>, Vec<_>) = entries.into_iter()
+        .filter(|entry| entry.state == SolutionState::Found)
+        .partition(|entry| entry.random_seed == miner.get_random_seed() && miner.get_public_keys().contains(&entry.public_key));
+
+    if !stale.is_empty() {
+        log::warn!("{} unsent solutions in the journal belong to another random seed or ID, skipping them", stale.len());
+    }
+    if !pending.is_empty() {
+        log::info!("Replaying {} unsent solutions from the journal", pending.len());
+    }
+
+    Ok((Journal::open(path)?, pending.iter().map(JournalEntry::solution).collect()))
+}
+
+/// Resolves on SIGINT or SIGTERM with the name of the signal
+async fn wait_for_shutdown_signal() -> &'static str {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
 
-    tokio::join!(
-		display_info_future,
-		send_solution_future
-	);
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => tokio::select!(
+                _ = tokio::signal::ctrl_c() => "SIGINT",
+                _ = sigterm.recv() => "SIGTERM",
+            ),
+            Err(err) => {
+                log::error!("Failed to listen for SIGTERM: {err}");
+                let _ = tokio::signal::ctrl_c().await;
+                "SIGINT"
+            }
+        }
+    }
 
-    println!("End");
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+        "Ctrl-C"
+    }
 }
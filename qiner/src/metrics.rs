@@ -0,0 +1,357 @@
+use std::fmt::Write as _;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::Ordering;
+use std::time::{Duration, Instant};
+use lib::types::MAX_SCORE;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+use crate::converters::get_id_string;
+use crate::miner::Miner;
+use crate::network::connection::ConnectionState;
+use crate::submission::{PeerStatus, SubmissionStats};
+use crate::worker_state::WorkerPool;
+
+/// How often the iteration rate is sampled
+pub const SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A request head longer than this is refused
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+
+/// Time for a client to send its request
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Name, help and value of a counter kept for every peer
+type PeerCounter = (&'static str, &'static str, fn(&PeerStatus) -> usize);
+
+#[derive(Debug)]
+struct RateSample {
+	iterations: usize,
+	at: Instant,
+	per_second: f64,
+}
+
+/// Everything exported on `/metrics`, read from the counters of the miner and the submitter
+pub struct Metrics {
+	miner: Arc<Miner>,
+	submission_stats: Arc<SubmissionStats>,
+	peer_status: watch::Receiver<Vec<PeerStatus>>,
+	worker_pool: Arc<WorkerPool>,
+	rate: Mutex<RateSample>,
+}
+
+impl Metrics {
+	pub fn new(miner: Arc<Miner>, submission_stats: Arc<SubmissionStats>, peer_status: watch::Receiver<Vec<PeerStatus>>, worker_pool: Arc<WorkerPool>) -> Self {
+		let rate = RateSample {
+			iterations: miner.get_iter_counter(),
+			at: Instant::now(),
+			per_second: 0.0,
+		};
+
+		Metrics {
+			miner,
+			submission_stats,
+			peer_status,
+			worker_pool,
+			rate: Mutex::new(rate),
+		}
+	}
+
+	/// Updates the iteration rate with the iterations since the last sample
+	pub fn sample(&self) {
+		let iterations = self.miner.get_iter_counter();
+		let now = Instant::now();
+		let mut rate = self.rate.lock().unwrap();
+		let elapsed = now.duration_since(rate.at).as_secs_f64();
+		if elapsed > 0.0 {
+			rate.per_second = iterations.saturating_sub(rate.iterations) as f64 / elapsed;
+			rate.iterations = iterations;
+			rate.at = now;
+		}
+	}
+
+	/// The metrics in the Prometheus text format
+	pub fn render(&self) -> String {
+		let mut out = String::new();
+
+		write_header(&mut out, "qiner_iterations_total", "counter", "Nonces scored by every worker");
+		write_sample(&mut out, "qiner_iterations_total", &[], self.miner.get_iter_counter());
+		write_header(&mut out, "qiner_iterations_per_second", "gauge", "Nonces scored over the last second");
+		write_sample(&mut out, "qiner_iterations_per_second", &[], self.rate.lock().unwrap().per_second);
+		write_header(&mut out, "qiner_workers", "gauge", "Mining threads running");
+		write_sample(&mut out, "qiner_workers", &[], self.miner.get_number_of_workers());
+		write_header(&mut out, "qiner_worker_iterations_total", "counter", "Nonces scored by each worker");
+		for (worker, iterations) in self.miner.get_worker_iterations().into_iter().enumerate() {
+			write_sample(&mut out, "qiner_worker_iterations_total", &[("worker", &worker.to_string())], iterations);
+		}
+
+		self.render_score_histogram(&mut out);
+		let solution_threshold = self.miner.get_solution_threshold();
+		write_header(&mut out, "qiner_solution_threshold", "gauge", "Lowest score of a solution");
+		write_sample(&mut out, "qiner_solution_threshold", &[], solution_threshold);
+		if let Some(distribution) = self.miner.get_score_histogram().distribution() {
+			write_header(&mut out, "qiner_best_score", "gauge", "Highest score reached");
+			write_sample(&mut out, "qiner_best_score", &[], distribution.best_score());
+			write_header(&mut out, "qiner_solution_probability", "gauge", "Estimated probability of a nonce to reach the solution threshold");
+			write_sample(&mut out, "qiner_solution_probability", &[], distribution.probability_at_least(solution_threshold));
+			let iterations_per_second = self.rate.lock().unwrap().per_second;
+			if let Some(expected_time) = distribution.expected_time_to_solution(solution_threshold, iterations_per_second) {
+				write_header(&mut out, "qiner_expected_seconds_to_solution", "gauge", "Estimated time to the next solution at the current rate");
+				write_sample(&mut out, "qiner_expected_seconds_to_solution", &[], expected_time.as_secs_f64());
+			}
+		}
+
+		let public_keys = self.miner.get_public_keys();
+		let identity_stats = self.miner.get_identity_stats();
+		write_header(&mut out, "qiner_identity_iterations_total", "counter", "Nonces scored for each ID");
+		for (public_key, stats) in public_keys.iter().zip(identity_stats) {
+			write_sample(&mut out, "qiner_identity_iterations_total", &[("id", &get_id_string(public_key))], stats.iterations.load(Ordering::Relaxed));
+		}
+		write_header(&mut out, "qiner_identity_solutions_total", "counter", "Solutions found for each ID");
+		for (public_key, stats) in public_keys.iter().zip(identity_stats) {
+			write_sample(&mut out, "qiner_identity_solutions_total", &[("id", &get_id_string(public_key))], stats.solutions.load(Ordering::Relaxed));
+		}
+
+		let stats = &self.submission_stats;
+		let counters = [
+			("qiner_solutions_found_total", "Solutions found by the workers", self.miner.get_score()),
+			("qiner_solutions_sent_total", "Solutions written to at least one peer", stats.sent.load(Ordering::Relaxed)),
+			("qiner_submission_failures_total", "Failed attempts to send a solution", stats.failed_attempts.load(Ordering::Relaxed)),
+			("qiner_solutions_discarded_total", "Solutions of a previous random seed, dropped unsent", stats.discarded.load(Ordering::Relaxed)),
+			("qiner_solutions_dropped_total", "Solutions no packet could be built for, dropped unsent", stats.dropped.load(Ordering::Relaxed)),
+			("qiner_solutions_acknowledged_total", "Sent solutions a peer relayed back", stats.acknowledged.load(Ordering::Relaxed)),
+		];
+		for (name, help, value) in counters {
+			write_header(&mut out, name, "counter", help);
+			write_sample(&mut out, name, &[], value);
+		}
+		write_header(&mut out, "qiner_solutions_pending", "gauge", "Solutions waiting for the next attempt");
+		write_sample(&mut out, "qiner_solutions_pending", &[], stats.pending.load(Ordering::Relaxed));
+
+		self.render_peers(&mut out);
+
+		write_header(&mut out, "qiner_worker_buffers_bytes", "gauge", "Memory of the neuron buffers");
+		write_sample(&mut out, "qiner_worker_buffers_bytes", &[], self.worker_pool.allocated_size());
+		if let Some(resident_memory) = get_resident_memory() {
+			write_header(&mut out, "process_resident_memory_bytes", "gauge", "Resident memory size in bytes");
+			write_sample(&mut out, "process_resident_memory_bytes", &[], resident_memory);
+		}
+
+		out
+	}
+
+	fn render_score_histogram(&self, out: &mut String) {
+		let counts = self.miner.get_score_histogram().snapshot();
+
+		write_header(out, "qiner_score", "histogram", "Scores of the nonces tried");
+		let mut cumulative = 0;
+		let mut score = 0;
+		for bound in get_score_buckets() {
+			while score <= bound && score < counts.len() {
+				cumulative += counts[score];
+				score += 1;
+			}
+			write_sample(out, "qiner_score_bucket", &[("le", &bound.to_string())], cumulative);
+		}
+		let count = counts.iter().sum::<u64>();
+		let sum = counts.iter().enumerate().map(|(score, count)| score as u64 * count).sum::<u64>();
+		write_sample(out, "qiner_score_bucket", &[("le", "+Inf")], count);
+		write_sample(out, "qiner_score_sum", &[], sum);
+		write_sample(out, "qiner_score_count", &[], count);
+	}
+
+	fn render_peers(&self, out: &mut String) {
+		let peer_status = self.peer_status.borrow();
+
+		write_header(out, "qiner_peer_up", "gauge", "1 when the connection to the peer is established");
+		for peer in peer_status.iter() {
+			let is_up = *peer.connection.borrow() == ConnectionState::Connected;
+			write_sample(out, "qiner_peer_up", &[("peer", &peer.addr)], usize::from(is_up));
+		}
+		write_header(out, "qiner_peer_down", "gauge", "1 while the peer is skipped after failures");
+		for peer in peer_status.iter() {
+			write_sample(out, "qiner_peer_down", &[("peer", &peer.addr)], usize::from(peer.stats.is_down.load(Ordering::Relaxed)));
+		}
+		let counters: [PeerCounter; 3] = [
+			("qiner_peer_solutions_sent_total", "Solutions written to the peer", |peer| peer.stats.sent.load(Ordering::Relaxed)),
+			("qiner_peer_failures_total", "Failed writes to the peer", |peer| peer.stats.failures.load(Ordering::Relaxed)),
+			("qiner_peer_frames_received_total", "Frames read from the peer", |peer| peer.received.frames.load(Ordering::Relaxed)),
+		];
+		for (name, help, get_value) in counters {
+			write_header(out, name, "counter", help);
+			for peer in peer_status.iter() {
+				write_sample(out, name, &[("peer", &peer.addr)], get_value(peer));
+			}
+		}
+	}
+}
+
+/// Upper bounds of the score buckets: 0 and the powers of two up to `MAX_SCORE`
+fn get_score_buckets() -> impl Iterator<Item = usize> {
+	std::iter::once(0).chain((0..).map(|exponent| 1 << exponent).take_while(|bound| *bound <= MAX_SCORE))
+}
+
+fn write_header(out: &mut String, name: &str, kind: &str, help: &str) {
+	let _ = writeln!(out, "# HELP {name} {help}");
+	let _ = writeln!(out, "# TYPE {name} {kind}");
+}
+
+fn write_sample(out: &mut String, name: &str, labels: &[(&str, &str)], value: impl std::fmt::Display) {
+	out.push_str(name);
+	if !labels.is_empty() {
+		let labels = labels.iter().map(|(label, value)| format!("{label}=\"{}\"", escape_label_value(value))).collect::<Vec<String>>();
+		let _ = write!(out, "{{{}}}", labels.join(","));
+	}
+	let _ = writeln!(out, " {value}");
+}
+
+fn escape_label_value(value: &str) -> String {
+	value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+/// Resident set size of the process
+#[cfg(target_os = "linux")]
+fn get_resident_memory() -> Option<usize> {
+	let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+	let resident_pages = statm.split_whitespace().nth(1)?.parse::<usize>().ok()?;
+	let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+
+	usize::try_from(page_size).ok().map(|page_size| resident_pages * page_size)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn get_resident_memory() -> Option<usize> {
+	None
+}
+
+/// Answers `GET /metrics` on `listener` and samples the iteration rate, until the task is dropped
+pub async fn serve(listener: TcpListener, metrics: Arc<Metrics>) {
+	let mut sample_interval = tokio::time::interval(SAMPLE_INTERVAL);
+
+	loop {
+		tokio::select!(
+			_ = sample_interval.tick() => metrics.sample(),
+			accepted = listener.accept() => match accepted {
+				Ok((stream, _)) => {
+					let metrics = metrics.clone();
+					tokio::spawn(async move {
+						if let Err(err) = respond(stream, &metrics).await {
+							log::debug!("Failed to answer a metrics request: {err}");
+						}
+					});
+				}
+				Err(err) => log::warn!("Failed to accept a metrics connection: {err}"),
+			},
+		);
+	}
+}
+
+/// Reads one request and answers it, the connection is closed afterwards
+async fn respond(mut stream: TcpStream, metrics: &Metrics) -> io::Result<()> {
+	let head = tokio::time::timeout(REQUEST_TIMEOUT, read_request_head(&mut stream)).await
+		.map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "the request took too long"))??;
+
+	let mut request_line = head.lines().next().unwrap_or_default().split_whitespace();
+	let method = request_line.next().unwrap_or_default();
+	let path = request_line.next().unwrap_or_default();
+	let path = path.split_once('?').map_or(path, |(path, _)| path);
+	let (status, body) = if method != "GET" {
+		("405 Method Not Allowed", "Only GET is supported\n".to_string())
+	} else if path == "/metrics" {
+		("200 OK", metrics.render())
+	} else {
+		("404 Not Found", "Metrics are on /metrics\n".to_string())
+	};
+
+	let response = format!(
+		"HTTP/1.1 {status}\r\nContent-Type: {CONTENT_TYPE}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+		body.len(),
+	);
+	stream.write_all(response.as_bytes()).await?;
+	stream.shutdown().await
+}
+
+/// The request up to the empty line, the body of a GET is ignored
+async fn read_request_head(stream: &mut TcpStream) -> io::Result<String> {
+	let mut head = Vec::new();
+	let mut buffer = [0; 1024];
+	while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+		if head.len() > MAX_REQUEST_SIZE {
+			return Err(io::Error::new(io::ErrorKind::InvalidData, "the request is too long"));
+		}
+		let read = stream.read(&mut buffer).await?;
+		if read == 0 {
+			return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the request ended early"));
+		}
+		head.extend_from_slice(&buffer[..read]);
+	}
+
+	Ok(String::from_utf8_lossy(&head).into_owned())
+}
+
+#[cfg(test)]
+fn new_test_metrics() -> Metrics {
+	let miner = Arc::new(Miner::new([7; 4], &[0; 32], 22));
+	let (_, peer_status) = watch::channel(Vec::new());
+
+	Metrics::new(miner, Arc::new(SubmissionStats::default()), peer_status, Arc::new(WorkerPool::new(false)))
+}
+
+#[test]
+fn test_render_metrics() {
+	let metrics = new_test_metrics();
+	let histogram = metrics.miner.get_score_histogram();
+	histogram.record(0);
+	histogram.record(3);
+	histogram.record(3);
+	histogram.record(40);
+	metrics.submission_stats.sent.store(2, Ordering::Relaxed);
+
+	let rendered = metrics.render();
+	let lines = rendered.lines().collect::<Vec<&str>>();
+
+	assert!(lines.contains(&"# TYPE qiner_iterations_total counter"));
+	assert!(lines.contains(&"qiner_iterations_total 0"));
+	assert!(lines.contains(&"qiner_solutions_sent_total 2"));
+	assert!(lines.contains(&"qiner_solution_threshold 22"));
+	assert!(lines.contains(&"qiner_score_bucket{le=\"0\"} 1"));
+	assert!(lines.contains(&"qiner_score_bucket{le=\"2\"} 1"));
+	assert!(lines.contains(&"qiner_score_bucket{le=\"4\"} 3"));
+	assert!(lines.contains(&"qiner_score_bucket{le=\"32\"} 3"));
+	assert!(lines.contains(&"qiner_score_bucket{le=\"64\"} 4"));
+	assert!(lines.contains(&"qiner_score_bucket{le=\"+Inf\"} 4"));
+	assert!(lines.contains(&"qiner_score_sum 46"));
+	assert!(lines.contains(&"qiner_score_count 4"));
+	assert!(lines.contains(&"qiner_best_score 40"));
+	assert!(lines.iter().any(|line| line.starts_with("qiner_solution_probability 0.")));
+	assert!(!rendered.contains("qiner_expected_seconds_to_solution"));
+	assert!(lines.iter().any(|line| line.starts_with("qiner_identity_solutions_total{id=\"") && line.ends_with("\"} 0")));
+	assert_eq!("a\\\"b\\\\c\\n", escape_label_value("a\"b\\c\n"));
+}
+
+#[tokio::test]
+async fn test_serve_metrics() {
+	let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+	let addr = listener.local_addr().unwrap();
+	let server = tokio::spawn(serve(listener, Arc::new(new_test_metrics())));
+
+	let get = |path: &'static str| async move {
+		let mut stream = TcpStream::connect(addr).await.unwrap();
+		stream.write_all(format!("GET {path} HTTP/1.1\r\nHost: localhost\r\n\r\n").as_bytes()).await.unwrap();
+		let mut response = String::new();
+		stream.read_to_string(&mut response).await.unwrap();
+		response
+	};
+
+	let response = get("/metrics").await;
+	assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+	assert!(response.contains(&format!("Content-Type: {CONTENT_TYPE}\r\n")));
+	assert!(response.contains("\r\n\r\n# HELP qiner_iterations_total"));
+
+	assert!(get("/").await.starts_with("HTTP/1.1 404 Not Found\r\n"));
+
+	server.abort();
+}
@@ -1,96 +1,187 @@
-﻿use std::arch::x86_64::_rdrand64_step;
-use std::collections::HashMap;
-use std::mem::{size_of, zeroed};
-use std::sync::{Arc};
-use std::sync::atomic::{AtomicUsize, Ordering};
+﻿use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::thread;
-use std::thread::ThreadId;
-use lib::solution_threshold::get_solution_threshold;
-use lib::types::{
-	MiningItemData,
-	MiningData,
-	NeuronLink,
-	NeuronLinks64,
-	NeuronValue,
-	NeuronValues,
-	Nonce64,
-	PublicKey64,
-	Seed,
-	Seed64,
-	MINING_DATA_LENGTH,
-	NEURON_MOD_BITS,
-	NUMBER_OF_NEURONS,
-	NUMBER_OF_NEURONS_64,
-};
+use std::thread::JoinHandle;
+use crate::affinity::{get_numa_node, get_worker_cpu, pin_current_thread};
+use crate::converters::{get_hex_from_64, get_id_string};
+use crate::estimate::ScoreDistribution;
+use crate::entropy::{EntropyError, NonceSource, OsSource};
+use crate::score::{get_mining_data, score_with_buffers, NeuronData};
+use crate::submission::SolutionSender;
+use crate::worker_state::WorkerPool;
+use lib::config::{IdSchedule, Identity};
+use lib::types::{MiningData, Nonce64, PublicKey64, Seed, MAX_SCORE};
+use tokio::sync::watch;
+
+/// A nonce reaching the solution threshold, with the random seed and the ID it was found for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Solution {
+	pub random_seed: Seed,
+	pub public_key: PublicKey64,
+	pub nonce: Nonce64,
+	pub score: usize,
+}
+
+/// What the scores are checked against. A new epoch of the network brings a new random seed
+#[derive(Debug)]
+pub struct Epoch {
+	pub random_seed: Seed,
+	pub solution_threshold: usize,
+	/// Shared with the previous epoch when only the threshold changed
+	mining_data: Arc<MiningData>,
+}
+
+impl Epoch {
+	pub fn new(random_seed: &Seed, solution_threshold: usize) -> Self {
+		Epoch {
+			random_seed: *random_seed,
+			solution_threshold,
+			mining_data: Arc::new(get_mining_data(random_seed)),
+		}
+	}
+}
 
+/// Picks the ID of every nonce of a worker, see [`IdSchedule`]
 #[derive(Debug, Clone)]
-pub struct NeuronContainer {
-	neuron_data: HashMap<ThreadId, NeuronData>,
+pub struct IdentityPicker {
+	weights: Vec<u32>,
+	schedule: IdSchedule,
+	/// Of the smooth weighted round-robin: every ID earns its weight each nonce, the richest one pays a whole round for it
+	credits: Vec<i64>,
 }
 
-impl NeuronContainer {
-	pub fn get_mut_data(&mut self, thread_id: &ThreadId) -> &mut NeuronData {
-		if !self.neuron_data.contains_key(thread_id) {
-			self.neuron_data.insert(thread_id.clone(), NeuronData::default());
+impl IdentityPicker {
+	pub fn new(weights: &[u32], schedule: IdSchedule) -> Self {
+		IdentityPicker {
+			weights: weights.to_vec(),
+			schedule,
+			credits: vec![0; weights.len()],
 		}
+	}
 
-		self.neuron_data.get_mut(thread_id).unwrap()
+	/// Index of the ID for the next nonce of the worker `worker_idx` out of `number_of_workers`
+	pub fn next(&mut self, worker_idx: usize, number_of_workers: usize) -> usize {
+		match self.schedule {
+			IdSchedule::Share => get_worker_identity(&self.weights, worker_idx, number_of_workers),
+			IdSchedule::RoundRobin => {
+				self.credits.iter_mut().zip(&self.weights).for_each(|(credit, weight)| *credit += *weight as i64);
+				// The first one wins a tie
+				let idx = (0..self.credits.len()).rev().max_by_key(|&idx| self.credits[idx]).unwrap();
+				self.credits[idx] -= self.weights.iter().map(|weight| *weight as i64).sum::<i64>();
+
+				idx
+			}
+		}
 	}
 }
 
-#[derive(Debug, Clone)]
-pub struct NeuronData {
-	neuron_links: NeuronLinks64,
-	neuron_values: NeuronValues,
+/// The ID of a worker when the workers are divided by weight: the workers and the weights are laid on the same line,
+/// a worker takes the ID under the middle of its slot
+pub fn get_worker_identity(weights: &[u32], worker_idx: usize, number_of_workers: usize) -> usize {
+	let total_weight = weights.iter().map(|weight| *weight as u64).sum::<u64>();
+	let position = (2 * worker_idx as u64 + 1) * total_weight / (2 * number_of_workers.max(1) as u64);
+
+	let mut end = 0;
+	weights.iter().position(|weight| {
+		end += *weight as u64;
+		position < end
+	}).unwrap_or(weights.len() - 1)
 }
 
-impl Default for NeuronData {
-	fn default() -> Self {
-		NeuronData {
-			neuron_links: [0; NUMBER_OF_NEURONS_64 * 2],
-			neuron_values: [NeuronValue::MAX; NUMBER_OF_NEURONS],
+/// Work done for one ID
+#[derive(Debug, Default)]
+pub struct IdentityStats {
+	pub iterations: AtomicUsize,
+	pub solutions: AtomicUsize,
+}
+
+/// How many nonces reached each score, from 0 to `MAX_SCORE`
+#[derive(Debug)]
+pub struct ScoreHistogram {
+	counts: Box<[AtomicU64]>,
+}
+
+impl ScoreHistogram {
+	pub fn new() -> Self {
+		ScoreHistogram {
+			counts: (0..=MAX_SCORE).map(|_| AtomicU64::new(0)).collect(),
 		}
 	}
+
+	pub fn record(&self, score: usize) {
+		self.counts[score.min(MAX_SCORE)].fetch_add(1, Ordering::Relaxed);
+	}
+
+	/// The counts by score, up to the highest score reached
+	pub fn snapshot(&self) -> Vec<u64> {
+		let mut counts = self.counts.iter().map(|count| count.load(Ordering::Relaxed)).collect::<Vec<u64>>();
+		let len = counts.iter().rposition(|count| *count > 0).map_or(0, |score| score + 1);
+		counts.truncate(len);
+
+		counts
+	}
+
+	/// `None` before the first nonce
+	pub fn distribution(&self) -> Option<ScoreDistribution> {
+		ScoreDistribution::from_counts(self.snapshot())
+	}
 }
 
+impl Default for ScoreHistogram {
+	fn default() -> Self {
+		ScoreHistogram::new()
+	}
+}
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct Miner {
-	solution_threshold: usize,
-	num_tasks: usize,
-
-	mining_data: MiningData,
-	computor_public_key: PublicKey64,
+	epoch: watch::Sender<Arc<Epoch>>,
+	/// Of every ID to mine for
+	public_keys: Vec<PublicKey64>,
+	weights: Vec<u32>,
+	id_schedule: IdSchedule,
+	identity_stats: Vec<IdentityStats>,
+	score_histogram: ScoreHistogram,
+	/// Iterations of every worker which ever ran, by index
+	worker_iterations: Mutex<Vec<Arc<AtomicUsize>>>,
 
 	score_counter: Arc<AtomicUsize>,
 	iter_counter: Arc<AtomicUsize>,
-
-	pub found_nonce: Arc<tokio::sync::Mutex<Vec<Nonce64>>>,
+	stop_flag: Arc<AtomicBool>,
+	/// Workers from this index on stop
+	number_of_workers: AtomicUsize,
 }
 
 impl Miner {
-	pub fn new(computor_public_key: PublicKey64, num_threads: usize) -> Self {
+	/// Mines for one ID
+	pub fn new(computor_public_key: PublicKey64, random_seed: &Seed, solution_threshold: usize) -> Self {
+		Miner::with_public_keys(vec![computor_public_key], vec![1], IdSchedule::Share, random_seed, solution_threshold)
+	}
 
-		// Random Seed
-		let random_seed = Miner::get_random_seed_64();
+	/// Mines for every ID, sharing the worker time by their weights
+	pub fn with_identities(identities: &[Identity], id_schedule: IdSchedule, random_seed: &Seed, solution_threshold: usize) -> Self {
+		let public_keys = identities.iter().map(|identity| identity.public_key).collect();
+		let weights = identities.iter().map(|identity| identity.weight).collect();
 
-		// Zeroed mining data
-		let mut mining_data: MiningData;
-		unsafe {
-			mining_data = zeroed::<MiningData>();
-		}
+		Miner::with_public_keys(public_keys, weights, id_schedule, random_seed, solution_threshold)
+	}
 
-		// Generate Mining data
-		crate::math::random_64(&random_seed, &random_seed, &mut mining_data);
+	fn with_public_keys(public_keys: Vec<PublicKey64>, weights: Vec<u32>, id_schedule: IdSchedule, random_seed: &Seed, solution_threshold: usize) -> Self {
+		assert!(!public_keys.is_empty(), "at least one ID is required");
 
 		Miner {
-			solution_threshold: get_solution_threshold(),
-			num_tasks: num_threads,
-			mining_data,
-			computor_public_key,
+			epoch: watch::Sender::new(Arc::new(Epoch::new(random_seed, solution_threshold))),
+			identity_stats: public_keys.iter().map(|_| IdentityStats::default()).collect(),
+			score_histogram: ScoreHistogram::new(),
+			worker_iterations: Mutex::new(Vec::new()),
+			public_keys,
+			weights,
+			id_schedule,
 			score_counter: Arc::new(AtomicUsize::new(0)),
 			iter_counter: Arc::new(AtomicUsize::new(0)),
-			found_nonce: Arc::new(tokio::sync::Mutex::new(Vec::new())),
+			stop_flag: Arc::new(AtomicBool::new(false)),
+			number_of_workers: AtomicUsize::new(0),
 		}
 	}
 
@@ -102,108 +193,328 @@ impl Miner {
 		self.iter_counter.load(Ordering::SeqCst)
 	}
 
-	fn get_random_seed_64() -> Seed64 {
-		let seed = lib::random_seed::get_random_seed();
+	pub fn get_number_of_workers(&self) -> usize {
+		self.number_of_workers.load(Ordering::SeqCst)
+	}
+
+	pub fn get_solution_threshold(&self) -> usize {
+		self.epoch.borrow().solution_threshold
+	}
 
-		let seed_64: Seed64;
-		unsafe {
-			seed_64 = std::mem::transmute::<Seed, Seed64>(seed);
+	pub fn get_random_seed(&self) -> Seed {
+		self.epoch.borrow().random_seed
+	}
+
+	pub fn get_epoch(&self) -> Arc<Epoch> {
+		self.epoch.borrow().clone()
+	}
+
+	/// Notified on every epoch switch
+	pub fn subscribe_epoch(&self) -> watch::Receiver<Arc<Epoch>> {
+		self.epoch.subscribe()
+	}
+
+	/// Switches the workers to a new random seed or threshold from their next nonce on, `false` when nothing changes.
+	/// The mining data is generated before the switch, and only for a new random seed
+	pub fn update_epoch(&self, random_seed: &Seed, solution_threshold: usize) -> bool {
+		let current = self.get_epoch();
+		if current.random_seed == *random_seed && current.solution_threshold == solution_threshold {
+			return false;
 		}
 
-		seed_64
+		let mining_data = if current.random_seed == *random_seed {
+			current.mining_data.clone()
+		} else {
+			Arc::new(get_mining_data(random_seed))
+		};
+		self.epoch.send_replace(Arc::new(Epoch { random_seed: *random_seed, solution_threshold, mining_data }));
+
+		if current.random_seed != *random_seed {
+			log::info!("Epoch switch: random seed {:?} -> {:?}", current.random_seed, random_seed);
+		}
+		if current.solution_threshold != solution_threshold {
+			log::info!("Epoch switch: solution threshold {} -> {solution_threshold}", current.solution_threshold);
+		}
+
+		true
+	}
+
+	/// The public keys of the IDs, in the order of their indexes
+	pub fn get_public_keys(&self) -> &[PublicKey64] {
+		&self.public_keys
 	}
 
-	pub fn find_solution(&self, nonce: &mut Nonce64, neuron_data: &mut NeuronData) -> bool {
-		nonce.iter_mut().for_each(|item| { unsafe { _rdrand64_step(item) }; });
+	pub fn get_identity_stats(&self) -> &[IdentityStats] {
+		&self.identity_stats
+	}
+
+	/// Scores of every nonce tried
+	pub fn get_score_histogram(&self) -> &ScoreHistogram {
+		&self.score_histogram
+	}
 
-		crate::math::random_64(&self.computor_public_key, nonce, &mut neuron_data.neuron_links);
+	/// Iterations of every worker which ever ran, by index
+	pub fn get_worker_iterations(&self) -> Vec<usize> {
+		self.worker_iterations.lock().unwrap().iter().map(|iterations| iterations.load(Ordering::Relaxed)).collect()
+	}
 
-		for idx in 0..NUMBER_OF_NEURONS_64 {
-			neuron_data.neuron_links[idx] &= NEURON_MOD_BITS;
-			neuron_data.neuron_links[NUMBER_OF_NEURONS_64 + idx] &= NEURON_MOD_BITS;
+	/// The counter of the worker `idx`, it keeps counting when the worker starts again
+	fn get_worker_counter(&self, idx: usize) -> Arc<AtomicUsize> {
+		let mut worker_iterations = self.worker_iterations.lock().unwrap();
+		while worker_iterations.len() <= idx {
+			worker_iterations.push(Arc::new(AtomicUsize::new(0)));
 		}
 
-		let mut limit = MINING_DATA_LENGTH;
-		let mut score: usize = 0;
+		worker_iterations[idx].clone()
+	}
+
+	/// A picker for one worker
+	pub fn new_identity_picker(&self) -> IdentityPicker {
+		IdentityPicker::new(&self.weights, self.id_schedule)
+	}
 
-		loop {
-			let prev_value0 = neuron_data.neuron_values[NUMBER_OF_NEURONS - 1];
-			let prev_value1 = neuron_data.neuron_values[NUMBER_OF_NEURONS - 2];
+	/// Asks the workers to finish their current iteration
+	pub fn stop(&self) {
+		self.stop_flag.store(true, Ordering::SeqCst);
+	}
 
-			for idx in 0..NUMBER_OF_NEURONS_64 {
-				let idx_left = idx * 2; //[j][0]
-				let idx_right = idx * 2 + 1; // [j][1]
-				let value_idx = idx * 2;
+	pub fn is_stopped(&self) -> bool {
+		self.stop_flag.load(Ordering::SeqCst)
+	}
 
-				let nv_l0 = (neuron_data.neuron_links[idx_left] as NeuronLink) as usize; // neuronValues[neuronLinks[j][0]]
-				let nv_r0 = ((neuron_data.neuron_links[idx_left] >> size_of::<NeuronLink>() * 8) as NeuronLink) as usize; // neuronValues[neuronLinks[j][1]]
+	/// Tries a new nonce for the ID `identity` in the current epoch, a solution is returned when it reaches the threshold of that epoch
+	pub fn find_solution(&self, identity: usize, nonce: &mut Nonce64, neuron_data: &mut NeuronData, nonce_source: &mut dyn NonceSource) -> Result<Option<Solution>, EntropyError> {
+		nonce_source.fill(nonce)?;
+
+		let epoch = self.get_epoch();
+		let public_key = self.public_keys[identity];
+		let score = score_with_buffers(&public_key, nonce, &epoch.mining_data, neuron_data);
+		self.score_histogram.record(score);
+		let solution = Solution {
+			random_seed: epoch.random_seed,
+			public_key,
+			nonce: *nonce,
+			score,
+		};
+
+		Ok(Some(solution).filter(|solution| solution.score >= epoch.solution_threshold))
+	}
 
-				let nv_l1 = (neuron_data.neuron_links[idx_right] as NeuronLink) as usize; // neuronValues[neuronLinks[j + 1][0]]
-				let nv_r1 = ((neuron_data.neuron_links[idx_right] >> size_of::<NeuronLink>() * 8) as NeuronLink) as usize; // neuronValues[neuronLinks[j + 1][1]]
+	pub fn compute_score(&self, identity: usize, nonce: &Nonce64, neuron_data: &mut NeuronData) -> usize {
+		score_with_buffers(&self.public_keys[identity], nonce, &self.get_epoch().mining_data, neuron_data)
+	}
 
-				let and_result0 = neuron_data.neuron_values[nv_l0] & neuron_data.neuron_values[nv_r0];
-				let and_result1 = neuron_data.neuron_values[nv_l1] & neuron_data.neuron_values[nv_r1];
-				neuron_data.neuron_values[value_idx] = !(and_result0);
-				neuron_data.neuron_values[value_idx + 1] = !(and_result1);
-			}
+	/// Spawns one mining thread per nonce source, each with its own state from the pool.
+	/// Worker `idx` is pinned to `cpu_list[idx % cpu_list.len()]` when the list is not empty.
+	/// Found solutions go to the submission queue, the threads end after [`Miner::stop`]
+	pub fn run(miner: &Arc<Miner>, nonce_sources: Vec<Box<dyn NonceSource>>, worker_pool: &Arc<WorkerPool>, cpu_list: &[usize], solution_sender: SolutionSender) -> Workers {
+		let number_of_workers = nonce_sources.len();
+		let mut workers = Workers {
+			miner: miner.clone(),
+			worker_pool: worker_pool.clone(),
+			cpu_list: cpu_list.to_vec(),
+			solution_sender,
+			handles: Vec::new(),
+			parked: nonce_sources.into_iter().map(Some).collect(),
+			panicked: 0,
+		};
+		workers.resize(number_of_workers, |_| Ok(Box::new(OsSource))).unwrap();
+
+		workers
+	}
+}
 
-			let current_value0 = neuron_data.neuron_values[NUMBER_OF_NEURONS - 1];
-			let current_value1 = neuron_data.neuron_values[NUMBER_OF_NEURONS - 2];
+/// The mining threads of a [`Miner`], their number can change while mining
+pub struct Workers {
+	miner: Arc<Miner>,
+	worker_pool: Arc<WorkerPool>,
+	cpu_list: Vec<usize>,
+	solution_sender: SolutionSender,
+	/// Worker `idx` at `idx`, each thread gives its nonce source back when it ends
+	handles: Vec<JoinHandle<Box<dyn NonceSource>>>,
+	/// Nonce sources of the stopped workers following the running ones, `None` after a panic
+	parked: VecDeque<Option<Box<dyn NonceSource>>>,
+	panicked: usize,
+}
 
-			let data_of_mining = self.mining_data[score >> 6];
-			let is_bit_set = ((data_of_mining >> (score & 63) as MiningItemData) & 1) as u8;
-			if current_value0 != prev_value0 && current_value1 == prev_value1 {
-				if is_bit_set == 0 {
-					break;
-				}
+impl Workers {
+	pub fn len(&self) -> usize {
+		self.handles.len()
+	}
 
-				score += 1;
-			} else if current_value1 != prev_value1 && current_value0 == prev_value0 {
-				if is_bit_set == 1 {
-					break;
-				}
+	pub fn is_empty(&self) -> bool {
+		self.handles.is_empty()
+	}
 
-				score += 1;
-			} else {
-				limit -= 1;
+	/// Starts or stops workers until `number_of_workers` of them run. A stopped worker finishes its iteration first,
+	/// which blocks the caller, and keeps its nonce stream for when it starts again.
+	/// Workers starting for the first time get their nonce source from `new_nonce_source`
+	pub fn resize(&mut self, number_of_workers: usize, mut new_nonce_source: impl FnMut(usize) -> Result<Box<dyn NonceSource>, EntropyError>) -> Result<(), EntropyError> {
+		self.miner.number_of_workers.store(number_of_workers, Ordering::SeqCst);
 
-				if limit == 0 {
-					break;
-				}
+		while self.handles.len() > number_of_workers {
+			let nonce_source = self.handles.pop().unwrap().join().ok();
+			if nonce_source.is_none() {
+				self.panicked += 1;
 			}
+			self.parked.push_front(nonce_source);
 		}
 
-		score >= self.solution_threshold
-	}
-
-	pub fn run(miner: &Arc<Miner>) {
-		for idx in 0..miner.num_tasks {
-			let arc_miner_clone = miner.clone();
+		while self.handles.len() < number_of_workers {
+			let idx = self.handles.len();
+			let nonce_source = match self.parked.pop_front().flatten() {
+				Some(nonce_source) => nonce_source,
+				None => match new_nonce_source(idx) {
+					Ok(nonce_source) => nonce_source,
+					Err(err) => {
+						self.miner.number_of_workers.store(idx, Ordering::SeqCst);
+						return Err(err);
+					}
+				},
+			};
+			self.handles.push(self.spawn(idx, nonce_source));
+		}
 
-			let idx_clone = idx.clone();
+		Ok(())
+	}
 
-			tokio::spawn(async move {
-				let mut nonce: Nonce64 = Nonce64::default();
-				let mut neuron_data = NeuronData::default();
-				let mut nonce_for_send: Vec<Nonce64> = Vec::new();
+	/// Waits for every worker to end after [`Miner::stop`], returns how many of them panicked since the start
+	pub fn join(&mut self) -> usize {
+		let panicked = self.handles.drain(..).map(JoinHandle::join).filter(Result::is_err).count();
 
-				loop {
-					log::debug!("[{idx_clone}]Find solution in Thread Id ({:?})", thread::current().id());
+		self.panicked + panicked
+	}
 
-					if arc_miner_clone.find_solution(&mut nonce, &mut neuron_data) {
-						arc_miner_clone.score_counter.fetch_add(1, Ordering::Relaxed);
-						nonce_for_send.push(nonce);
+	fn spawn(&self, idx: usize, mut nonce_source: Box<dyn NonceSource>) -> JoinHandle<Box<dyn NonceSource>> {
+		let arc_miner_clone = self.miner.clone();
+		let worker_pool = self.worker_pool.clone();
+		let solution_sender = self.solution_sender.clone();
+		let cpu = get_worker_cpu(&self.cpu_list, idx);
+		let worker_iterations = self.miner.get_worker_counter(idx);
+
+		thread::Builder::new().name(format!("qiner-worker-{idx}")).spawn(move || {
+			if let Some(cpu) = cpu {
+				if pin_current_thread(cpu) {
+					match get_numa_node(cpu) {
+						Some(numa_node) => log::info!("[{idx}]Pinned to CPU {cpu} (NUMA node {numa_node})"),
+						None => log::info!("[{idx}]Pinned to CPU {cpu}"),
 					}
+				} else {
+					log::warn!("[{idx}]Failed to pin to CPU {cpu}");
+				}
+			}
 
-					if !nonce_for_send.is_empty() {
-						if let Ok(lock) = arc_miner_clone.found_nonce.try_lock().as_mut() {
-							lock.append(&mut nonce_for_send);
+			// Acquired after pinning, a state allocated here is first touched on the NUMA node of the worker.
+			// A state given back by a stopped worker is reused as it is, wherever its pages are
+			let mut state = worker_pool.acquire();
+			let mut identity_picker = arc_miner_clone.new_identity_picker();
+			let mut iteration: usize = 0;
+
+			while !arc_miner_clone.is_stopped() && idx < arc_miner_clone.get_number_of_workers() {
+				log::debug!("[{idx}]Find solution in Thread Id ({:?})", thread::current().id());
+
+				let identity = identity_picker.next(idx, arc_miner_clone.get_number_of_workers());
+				match arc_miner_clone.find_solution(identity, &mut state.nonce, &mut state.neuron_data, nonce_source.as_mut()) {
+					Ok(Some(solution)) => {
+						log::info!(
+							"[{idx}]Found solution for {} at iteration {iteration} with score {}: {}",
+							get_id_string(&solution.public_key),
+							solution.score,
+							get_hex_from_64(&solution.nonce),
+						);
+						arc_miner_clone.score_counter.fetch_add(1, Ordering::Relaxed);
+						arc_miner_clone.identity_stats[identity].solutions.fetch_add(1, Ordering::Relaxed);
+						if solution_sender.submit(solution).is_err() {
+							log::error!("[{idx}]The submission queue is closed, the solution stays in the journal");
 						}
 					}
-
-					arc_miner_clone.iter_counter.fetch_add(1, Ordering::Relaxed);
+					Ok(None) => {}
+					Err(err) => {
+						log::error!("[{idx}]Failed to get a nonce from {}: {err}, switching to the OS generator", nonce_source.name());
+						nonce_source = Box::new(OsSource);
+						continue;
+					}
 				}
-			});
-		}
+
+				arc_miner_clone.iter_counter.fetch_add(1, Ordering::Relaxed);
+				arc_miner_clone.identity_stats[identity].iterations.fetch_add(1, Ordering::Relaxed);
+				worker_iterations.fetch_add(1, Ordering::Relaxed);
+				iteration += 1;
+			}
+
+			worker_pool.release(state);
+			log::debug!("[{idx}]Stopped after {iteration} iterations");
+
+			nonce_source
+		}).unwrap()
 	}
 }
+
+#[test]
+fn test_workers_resize() {
+	use crate::entropy::ChaChaSource;
+	use crate::journal::Journal;
+	use crate::test_utils::TestFile;
+	use crate::submission::submission_queue;
+
+	let journal_file = TestFile::new("workers.journal");
+	let journal = Arc::new(Journal::open(journal_file.path()).unwrap());
+	let (solution_sender, _receiver) = submission_queue(journal);
+	// Nothing reaches the threshold
+	let miner = Arc::new(Miner::new(PublicKey64::default(), &Seed::default(), usize::MAX));
+	let worker_pool = Arc::new(WorkerPool::new(false));
+	let nonce_sources = (0..2).map(|idx| Box::new(ChaChaSource::for_worker(1, idx)) as Box<dyn NonceSource>).collect();
+	// Stopped from the start, so joining a worker does not wait for a whole iteration
+	miner.stop();
+
+	let mut workers = Miner::run(&miner, nonce_sources, &worker_pool, &[], solution_sender);
+	assert_eq!(2, workers.len());
+	assert_eq!(2, miner.get_number_of_workers());
+	assert_eq!(vec![0, 0], miner.get_worker_iterations());
+
+	let mut new_sources = Vec::new();
+	workers.resize(1, |idx| { new_sources.push(idx); Ok(Box::new(OsSource)) }).unwrap();
+	assert_eq!(1, workers.len());
+	assert_eq!(1, miner.get_number_of_workers());
+
+	// The stopped worker picks its nonce stream up again, only the new one needs a source
+	workers.resize(3, |idx| { new_sources.push(idx); Ok(Box::new(OsSource)) }).unwrap();
+	assert_eq!(3, workers.len());
+	assert_eq!(vec![2], new_sources);
+	assert_eq!(3, miner.get_worker_iterations().len());
+
+	assert_eq!(0, workers.join());
+	assert!(workers.is_empty());
+}
+
+#[test]
+fn test_identity_picker() {
+	let mut picker = IdentityPicker::new(&[3, 1], IdSchedule::RoundRobin);
+	assert_eq!(vec![0, 0, 1, 0, 0, 0, 1, 0], (0..8).map(|_| picker.next(0, 1)).collect::<Vec<_>>());
+
+	let mut picker = IdentityPicker::new(&[1, 1, 1], IdSchedule::RoundRobin);
+	assert_eq!(vec![0, 1, 2, 0], (0..4).map(|_| picker.next(5, 8)).collect::<Vec<_>>());
+
+	// Divided by weight, a worker sticks to its ID
+	let mut picker = IdentityPicker::new(&[3, 1], IdSchedule::Share);
+	assert_eq!(vec![0, 0, 0, 1], (0..4).map(|idx| picker.next(idx, 4)).collect::<Vec<_>>());
+	assert_eq!(vec![0, 0, 0, 0, 0, 0, 1, 1], (0..8).map(|idx| picker.next(idx, 8)).collect::<Vec<_>>());
+	assert_eq!(0, picker.next(0, 1));
+	assert_eq!(vec![0, 1], (0..2).map(|idx| get_worker_identity(&[1, 1], idx, 2)).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_score_histogram() {
+	let histogram = ScoreHistogram::new();
+	assert!(histogram.snapshot().is_empty());
+
+	histogram.record(0);
+	histogram.record(2);
+	histogram.record(2);
+	histogram.record(usize::MAX);
+	let snapshot = histogram.snapshot();
+
+	assert_eq!(vec![1, 0, 2], snapshot[..3]);
+	assert_eq!(MAX_SCORE + 1, snapshot.len());
+	assert_eq!(1, snapshot[MAX_SCORE]);
+}
@@ -1,13 +1,149 @@
-﻿use std::arch::x86_64::{_rdrand32_step, _rdrand64_step};
-use std::mem::{size_of, transmute, transmute_copy, zeroed};
-use std::ptr;
-use k12::digest::{ExtendableOutputReset, Update};
-use k12::KangarooTwelve;
-use lib::types::network::{Dejavu, Key, KeyAndNonce, Protocol, Size, Type};
-use lib::types::{Gamma, Nonce, Nonce64, NUMBER_OF_NONCE, NUMBER_OF_NONCE_64, PublicKey64, Signature};
-use lib::version::get_version;
-
-#[derive(Default, Debug, Clone, Copy)]
+﻿pub mod capture;
+pub mod connection;
+pub mod frame;
+
+use std::mem::size_of;
+use crate::entropy::{EntropyError, NonceSource};
+use crate::signing::{k12, get_digest, get_signature_64, get_signature_bytes, verify, Digest, KeyPair};
+use std::fmt;
+use std::fmt::Formatter;
+use lib::types::network::{Dejavu, Key, KeyAndNonce, Protocol, Size, Type, HEADER_SIZE};
+use lib::types::{Gamma, Nonce, Nonce64, PublicKey64, Signature};
+
+// The wire format is Qubic's: the structures are written field after field without padding,
+// every integer in little-endian, whatever the host is
+
+/// Largest size the 24-bit size field of a header holds
+pub const MAX_MESSAGE_SIZE: usize = (1 << 24) - 1;
+
+fn encode_64(values: &[u64], buffer: &mut Vec<u8>) {
+	values.iter().for_each(|value| buffer.extend_from_slice(&value.to_le_bytes()));
+}
+
+/// `bytes` holds exactly `S` items
+fn decode_64<const S: usize>(bytes: &[u8]) -> [u64; S] {
+	let mut values = [0u64; S];
+	values.iter_mut().zip(bytes.chunks_exact(size_of::<u64>())).for_each(|(value, chunk)| *value = u64::from_le_bytes(chunk.try_into().unwrap()));
+
+	values
+}
+
+fn get_nonce_bytes(nonce: &Nonce64) -> Nonce {
+	let mut bytes = Nonce::default();
+	bytes.chunks_exact_mut(size_of::<u64>()).zip(nonce).for_each(|(chunk, value)| chunk.copy_from_slice(&value.to_le_bytes()));
+
+	bytes
+}
+
+/// Takes `size` Bytes from the front of `bytes`
+fn split_bytes<'a>(bytes: &mut &'a [u8], size: usize) -> Result<&'a [u8], DecodeError> {
+	if bytes.len() < size {
+		return Err(DecodeError::TooShort { expected: size, found: bytes.len() });
+	}
+
+	let (head, tail) = bytes.split_at(size);
+	*bytes = tail;
+
+	Ok(head)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+	/// Fewer bytes than the structure takes
+	TooShort { expected: usize, found: usize },
+	/// A header whose size cannot even hold the header itself
+	InvalidSize(usize),
+}
+
+impl fmt::Display for DecodeError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self {
+			DecodeError::TooShort { expected, found } => write!(f, "expected {expected} Bytes, found {found}"),
+			DecodeError::InvalidSize(size) => write!(f, "invalid message size {size}, the header alone takes {HEADER_SIZE} Bytes"),
+		}
+	}
+}
+
+impl std::error::Error for DecodeError {}
+
+#[derive(Debug)]
+pub enum PacketError {
+	Entropy(EntropyError),
+	/// The computor public key is no point of the curve, there is no key to agree on with it
+	NoSharedKey,
+}
+
+impl fmt::Display for PacketError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self {
+			PacketError::Entropy(err) => err.fmt(f),
+			PacketError::NoSharedKey => write!(f, "no shared key can be agreed with the computor public key"),
+		}
+	}
+}
+
+impl std::error::Error for PacketError {}
+
+impl From<EntropyError> for PacketError {
+	fn from(err: EntropyError) -> Self {
+		PacketError::Entropy(err)
+	}
+}
+
+/// Where the shared key, which the gamming key is derived from, comes from
+pub trait SharedKeyDerivation {
+	/// `None` when no key can be agreed with the destination
+	fn get_shared_key(&self, destination_public_key: &PublicKey64) -> Option<Key>;
+}
+
+/// The zero key of anonymous messages, any node can decrypt them
+pub struct AnonymousKey;
+
+impl SharedKeyDerivation for AnonymousKey {
+	fn get_shared_key(&self, _destination_public_key: &PublicKey64) -> Option<Key> {
+		Some(Key::default())
+	}
+}
+
+/// ECDH of the source identity with the destination
+impl SharedKeyDerivation for KeyPair {
+	fn get_shared_key(&self, destination_public_key: &PublicKey64) -> Option<Key> {
+		KeyPair::get_shared_key(self, destination_public_key)
+	}
+}
+
+/// K12 of the shared key followed by the gamming nonce
+pub fn get_gamming_key(shared_key: &Key, gamming_nonce: &Nonce64) -> Key {
+	let mut shared_key_and_gamming_nonce: KeyAndNonce = [0u8; size_of::<KeyAndNonce>()];
+	shared_key_and_gamming_nonce[..shared_key.len()].copy_from_slice(shared_key);
+	shared_key_and_gamming_nonce[shared_key.len()..].copy_from_slice(&get_nonce_bytes(gamming_nonce));
+
+	let mut gamming_key = Key::default();
+	k12(&shared_key_and_gamming_nonce, &mut gamming_key);
+
+	gamming_key
+}
+
+/// XOR of the nonce with the gamma, the K12 stream of the gamming key
+fn apply_gamma(gamming_key: &Key, nonce: &Nonce64) -> Nonce64 {
+	let mut gamma: Gamma = Gamma::default();
+	k12(gamming_key, &mut gamma);
+
+	let mut nonce_bytes = get_nonce_bytes(nonce);
+	nonce_bytes.iter_mut().zip(gamma.iter()).for_each(|(nonce_value, gamma_value)| *nonce_value ^= gamma_value);
+
+	decode_64(&nonce_bytes)
+}
+
+pub fn encrypt_solution_nonce(gamming_key: &Key, nonce: &Nonce64) -> Nonce64 {
+	apply_gamma(gamming_key, nonce)
+}
+
+pub fn decrypt_solution_nonce(gamming_key: &Key, solution_nonce: &Nonce64) -> Nonce64 {
+	apply_gamma(gamming_key, solution_nonce)
+}
+
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
 pub struct RequestResponseHeader {
 	size: Size,
 	protocol: Protocol,
@@ -16,35 +152,66 @@ pub struct RequestResponseHeader {
 }
 
 impl RequestResponseHeader {
-	pub fn new(in_type: &Type, in_size: &usize) -> Self {
+	pub fn new(in_type: &Type, in_size: &usize, in_protocol: &Protocol) -> Self {
 		let mut header: RequestResponseHeader = Default::default();
 		header.set_size(in_size);
-		header.set_protocol();
+		header.set_protocol(in_protocol);
 		header.zeroed_dejavi();
 		header.set_type(in_type);
 
 		header
 	}
 
-	pub fn get_size(&self) -> usize {
-		unsafe {
-			ptr::read_unaligned(&self.size as *const Size as *const usize)
+	pub const SIZE: usize = HEADER_SIZE;
+
+	/// Writes a 24-bit size, which counts the header too, the protocol, the dejavu and the type
+	pub fn encode(&self, buffer: &mut Vec<u8>) {
+		buffer.extend_from_slice(&self.size);
+		buffer.push(self.protocol);
+		buffer.extend_from_slice(&self.dejavu);
+		buffer.push(self.r#type);
+	}
+
+	/// Reads the header from the start of `bytes`, the rest is left to the caller
+	pub fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+		let Some(bytes) = bytes.get(..HEADER_SIZE) else {
+			return Err(DecodeError::TooShort { expected: HEADER_SIZE, found: bytes.len() });
+		};
+
+		let header = RequestResponseHeader {
+			size: [bytes[0], bytes[1], bytes[2]],
+			protocol: bytes[3],
+			dejavu: [bytes[4], bytes[5], bytes[6]],
+			r#type: bytes[7],
+		};
+		if header.get_size() < HEADER_SIZE {
+			return Err(DecodeError::InvalidSize(header.get_size()));
 		}
+
+		Ok(header)
+	}
+
+	pub fn get_size(&self) -> usize {
+		u32::from_le_bytes([self.size[0], self.size[1], self.size[2], 0]) as usize
 	}
 
 	pub fn set_size(&mut self, new_size: &usize) {
-		unsafe {
-			self.size = transmute_copy::<usize, Size>(new_size);
-		}
+		assert!(*new_size <= MAX_MESSAGE_SIZE, "a message takes at most {MAX_MESSAGE_SIZE} Bytes");
+
+		let bytes = (*new_size as u32).to_le_bytes();
+		self.size = [bytes[0], bytes[1], bytes[2]];
 	}
 
 	pub fn get_protocol(&self) -> Protocol {
 		self.protocol
 	}
 
-	pub fn set_protocol(&mut self) {
-		let version = get_version();
-		self.protocol = version[1];
+	pub fn set_protocol(&mut self, new_protocol: &Protocol) {
+		self.protocol = *new_protocol;
+	}
+
+	pub fn get_dejavu(&self) -> Dejavu {
+		self.dejavu
 	}
 
 	pub fn is_dejavu_zero(&self) -> bool {
@@ -52,20 +219,14 @@ impl RequestResponseHeader {
 	}
 
 	pub fn zeroed_dejavi(&mut self) {
-		unsafe {
-			self.dejavu = zeroed::<Dejavu>();
-		}
+		self.dejavu = Dejavu::default();
 	}
 
-	pub fn randomize_dejavu(&mut self) {
-		assert!(size_of::<Dejavu>() <= size_of::<u32>());
+	pub fn randomize_dejavu(&mut self, entropy: &mut dyn NonceSource) -> Result<(), EntropyError> {
+		let random = entropy.next_u64()?.to_le_bytes();
+		self.dejavu.copy_from_slice(&random[..size_of::<Dejavu>()]);
 
-		let mut random: u32 = 0;
-		unsafe { _rdrand32_step(&mut random) };
-
-		unsafe {
-			self.dejavu = transmute_copy::<u32, Dejavu>(&random);
-		}
+		Ok(())
 	}
 
 	pub fn get_type(&self) -> Type {
@@ -77,7 +238,7 @@ impl RequestResponseHeader {
 	}
 }
 
-#[derive(Default, Debug, Copy, Clone)]
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Message {
 	source_public_key: PublicKey64,
 	destination_public_key: PublicKey64,
@@ -85,12 +246,40 @@ pub struct Message {
 }
 
 impl Message {
+	pub const SIZE: usize = 2 * size_of::<PublicKey64>() + size_of::<Nonce64>();
+
+	pub fn encode(&self, buffer: &mut Vec<u8>) {
+		encode_64(&self.source_public_key, buffer);
+		encode_64(&self.destination_public_key, buffer);
+		encode_64(&self.gamming_nonce, buffer);
+	}
+
+	pub fn decode(mut bytes: &[u8]) -> Result<Self, DecodeError> {
+		if bytes.len() < Message::SIZE {
+			return Err(DecodeError::TooShort { expected: Message::SIZE, found: bytes.len() });
+		}
+
+		Ok(Message {
+			source_public_key: decode_64(split_bytes(&mut bytes, size_of::<PublicKey64>())?),
+			destination_public_key: decode_64(split_bytes(&mut bytes, size_of::<PublicKey64>())?),
+			gamming_nonce: decode_64(split_bytes(&mut bytes, size_of::<Nonce64>())?),
+		})
+	}
+
+	pub fn get_source_public_key(&self) -> PublicKey64 {
+		self.source_public_key
+	}
+
+	pub fn get_destination_public_key(&self) -> PublicKey64 {
+		self.destination_public_key
+	}
+
 	pub fn get_gamming_nonce(&self) -> Nonce64 {
 		self.gamming_nonce
 	}
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Packet {
 	header: RequestResponseHeader,
 	message: Message,
@@ -99,90 +288,257 @@ pub struct Packet {
 }
 
 impl Packet {
-	pub fn new(r#type: &Type, computor_public_key: &PublicKey64, in_nonce: &Nonce64) -> Self {
+	/// Without a signer the source is the zero public key, the shared key zero and the signature random bytes.
+	/// With one, the shared key is agreed with the computor, so only that computor can decrypt the nonce
+	pub fn new(r#type: &Type, protocol: &Protocol, computor_public_key: &PublicKey64, in_nonce: &Nonce64, signer: Option<&KeyPair>, entropy: &mut dyn NonceSource) -> Result<Self, PacketError> {
 		//*****************************
 		// Header
 		//*****************************
 
-		let header: RequestResponseHeader = RequestResponseHeader::new(r#type, &size_of::<Packet>());
+		let header: RequestResponseHeader = RequestResponseHeader::new(r#type, &Packet::SIZE, protocol);
 
 		//*****************************
 		// Message
 		//*****************************
 
-		let mut message = Message::default();
-		message.source_public_key = PublicKey64::default();
-		message.destination_public_key = *computor_public_key;
+		let mut message = Message {
+			source_public_key: signer.map(KeyPair::public_key).unwrap_or_default(),
+			destination_public_key: *computor_public_key,
+			..Default::default()
+		};
 
-		let mut kangaroo_twelve = KangarooTwelve::default();
+		let shared_key = match signer {
+			Some(signer) => signer as &dyn SharedKeyDerivation,
+			None => &AnonymousKey,
+		}.get_shared_key(computor_public_key).ok_or(PacketError::NoSharedKey)?;
 
-		let mut shared_key_and_gamming_nonce: KeyAndNonce = unsafe { zeroed::<KeyAndNonce>() };
-		let mut gamming_key: Key = Key::default();
-		let mut nonce_buffer: Nonce = Nonce::default();
-
-		let nonce_chunk_size = NUMBER_OF_NONCE / NUMBER_OF_NONCE_64;
+		// The computor reads a message as a solution when the first Byte of the gamming key is zero
+		let mut gamming_key: Key;
 		loop {
-			nonce_buffer.chunks_mut(nonce_chunk_size).for_each(|items| {
-				let item_64 = items.as_mut_ptr() as *mut u64;
-				unsafe {
-					_rdrand64_step(item_64.as_mut().unwrap());
-				}
-			});
-
-			shared_key_and_gamming_nonce[(gamming_key.len())..].copy_from_slice(nonce_buffer.as_slice());
+			entropy.fill(&mut message.gamming_nonce)?;
+			gamming_key = get_gamming_key(&shared_key, &message.gamming_nonce);
 
-			kangaroo_twelve.update(shared_key_and_gamming_nonce.as_slice());
-			kangaroo_twelve.finalize_xof_reset_into(gamming_key.as_mut());
-
-			if (gamming_key[0]) == 0 {
+			if gamming_key[0] == 0 {
 				break;
 			}
 		}
-		message.gamming_nonce = unsafe { transmute::<Nonce, Nonce64>(nonce_buffer) };
 
 		//*****************************
 		// Solution nonce
 		//*****************************
 
-		// Get Gamma
-		let mut gamma: Gamma = Gamma::default();
-		kangaroo_twelve.update(gamming_key.as_slice());
-		kangaroo_twelve.finalize_xof_reset_into(gamma.as_mut_slice());
-
-		// Make solution nonce 
-		let nonce_u8_ptr = in_nonce.as_ptr() as *const Nonce;
-		unsafe {
-			nonce_buffer.iter_mut().zip(nonce_u8_ptr.read().iter()).zip(gamma.as_slice()).for_each(|((nonce_buffer_value, in_nonce_value), gamma_value)| {
-				*nonce_buffer_value = *in_nonce_value ^ *gamma_value;
-			});
-		}
-		let solution_nonce = unsafe { transmute::<Nonce, Nonce64>(nonce_buffer) };
-
-		//*****************************
-		// Signature
-		//*****************************
-		let signature = Packet::get_random_signature();
+		let solution_nonce = encrypt_solution_nonce(&gamming_key, in_nonce);
 
 		//*****************************
 		// Packet
 		//*****************************
 
-		Packet {
+		let mut packet = Packet {
 			header,
 			message,
 			solution_nonce,
-			signature,
+			signature: Signature::default(),
+		};
+
+		//*****************************
+		// Signature
+		//*****************************
+		packet.signature = match signer {
+			Some(signer) => get_signature_64(&signer.sign(&packet.get_digest())),
+			None => Packet::get_random_signature(entropy)?,
+		};
+
+		Ok(packet)
+	}
+
+	pub const SIZE: usize = RequestResponseHeader::SIZE + Message::SIZE + size_of::<Nonce64>() + size_of::<Signature>();
+
+	pub fn encode(&self, buffer: &mut Vec<u8>) {
+		self.header.encode(buffer);
+		self.message.encode(buffer);
+		encode_64(&self.solution_nonce, buffer);
+		encode_64(&self.signature, buffer);
+	}
+
+	pub fn to_bytes(&self) -> Vec<u8> {
+		let mut buffer = Vec::with_capacity(Packet::SIZE);
+		self.encode(&mut buffer);
+
+		buffer
+	}
+
+	/// Reads a whole packet, a size in the header other than [`Packet::SIZE`] is an error
+	pub fn decode(mut bytes: &[u8]) -> Result<Self, DecodeError> {
+		let header = RequestResponseHeader::decode(split_bytes(&mut bytes, RequestResponseHeader::SIZE)?)?;
+		if header.get_size() != Packet::SIZE {
+			return Err(DecodeError::InvalidSize(header.get_size()));
 		}
+
+		Ok(Packet {
+			header,
+			message: Message::decode(split_bytes(&mut bytes, Message::SIZE)?)?,
+			solution_nonce: decode_64(split_bytes(&mut bytes, size_of::<Nonce64>())?),
+			signature: decode_64(split_bytes(&mut bytes, size_of::<Signature>())?),
+		})
+	}
+
+	pub fn get_header(&self) -> &RequestResponseHeader {
+		&self.header
+	}
+
+	pub fn get_message(&self) -> &Message {
+		&self.message
+	}
+
+	pub fn get_solution_nonce(&self) -> Nonce64 {
+		self.solution_nonce
+	}
+
+	pub fn get_signature(&self) -> Signature {
+		self.signature
 	}
 
-	pub fn get_random_signature() -> Signature {
+	/// K12 of the message and the solution nonce, which the signature covers like for every Qubic message
+	pub fn get_digest(&self) -> Digest {
+		let mut signed_data = Vec::with_capacity(Message::SIZE + size_of::<Nonce64>());
+		self.message.encode(&mut signed_data);
+		encode_64(&self.solution_nonce, &mut signed_data);
+
+		get_digest(&signed_data)
+	}
+
+	/// Whether the source public key signed the packet, never for an anonymous one
+	pub fn is_signature_valid(&self) -> bool {
+		verify(&self.message.source_public_key, &self.get_digest(), &get_signature_bytes(&self.signature))
+	}
+
+	pub fn get_random_signature(entropy: &mut dyn NonceSource) -> Result<Signature, EntropyError> {
 		let mut signature = Signature::default();
-		signature.iter_mut().for_each(|item: &mut u64| {
-			unsafe {
-				_rdrand64_step(item);
-			}
-		});
+		entropy.fill(&mut signature)?;
 
-		signature
+		Ok(signature)
 	}
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+fn test_packet() -> Packet {
+	Packet {
+		header: RequestResponseHeader::new(&1, &Packet::SIZE, &142),
+		message: Message {
+			source_public_key: [0x0807060504030201, 0, 0, 0],
+			destination_public_key: [0, 0, 0, 0x1122334455667788],
+			gamming_nonce: [1, 2, 3, 4],
+		},
+		solution_nonce: [u64::MAX, 0, 0x0100, 0],
+		signature: [5, 0, 0, 0, 0, 0, 0, 6],
+	}
+}
+
+#[test]
+fn test_header_wire_format() {
+	let mut header = RequestResponseHeader::new(&24, &0x0a0b0c, &7);
+	header.dejavu = [1, 2, 3];
+
+	let mut bytes = Vec::new();
+	header.encode(&mut bytes);
+	assert_eq!(vec![0x0c, 0x0b, 0x0a, 7, 1, 2, 3, 24], bytes);
+	assert_eq!(Ok(header), RequestResponseHeader::decode(&bytes));
+	assert_eq!(0x0a0b0c, header.get_size());
+
+	assert_eq!(Err(DecodeError::TooShort { expected: 8, found: 7 }), RequestResponseHeader::decode(&bytes[..7]));
+	assert_eq!(Err(DecodeError::InvalidSize(7)), RequestResponseHeader::decode(&[7, 0, 0, 0, 0, 0, 0, 0]));
+}
+
+#[test]
+fn test_packet_wire_format() {
+	let packet = test_packet();
+	let bytes = packet.to_bytes();
+	assert_eq!(Packet::SIZE, bytes.len());
+	assert_eq!(200, Packet::SIZE);
+
+	// Header: the size 200, the protocol, a zero dejavu and the type
+	assert_eq!([200, 0, 0, 142, 0, 0, 0, 1], bytes[..8]);
+	// Message: source and destination public keys, gamming nonce
+	assert_eq!([1, 2, 3, 4, 5, 6, 7, 8], bytes[8..16]);
+	assert_eq!([0; 24], bytes[16..40]);
+	assert_eq!([0; 24], bytes[40..64]);
+	assert_eq!([0x88, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11], bytes[64..72]);
+	assert_eq!([1, 0, 0, 0, 0, 0, 0, 0, 2], bytes[72..81]);
+	assert_eq!([4, 0, 0, 0, 0, 0, 0, 0], bytes[96..104]);
+	// Solution nonce
+	assert_eq!([0xff; 8], bytes[104..112]);
+	assert_eq!([0; 8], bytes[112..120]);
+	assert_eq!([0, 1, 0, 0, 0, 0, 0, 0], bytes[120..128]);
+	// Signature
+	assert_eq!(5, bytes[136]);
+	assert_eq!(6, bytes[192]);
+	assert_eq!([0; 7], bytes[193..]);
+
+	assert_eq!(Ok(packet), Packet::decode(&bytes));
+	assert_eq!(Err(DecodeError::TooShort { expected: 64, found: 63 }), Packet::decode(&bytes[..199]));
+
+	let mut other_size = bytes.clone();
+	other_size[0] = 100;
+	assert_eq!(Err(DecodeError::InvalidSize(100)), Packet::decode(&other_size));
+}
+
+#[test]
+fn test_new_packet_round_trip() {
+	let mut entropy = crate::entropy::ChaChaSource::from_seed([3; 32]);
+	let packet = Packet::new(&1, &142, &[1, 2, 3, 4], &[5, 6, 7, 8], None, &mut entropy).unwrap();
+
+	assert_eq!(Packet::SIZE, packet.get_header().get_size());
+	assert_eq!([1, 2, 3, 4], packet.get_message().get_destination_public_key());
+	assert_eq!(PublicKey64::default(), packet.get_message().get_source_public_key());
+	assert_eq!(Ok(packet), Packet::decode(&packet.to_bytes()));
+	assert!(!packet.is_signature_valid());
+
+	let gamming_key = get_gamming_key(&Key::default(), &packet.get_message().get_gamming_nonce());
+	assert_eq!(0, gamming_key[0]);
+	assert_eq!([5, 6, 7, 8], decrypt_solution_nonce(&gamming_key, &packet.get_solution_nonce()));
+}
+
+#[test]
+fn test_signed_packet() {
+	let signer = KeyPair::from_seed("bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb").unwrap();
+	let mut entropy = crate::entropy::ChaChaSource::from_seed([3; 32]);
+	let computor = KeyPair::from_seed("ccccccccccccccccccccccccccccccccccccccccccccccccccccccc").unwrap();
+	let packet = Packet::new(&1, &142, &computor.public_key(), &[5, 6, 7, 8], Some(&signer), &mut entropy).unwrap();
+
+	assert_eq!(signer.public_key(), packet.get_message().get_source_public_key());
+	assert!(Packet::decode(&packet.to_bytes()).unwrap().is_signature_valid());
+
+	// Only the computor can decrypt the nonce, with the key it agrees on with the source
+	let shared_key = computor.get_shared_key(&packet.get_message().get_source_public_key()).unwrap();
+	let gamming_key = get_gamming_key(&shared_key, &packet.get_message().get_gamming_nonce());
+	assert_eq!(0, gamming_key[0]);
+	assert_eq!([5, 6, 7, 8], decrypt_solution_nonce(&gamming_key, &packet.get_solution_nonce()));
+	let anonymous_gamming_key = get_gamming_key(&Key::default(), &packet.get_message().get_gamming_nonce());
+	assert_ne!([5, 6, 7, 8], decrypt_solution_nonce(&anonymous_gamming_key, &packet.get_solution_nonce()));
+
+	// No key can be agreed with a destination off the curve
+	let invalid_destination = [u64::MAX; 4];
+	assert!(matches!(Packet::new(&1, &142, &invalid_destination, &[5, 6, 7, 8], Some(&signer), &mut entropy), Err(PacketError::NoSharedKey)));
+
+	let mut tampered = packet;
+	tampered.solution_nonce[0] ^= 1;
+	assert!(!tampered.is_signature_valid());
+}
+
+#[test]
+fn test_solution_nonce_encryption() {
+	let gamming_key = get_gamming_key(&[9; 32], &[1, 2, 3, 4]);
+	assert_ne!(gamming_key, get_gamming_key(&Key::default(), &[1, 2, 3, 4]));
+	assert_ne!(gamming_key, get_gamming_key(&[9; 32], &[1, 2, 3, 5]));
+
+	let nonce = [0x0123_4567_89ab_cdef, 0, u64::MAX, 42];
+	let solution_nonce = encrypt_solution_nonce(&gamming_key, &nonce);
+	assert_ne!(nonce, solution_nonce);
+	assert_eq!(nonce, decrypt_solution_nonce(&gamming_key, &solution_nonce));
+	assert_ne!(nonce, decrypt_solution_nonce(&get_gamming_key(&[8; 32], &[1, 2, 3, 4]), &solution_nonce));
+
+	// The gamma is the K12 stream of the gamming key, so a zero nonce encrypts to it
+	let mut gamma = Gamma::default();
+	k12(&gamming_key, &mut gamma);
+	assert_eq!(decode_64::<4>(&gamma), encrypt_solution_nonce(&gamming_key, &Nonce64::default()));
+}
@@ -0,0 +1,266 @@
+use std::fmt;
+use std::fmt::Formatter;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+// Reads what `tcpdump -w` or Wireshark save in the classic pcap format, or a hex dump as `xxd -p` prints it,
+// back into the TCP byte streams the packets were sent in
+
+const PCAP_MAGIC_MICROSECONDS: u32 = 0xa1b2c3d4;
+const PCAP_MAGIC_NANOSECONDS: u32 = 0xa1b23c4d;
+const PCAP_HEADER_SIZE: usize = 24;
+const PCAP_RECORD_HEADER_SIZE: usize = 16;
+
+const LINKTYPE_NULL: u32 = 0;
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+const LINKTYPE_LINUX_SLL2: u32 = 276;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+
+const IP_PROTOCOL_TCP: u8 = 6;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CaptureError {
+	/// Neither a pcap file nor hex digits
+	UnknownFormat,
+	Truncated { offset: usize },
+	UnsupportedLinkType(u32),
+}
+
+impl fmt::Display for CaptureError {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		match self {
+			CaptureError::UnknownFormat => write!(f, "the input is neither a pcap file nor a hex dump"),
+			CaptureError::Truncated { offset } => write!(f, "the pcap file is truncated at Byte {offset}"),
+			CaptureError::UnsupportedLinkType(link_type) => write!(f, "the pcap link type {link_type} is not supported"),
+		}
+	}
+}
+
+impl std::error::Error for CaptureError {}
+
+/// The payload bytes of one direction of a TCP connection, in sequence order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TcpFlow {
+	/// Source and destination, unknown for a hex dump
+	pub endpoints: Option<(SocketAddr, SocketAddr)>,
+	pub data: Vec<u8>,
+	/// Sequence number the next segment should have
+	next_sequence: u32,
+}
+
+/// A pcap file gives one flow per connection direction, a hex dump a single flow of its bytes
+pub fn read_flows(input: &[u8]) -> Result<Vec<TcpFlow>, CaptureError> {
+	if let Some(magic) = input.get(..4) {
+		let magic = u32::from_le_bytes(magic.try_into().unwrap());
+		if [PCAP_MAGIC_MICROSECONDS, PCAP_MAGIC_NANOSECONDS].contains(&magic) {
+			return read_pcap(input, u32::from_le_bytes);
+		}
+		if [PCAP_MAGIC_MICROSECONDS, PCAP_MAGIC_NANOSECONDS].contains(&magic.swap_bytes()) {
+			return read_pcap(input, u32::from_be_bytes);
+		}
+	}
+
+	let data = parse_hex_dump(&String::from_utf8_lossy(input)).ok_or(CaptureError::UnknownFormat)?;
+
+	Ok(vec![TcpFlow { endpoints: None, data, next_sequence: 0 }])
+}
+
+/// Hex digits with any whitespace between them, an optional `0x` prefix per line
+pub fn parse_hex_dump(text: &str) -> Option<Vec<u8>> {
+	let digits = text.lines()
+		.map(|line| line.trim().trim_start_matches("0x"))
+		.flat_map(|line| line.chars().filter(|character| !character.is_whitespace()))
+		.collect::<String>();
+	if digits.is_empty() {
+		return None;
+	}
+
+	hex::decode(digits).ok()
+}
+
+/// `read_u32` reads the integers in the byte order of the writer, which the magic number tells
+fn read_pcap(input: &[u8], read_u32: fn([u8; 4]) -> u32) -> Result<Vec<TcpFlow>, CaptureError> {
+	let header = input.get(..PCAP_HEADER_SIZE).ok_or(CaptureError::Truncated { offset: input.len() })?;
+	// The upper bits hold the FCS length
+	let link_type = read_u32(header[20..24].try_into().unwrap()) & 0x0fff_ffff;
+
+	let mut flows: Vec<TcpFlow> = Vec::new();
+	let mut offset = PCAP_HEADER_SIZE;
+	while offset < input.len() {
+		let record_header = input.get(offset..offset + PCAP_RECORD_HEADER_SIZE).ok_or(CaptureError::Truncated { offset })?;
+		let captured_size = read_u32(record_header[8..12].try_into().unwrap()) as usize;
+		let record_start = offset + PCAP_RECORD_HEADER_SIZE;
+		let record = input.get(record_start..record_start + captured_size).ok_or(CaptureError::Truncated { offset: record_start })?;
+		offset = record_start + captured_size;
+
+		let Some(ip_packet) = get_ip_packet(link_type, record)? else {
+			continue;
+		};
+		let Some(segment) = parse_tcp_segment(ip_packet) else {
+			continue;
+		};
+		if segment.payload.is_empty() {
+			continue;
+		}
+
+		match flows.iter_mut().find(|flow| flow.endpoints == Some(segment.endpoints)) {
+			Some(flow) => flow.push(segment.sequence, segment.payload),
+			None => flows.push(TcpFlow {
+				endpoints: Some(segment.endpoints),
+				data: segment.payload.to_vec(),
+				next_sequence: segment.sequence.wrapping_add(segment.payload.len() as u32),
+			}),
+		}
+	}
+
+	Ok(flows)
+}
+
+impl TcpFlow {
+	/// Drops what was captured already, a retransmission, and appends the rest
+	fn push(&mut self, sequence: u32, payload: &[u8]) {
+		let already_seen = self.next_sequence.wrapping_sub(sequence);
+		// Half the sequence space ahead is a segment after a gap, which is taken whole
+		let new_data = if already_seen < (1 << 31) {
+			payload.get(already_seen as usize..).unwrap_or_default()
+		} else {
+			self.next_sequence = sequence;
+			payload
+		};
+
+		self.data.extend_from_slice(new_data);
+		self.next_sequence = self.next_sequence.wrapping_add(new_data.len() as u32);
+	}
+}
+
+/// The IP packet inside a link-layer frame, `None` for other protocols
+fn get_ip_packet(link_type: u32, record: &[u8]) -> Result<Option<&[u8]>, CaptureError> {
+	let ip_packet = match link_type {
+		LINKTYPE_NULL => record.get(4..),
+		LINKTYPE_RAW => Some(record),
+		LINKTYPE_ETHERNET => {
+			let mut ethertype_offset = 12;
+			while record.get(ethertype_offset..ethertype_offset + 2) == Some(&ETHERTYPE_VLAN.to_be_bytes()) {
+				ethertype_offset += 4;
+			}
+
+			match record.get(ethertype_offset..ethertype_offset + 2).map(|ethertype| u16::from_be_bytes(ethertype.try_into().unwrap())) {
+				Some(ETHERTYPE_IPV4 | ETHERTYPE_IPV6) => record.get(ethertype_offset + 2..),
+				_ => None,
+			}
+		}
+		LINKTYPE_LINUX_SLL => record.get(16..),
+		LINKTYPE_LINUX_SLL2 => record.get(20..),
+		_ => return Err(CaptureError::UnsupportedLinkType(link_type)),
+	};
+
+	Ok(ip_packet)
+}
+
+struct TcpSegment<'a> {
+	endpoints: (SocketAddr, SocketAddr),
+	sequence: u32,
+	payload: &'a [u8],
+}
+
+/// `None` for anything but a complete TCP segment over IPv4 or IPv6
+fn parse_tcp_segment(ip_packet: &[u8]) -> Option<TcpSegment<'_>> {
+	let (source, destination, tcp) = match ip_packet.first()? >> 4 {
+		4 => {
+			let header_size = ((ip_packet[0] & 0x0f) as usize) * 4;
+			let total_size = u16::from_be_bytes(ip_packet.get(2..4)?.try_into().unwrap()) as usize;
+			if *ip_packet.get(9)? != IP_PROTOCOL_TCP {
+				return None;
+			}
+
+			let source = Ipv4Addr::from(<[u8; 4]>::try_from(ip_packet.get(12..16)?).unwrap());
+			let destination = Ipv4Addr::from(<[u8; 4]>::try_from(ip_packet.get(16..20)?).unwrap());
+			(IpAddr::V4(source), IpAddr::V4(destination), ip_packet.get(header_size..total_size)?)
+		}
+		// Extension headers are not followed, TCP has to come right after the fixed header
+		6 => {
+			let payload_size = u16::from_be_bytes(ip_packet.get(4..6)?.try_into().unwrap()) as usize;
+			if *ip_packet.get(6)? != IP_PROTOCOL_TCP {
+				return None;
+			}
+
+			let source = Ipv6Addr::from(<[u8; 16]>::try_from(ip_packet.get(8..24)?).unwrap());
+			let destination = Ipv6Addr::from(<[u8; 16]>::try_from(ip_packet.get(24..40)?).unwrap());
+			(IpAddr::V6(source), IpAddr::V6(destination), ip_packet.get(40..40 + payload_size)?)
+		}
+		_ => return None,
+	};
+
+	let source_port = u16::from_be_bytes(tcp.get(0..2)?.try_into().unwrap());
+	let destination_port = u16::from_be_bytes(tcp.get(2..4)?.try_into().unwrap());
+	let sequence = u32::from_be_bytes(tcp.get(4..8)?.try_into().unwrap());
+	let data_offset = ((tcp.get(12)? >> 4) as usize) * 4;
+
+	Some(TcpSegment {
+		endpoints: (SocketAddr::new(source, source_port), SocketAddr::new(destination, destination_port)),
+		sequence,
+		payload: tcp.get(data_offset..)?,
+	})
+}
+
+#[cfg(test)]
+fn test_ethernet_record(sequence: u32, payload: &[u8]) -> Vec<u8> {
+	let mut frame = vec![0u8; 12];
+	frame.extend_from_slice(&ETHERTYPE_IPV4.to_be_bytes());
+
+	let total_size = (20 + 20 + payload.len()) as u16;
+	frame.extend_from_slice(&[0x45, 0]);
+	frame.extend_from_slice(&total_size.to_be_bytes());
+	frame.extend_from_slice(&[0, 0, 0, 0, 64, IP_PROTOCOL_TCP, 0, 0]);
+	frame.extend_from_slice(&[10, 0, 0, 1, 192, 168, 1, 2]);
+
+	frame.extend_from_slice(&40000u16.to_be_bytes());
+	frame.extend_from_slice(&21841u16.to_be_bytes());
+	frame.extend_from_slice(&sequence.to_be_bytes());
+	frame.extend_from_slice(&[0, 0, 0, 0, 0x50, 0x18, 0, 0, 0, 0, 0, 0]);
+	frame.extend_from_slice(payload);
+
+	let mut record = Vec::new();
+	record.extend_from_slice(&[0; 8]);
+	record.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+	record.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+	record.extend(frame);
+
+	record
+}
+
+#[test]
+fn test_read_pcap() {
+	let mut pcap = Vec::new();
+	pcap.extend_from_slice(&PCAP_MAGIC_MICROSECONDS.to_le_bytes());
+	pcap.extend_from_slice(&[2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 0, 0]);
+	pcap.extend_from_slice(&LINKTYPE_ETHERNET.to_le_bytes());
+
+	// The second segment comes twice, the retransmission overlaps the third one
+	pcap.extend(test_ethernet_record(1000, b"hello "));
+	pcap.extend(test_ethernet_record(1006, b"qubic"));
+	pcap.extend(test_ethernet_record(1006, b"qubic"));
+	pcap.extend(test_ethernet_record(1008, b"bic world"));
+
+	let flows = read_flows(&pcap).unwrap();
+	assert_eq!(1, flows.len());
+	assert_eq!(Some(("10.0.0.1:40000".parse().unwrap(), "192.168.1.2:21841".parse().unwrap())), flows[0].endpoints);
+	assert_eq!(b"hello qubic world".to_vec(), flows[0].data);
+
+	pcap.truncate(pcap.len() - 3);
+	assert!(matches!(read_flows(&pcap), Err(CaptureError::Truncated { .. })));
+}
+
+#[test]
+fn test_read_hex_dump() {
+	let flows = read_flows(b"c8000001\n  0x0a0B0c\n").unwrap();
+	assert_eq!(vec![0xc8, 0, 0, 1, 0x0a, 0x0b, 0x0c], flows[0].data);
+	assert_eq!(None, flows[0].endpoints);
+
+	assert_eq!(Err(CaptureError::UnknownFormat), read_flows(b"not hex"));
+	assert_eq!(Err(CaptureError::UnknownFormat), read_flows(b""));
+}
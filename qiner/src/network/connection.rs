@@ -0,0 +1,438 @@
+use std::future::Future;
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+use socket2::{SockRef, TcpKeepalive};
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio::sync::{broadcast, mpsc, oneshot, watch, Notify};
+use tokio::time::Instant;
+use crate::entropy::{NonceSource, OsSource};
+use crate::network::frame::{FrameReader, PeerMessage, ReceiveStats};
+
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+pub const WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+/// Idle time before the first TCP keepalive probe
+pub const KEEPALIVE_TIME: Duration = Duration::from_secs(30);
+pub const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(10);
+
+pub const INITIAL_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+pub const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+/// Events kept for a slow subscriber, older ones are dropped
+const EVENT_CAPACITY: usize = 64;
+/// Writes waiting for a connection
+const REQUEST_CAPACITY: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+	Connecting,
+	Connected,
+	/// Waiting before the next connection attempt, writes fail at once meanwhile
+	Backoff,
+	/// Every handle of the connection was dropped
+	Closed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionEvent {
+	Connected { addr: String },
+	Disconnected { addr: String, error: String },
+	Reconnecting { addr: String, attempt: u32, delay: Duration },
+	/// A message from the peer, except the relayed network traffic
+	Received { addr: String, message: PeerMessage },
+}
+
+/// Delay before the connection attempt following `failures` failed ones, doubling up to
+/// `MAX_RECONNECT_DELAY`. The jitter takes up to half of it, so peers dropped together do not reconnect together
+pub fn reconnect_delay(failures: u32, jitter: u64) -> Duration {
+	let delay = 2u32.checked_pow(failures.saturating_sub(1))
+		.and_then(|factor| INITIAL_RECONNECT_DELAY.checked_mul(factor))
+		.unwrap_or(MAX_RECONNECT_DELAY)
+		.min(MAX_RECONNECT_DELAY);
+
+	delay - delay.mul_f64((jitter >> 11) as f64 / (1u64 << 53) as f64 / 2.0)
+}
+
+struct WriteRequest {
+	data: Arc<[u8]>,
+	reply: oneshot::Sender<io::Result<()>>,
+}
+
+/// Long-lived connections to the peers, each one kept by its own task which reconnects when it drops
+#[derive(Debug)]
+pub struct ConnectionManager {
+	connections: Vec<ConnectionHandle>,
+	events: broadcast::Sender<ConnectionEvent>,
+	write_timeout: Duration,
+}
+
+#[derive(Debug, Clone)]
+struct ConnectionHandle {
+	addr: String,
+	requests: mpsc::Sender<WriteRequest>,
+	state: watch::Receiver<ConnectionState>,
+	reconnect: Arc<Notify>,
+	receive_stats: Arc<ReceiveStats>,
+}
+
+impl ConnectionManager {
+	/// Starts connecting to every address, must be called within a tokio runtime
+	pub fn new(addrs: &[String]) -> Self {
+		ConnectionManager::with_write_timeout(addrs, WRITE_TIMEOUT)
+	}
+
+	/// A write taking longer than `write_timeout` drops the connection
+	pub fn with_write_timeout(addrs: &[String], write_timeout: Duration) -> Self {
+		let (events, _) = broadcast::channel(EVENT_CAPACITY);
+		let connections = addrs.iter().map(|addr| ConnectionManager::connect(addr, &events, write_timeout)).collect();
+
+		ConnectionManager { connections, events, write_timeout }
+	}
+
+	fn connect(addr: &str, events: &broadcast::Sender<ConnectionEvent>, write_timeout: Duration) -> ConnectionHandle {
+		let (requests, request_receiver) = mpsc::channel(REQUEST_CAPACITY);
+		let (state_sender, state) = watch::channel(ConnectionState::Connecting);
+		let reconnect = Arc::new(Notify::new());
+		let receive_stats = Arc::new(ReceiveStats::default());
+		let peer = PeerConnection { addr: addr.to_string(), events: events.clone(), receive_stats: receive_stats.clone(), write_timeout };
+		tokio::spawn(keep_connection(peer, request_receiver, state_sender, reconnect.clone()));
+
+		ConnectionHandle { addr: addr.to_string(), requests, state, reconnect, receive_stats }
+	}
+
+	/// Connects to the new addresses in the given order, keeps the connections to the remaining ones
+	/// and closes the others once their pending writes are done
+	pub fn set_addrs(&mut self, addrs: &[String]) {
+		let mut connections = std::mem::take(&mut self.connections);
+		self.connections = addrs.iter().map(|addr| match connections.iter().position(|connection| connection.addr == *addr) {
+			Some(idx) => connections.swap_remove(idx),
+			None => ConnectionManager::connect(addr, &self.events, self.write_timeout),
+		}).collect();
+	}
+
+	pub fn subscribe(&self) -> broadcast::Receiver<ConnectionEvent> {
+		self.events.subscribe()
+	}
+
+	pub fn state(&self, idx: usize) -> ConnectionState {
+		*self.connections[idx].state.borrow()
+	}
+
+	/// Notified whenever the state of the connection to the peer `idx` changes
+	pub fn subscribe_state(&self, idx: usize) -> watch::Receiver<ConnectionState> {
+		self.connections[idx].state.clone()
+	}
+
+	pub fn addr(&self, idx: usize) -> &str {
+		&self.connections[idx].addr
+	}
+
+	/// What was read from the peer `idx` over all its connections
+	pub fn receive_stats(&self, idx: usize) -> &Arc<ReceiveStats> {
+		&self.connections[idx].receive_stats
+	}
+
+	/// Cuts the backoff of every lost connection short, e.g. for a last attempt on shutdown.
+	/// The writes sent afterwards wait for the new connection
+	pub fn reconnect(&self) {
+		self.connections.iter()
+			.filter(|connection| *connection.state.borrow() == ConnectionState::Backoff)
+			.for_each(|connection| connection.reconnect.notify_one());
+	}
+
+	pub fn len(&self) -> usize {
+		self.connections.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.connections.is_empty()
+	}
+
+	/// Writes the data to the peer `idx`, waiting for the connection while it is being established.
+	/// The future does not borrow the manager, so writes to several peers can run as separate tasks
+	pub fn send(&self, idx: usize, data: Arc<[u8]>) -> impl Future<Output = io::Result<()>> + Send + 'static {
+		let requests = self.connections[idx].requests.clone();
+
+		async move {
+			let (reply, reply_receiver) = oneshot::channel();
+			requests.send(WriteRequest { data, reply }).await
+				.map_err(|_| io::Error::new(io::ErrorKind::NotConnected, "the connection is closed"))?;
+
+			reply_receiver.await.unwrap_or_else(|_| Err(io::Error::new(io::ErrorKind::NotConnected, "the connection is closed")))
+		}
+	}
+}
+
+/// What the task of a connection shares with the rest of the miner
+struct PeerConnection {
+	addr: String,
+	events: broadcast::Sender<ConnectionEvent>,
+	receive_stats: Arc<ReceiveStats>,
+	write_timeout: Duration,
+}
+
+impl PeerConnection {
+	/// Reads the complete frames, logs them and publishes the known messages
+	fn dispatch(&self, reader: &mut FrameReader) -> io::Result<()> {
+		let addr = &self.addr;
+		while let Some(frame) = reader.next_frame().map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))? {
+			let message = PeerMessage::parse(&frame);
+			self.receive_stats.record(&message);
+
+			match message {
+				Ok(Some(PeerMessage::Broadcast(r#type))) => log::trace!("{addr} relayed a message of type {type}", type = r#type),
+				Ok(Some(message)) => {
+					log::debug!("{addr} sent {message:?}");
+					let _ = self.events.send(ConnectionEvent::Received { addr: addr.clone(), message });
+				}
+				Ok(None) => log::debug!("Discarding a message of unknown type {} ({} Bytes) from {addr}", frame.header.get_type(), frame.header.get_size()),
+				Err(err) => log::warn!("Discarding a malformed message of type {} from {addr}: {err}", frame.header.get_type()),
+			}
+		}
+
+		Ok(())
+	}
+}
+
+async fn keep_connection(
+	peer: PeerConnection,
+	mut requests: mpsc::Receiver<WriteRequest>,
+	state: watch::Sender<ConnectionState>,
+	reconnect: Arc<Notify>,
+) {
+	let addr = peer.addr.clone();
+	let events = peer.events.clone();
+	let mut jitter_source = OsSource;
+	let mut failures = 0u32;
+
+	loop {
+		state.send_replace(ConnectionState::Connecting);
+		log::debug!("Connecting to {addr}");
+
+		let error = match connect(&addr).await {
+			Ok(stream) => {
+				failures = 0;
+				state.send_replace(ConnectionState::Connected);
+				let _ = events.send(ConnectionEvent::Connected { addr: addr.clone() });
+				log::info!("Connected to {addr}");
+
+				match serve(stream, &mut requests, &peer).await {
+					Some(error) => error,
+					None => break,
+				}
+			}
+			Err(error) => error,
+		};
+
+		state.send_replace(ConnectionState::Backoff);
+		let _ = events.send(ConnectionEvent::Disconnected { addr: addr.clone(), error: error.to_string() });
+		// The writes waiting for this connection fail, so their solutions can go to another peer
+		while let Ok(request) = requests.try_recv() {
+			let _ = request.reply.send(Err(io::Error::new(error.kind(), error.to_string())));
+		}
+
+		failures += 1;
+		let delay = reconnect_delay(failures, jitter_source.next_u64().unwrap_or_default());
+		log::warn!("No connection to {addr}: {error}, reconnecting in {:.1} s", delay.as_secs_f64());
+		let _ = events.send(ConnectionEvent::Reconnecting { addr: addr.clone(), attempt: failures, delay });
+
+		let reconnect_at = Instant::now() + delay;
+		loop {
+			tokio::select!(
+				biased;
+				_ = reconnect.notified() => break,
+				_ = tokio::time::sleep_until(reconnect_at) => break,
+				request = requests.recv() => match request {
+					Some(request) => {
+						let _ = request.reply.send(Err(io::Error::new(io::ErrorKind::NotConnected, format!("reconnecting to {addr}"))));
+					}
+					None => {
+						state.send_replace(ConnectionState::Closed);
+						return;
+					}
+				},
+			);
+		}
+	}
+
+	state.send_replace(ConnectionState::Closed);
+}
+
+async fn connect(addr: &str) -> io::Result<TcpStream> {
+	let stream = tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(addr)).await
+		.map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "the connection timed out"))??;
+
+	let keepalive = TcpKeepalive::new().with_time(KEEPALIVE_TIME);
+	#[cfg(any(target_os = "linux", target_os = "macos", target_os = "windows"))]
+	let keepalive = keepalive.with_interval(KEEPALIVE_INTERVAL);
+	SockRef::from(&stream).set_tcp_keepalive(&keepalive)?;
+	stream.set_nodelay(true)?;
+
+	Ok(stream)
+}
+
+/// Writes the requests and reads the peer messages until the connection fails, `None` when every handle was dropped
+async fn serve(mut stream: TcpStream, requests: &mut mpsc::Receiver<WriteRequest>, peer: &PeerConnection) -> Option<io::Error> {
+	let mut read_buffer = vec![0u8; 4096];
+	let mut reader = FrameReader::default();
+
+	loop {
+		tokio::select!(
+			request = requests.recv() => {
+				let request = request?;
+				let result = match tokio::time::timeout(peer.write_timeout, stream.write_all(&request.data)).await {
+					Ok(result) => result,
+					Err(_) => Err(io::Error::new(io::ErrorKind::TimedOut, "the write timed out")),
+				};
+
+				let error = result.as_ref().err().map(|err| io::Error::new(err.kind(), err.to_string()));
+				let _ = request.reply.send(result);
+				if let Some(error) = error {
+					return Some(error);
+				}
+			}
+			// The peer closing the connection is also only noticed by reading
+			readable = stream.readable() => {
+				if let Err(err) = readable {
+					return Some(err);
+				}
+
+				match stream.try_read(&mut read_buffer) {
+					Ok(0) => return Some(io::Error::new(io::ErrorKind::ConnectionAborted, "closed by the peer")),
+					Ok(size) => {
+						reader.extend(&read_buffer[..size]);
+						if let Err(err) = peer.dispatch(&mut reader) {
+							return Some(err);
+						}
+					}
+					Err(err) if err.kind() == io::ErrorKind::WouldBlock => {}
+					Err(err) => return Some(err),
+				}
+			}
+		);
+	}
+}
+
+#[test]
+fn test_reconnect_delay() {
+	assert_eq!(Duration::from_secs(1), reconnect_delay(1, 0));
+	assert_eq!(Duration::from_secs(8), reconnect_delay(4, 0));
+	assert_eq!(MAX_RECONNECT_DELAY, reconnect_delay(100, 0));
+
+	// The jitter takes at most half of the delay
+	let delay = reconnect_delay(4, u64::MAX);
+	assert!(delay >= Duration::from_secs(4) && delay < Duration::from_secs(8));
+}
+
+#[tokio::test]
+async fn test_connection_manager_reconnects() {
+	use tokio::io::AsyncReadExt;
+
+	let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+	let addr = listener.local_addr().unwrap().to_string();
+	let manager = ConnectionManager::new(std::slice::from_ref(&addr));
+	let mut events = manager.subscribe();
+
+	// Two writes over the same connection
+	let (mut stream, _) = listener.accept().await.unwrap();
+	assert_eq!(ConnectionEvent::Connected { addr: addr.clone() }, events.recv().await.unwrap());
+	manager.send(0, Arc::from(&b"ab"[..])).await.unwrap();
+	manager.send(0, Arc::from(&b"cd"[..])).await.unwrap();
+	let mut data = [0u8; 4];
+	stream.read_exact(&mut data).await.unwrap();
+	assert_eq!(b"abcd", &data);
+	assert_eq!(ConnectionState::Connected, manager.state(0));
+
+	// The peer drops the connection, it is restored after the backoff
+	drop(stream);
+	assert!(matches!(events.recv().await.unwrap(), ConnectionEvent::Disconnected { .. }));
+	assert!(matches!(events.recv().await.unwrap(), ConnectionEvent::Reconnecting { attempt: 1, .. }));
+	assert!(manager.send(0, Arc::from(&b"ef"[..])).await.is_err());
+
+	let (mut stream, _) = listener.accept().await.unwrap();
+	assert_eq!(ConnectionEvent::Connected { addr: addr.clone() }, events.recv().await.unwrap());
+	manager.send(0, Arc::from(&b"gh"[..])).await.unwrap();
+	let mut data = [0u8; 2];
+	stream.read_exact(&mut data).await.unwrap();
+	assert_eq!(b"gh", &data);
+}
+
+#[tokio::test]
+async fn test_connection_reads_messages() {
+	use lib::types::network::protocols::EXCHANGE_PUBLIC_PEERS;
+	use std::net::Ipv4Addr;
+	use std::sync::atomic::Ordering;
+
+	let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+	let addr = listener.local_addr().unwrap().to_string();
+	let manager = ConnectionManager::new(std::slice::from_ref(&addr));
+	let mut events = manager.subscribe();
+
+	let (mut stream, _) = listener.accept().await.unwrap();
+	assert_eq!(ConnectionEvent::Connected { addr: addr.clone() }, events.recv().await.unwrap());
+
+	// Public peers, then a message of an unknown type
+	let mut data = vec![24, 0, 0, 0, 0, 0, 0, EXCHANGE_PUBLIC_PEERS, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4];
+	data.extend_from_slice(&[10, 0, 0, 0, 0, 0, 0, 250, 7, 7]);
+	stream.write_all(&data).await.unwrap();
+
+	let public_peers = [1, 2, 3, 4].map(|item| Ipv4Addr::new(item, item, item, item)).to_vec();
+	assert_eq!(ConnectionEvent::Received { addr: addr.clone(), message: PeerMessage::ExchangePublicPeers(public_peers) }, events.recv().await.unwrap());
+	manager.send(0, Arc::from(&b"ab"[..])).await.unwrap();
+	assert_eq!(2, manager.receive_stats(0).frames.load(Ordering::Relaxed));
+	assert_eq!(1, manager.receive_stats(0).unknown.load(Ordering::Relaxed));
+
+	// A size smaller than the header breaks the framing
+	stream.write_all(&[1, 0, 0, 0, 0, 0, 0, 0]).await.unwrap();
+	assert!(matches!(events.recv().await.unwrap(), ConnectionEvent::Disconnected { .. }));
+}
+
+#[tokio::test]
+async fn test_connection_manager_set_addrs() {
+	use tokio::io::AsyncReadExt;
+
+	let first_listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+	let second_listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+	let first_addr = first_listener.local_addr().unwrap().to_string();
+	let second_addr = second_listener.local_addr().unwrap().to_string();
+	let mut manager = ConnectionManager::new(std::slice::from_ref(&first_addr));
+	let (mut first_stream, _) = first_listener.accept().await.unwrap();
+
+	// The first connection is kept, only the second peer is connected to
+	manager.set_addrs(&[second_addr.clone(), first_addr.clone()]);
+	let (mut second_stream, _) = second_listener.accept().await.unwrap();
+	assert_eq!(second_addr, manager.addr(0));
+	manager.send(1, Arc::from(&b"ab"[..])).await.unwrap();
+	manager.send(0, Arc::from(&b"cd"[..])).await.unwrap();
+	let mut data = [0u8; 2];
+	first_stream.read_exact(&mut data).await.unwrap();
+	assert_eq!(b"ab", &data);
+	second_stream.read_exact(&mut data).await.unwrap();
+	assert_eq!(b"cd", &data);
+
+	// A removed peer is disconnected
+	manager.set_addrs(std::slice::from_ref(&second_addr));
+	assert_eq!(1, manager.len());
+	assert_eq!(0, first_stream.read(&mut data).await.unwrap());
+}
+
+#[tokio::test]
+async fn test_connection_write_timeout() {
+	// The peer never reads and its receive buffer is small, so a large write stalls
+	let socket = tokio::net::TcpSocket::new_v4().unwrap();
+	socket.set_recv_buffer_size(4 * 1024).unwrap();
+	socket.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+	let listener = socket.listen(1).unwrap();
+	let addr = listener.local_addr().unwrap().to_string();
+	let manager = ConnectionManager::with_write_timeout(std::slice::from_ref(&addr), Duration::from_millis(500));
+	let mut events = manager.subscribe();
+
+	let (_stream, _) = listener.accept().await.unwrap();
+	assert_eq!(ConnectionEvent::Connected { addr: addr.clone() }, events.recv().await.unwrap());
+
+	let error = manager.send(0, Arc::from(vec![0u8; 4 * 1024 * 1024])).await.unwrap_err();
+	assert_eq!(io::ErrorKind::TimedOut, error.kind());
+	assert_eq!(ConnectionEvent::Disconnected { addr: addr.clone(), error: "the write timed out".to_string() }, events.recv().await.unwrap());
+	assert_eq!(ConnectionState::Backoff, manager.state(0));
+}
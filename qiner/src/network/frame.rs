@@ -0,0 +1,176 @@
+use std::net::Ipv4Addr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use lib::types::network::{Type, HEADER_SIZE, NUMBER_OF_EXCHANGED_PEERS};
+use lib::types::network::protocols::{BROADCAST_COMPUTORS, BROADCAST_FUTURE_TICK_DATA, BROADCAST_MESSAGE, BROADCAST_TICK, BROADCAST_TRANSACTION, END_RESPONSE, EXCHANGE_PUBLIC_PEERS};
+use lib::types::{PublicKey64, Signature};
+use crate::network::{DecodeError, Message, RequestResponseHeader};
+use crate::signing::get_signature_64;
+
+/// One message read from a peer: its header and the bytes following it
+#[derive(Debug, Clone)]
+pub struct Frame {
+	pub header: RequestResponseHeader,
+	pub payload: Vec<u8>,
+}
+
+/// Splits the byte stream of a connection into frames, whatever the sizes of the reads are
+#[derive(Debug, Default)]
+pub struct FrameReader {
+	buffer: Vec<u8>,
+}
+
+impl FrameReader {
+	pub fn extend(&mut self, data: &[u8]) {
+		self.buffer.extend_from_slice(data);
+	}
+
+	/// The next complete frame, `None` until all its bytes are read.
+	/// An error means the stream is out of step and the connection has to be dropped
+	pub fn next_frame(&mut self) -> Result<Option<Frame>, DecodeError> {
+		if self.buffer.len() < HEADER_SIZE {
+			return Ok(None);
+		}
+
+		let header = RequestResponseHeader::decode(&self.buffer)?;
+		let size = header.get_size();
+		if self.buffer.len() < size {
+			return Ok(None);
+		}
+
+		let payload = self.buffer[HEADER_SIZE..size].to_vec();
+		self.buffer.drain(..size);
+
+		Ok(Some(Frame { header, payload }))
+	}
+}
+
+/// The messages the miner understands, the others are only counted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeerMessage {
+	/// Sent by a node right after the connection is accepted
+	ExchangePublicPeers(Vec<Ipv4Addr>),
+	/// The signature ends the payload, a relayed copy of a sent packet carries the signature it was sent with
+	BroadcastMessage { source_public_key: PublicKey64, destination_public_key: PublicKey64, signature: Signature },
+	/// Network traffic relayed to every peer, of no use to the miner
+	Broadcast(Type),
+	EndResponse,
+}
+
+impl PeerMessage {
+	/// `None` for an unknown type, an error for a known type with a malformed payload
+	pub fn parse(frame: &Frame) -> Result<Option<PeerMessage>, DecodeError> {
+		let payload = frame.payload.as_slice();
+
+		let message = match frame.header.get_type() {
+			EXCHANGE_PUBLIC_PEERS => {
+				let peers = payload.get(..NUMBER_OF_EXCHANGED_PEERS * 4)
+					.ok_or(DecodeError::TooShort { expected: NUMBER_OF_EXCHANGED_PEERS * 4, found: payload.len() })?;
+
+				PeerMessage::ExchangePublicPeers(peers.chunks_exact(4).map(|ip| Ipv4Addr::new(ip[0], ip[1], ip[2], ip[3])).collect())
+			}
+			BROADCAST_MESSAGE => {
+				let expected = Message::SIZE + size_of::<Signature>();
+				if payload.len() < expected {
+					return Err(DecodeError::TooShort { expected, found: payload.len() });
+				}
+
+				let message = Message::decode(payload)?;
+				let signature = payload[payload.len() - size_of::<Signature>()..].try_into().unwrap();
+				PeerMessage::BroadcastMessage {
+					source_public_key: message.get_source_public_key(),
+					destination_public_key: message.get_destination_public_key(),
+					signature: get_signature_64(signature),
+				}
+			}
+			r#type @ (BROADCAST_COMPUTORS | BROADCAST_TICK | BROADCAST_FUTURE_TICK_DATA | BROADCAST_TRANSACTION) => PeerMessage::Broadcast(r#type),
+			END_RESPONSE => PeerMessage::EndResponse,
+			_ => return Ok(None),
+		};
+
+		Ok(Some(message))
+	}
+}
+
+/// What a connection has read from its peer so far
+#[derive(Debug, Default)]
+pub struct ReceiveStats {
+	pub frames: AtomicUsize,
+	/// Frames of a type the miner does not know, discarded
+	pub unknown: AtomicUsize,
+	/// Frames of a known type whose payload could not be read
+	pub malformed: AtomicUsize,
+}
+
+impl ReceiveStats {
+	pub fn record(&self, message: &Result<Option<PeerMessage>, DecodeError>) {
+		self.frames.fetch_add(1, Ordering::Relaxed);
+		match message {
+			Ok(Some(_)) => {}
+			Ok(None) => { self.unknown.fetch_add(1, Ordering::Relaxed); }
+			Err(_) => { self.malformed.fetch_add(1, Ordering::Relaxed); }
+		}
+	}
+}
+
+#[cfg(test)]
+fn test_frame_bytes(r#type: Type, payload: &[u8]) -> Vec<u8> {
+	let size = (HEADER_SIZE + payload.len()) as u32;
+	let mut bytes = size.to_le_bytes()[..3].to_vec();
+	bytes.extend_from_slice(&[0, 1, 2, 3, r#type]);
+	bytes.extend_from_slice(payload);
+
+	bytes
+}
+
+#[test]
+fn test_frame_reader() {
+	let mut stream = test_frame_bytes(EXCHANGE_PUBLIC_PEERS, &[127, 0, 0, 1, 10, 0, 0, 2, 0, 0, 0, 0, 192, 168, 1, 1]);
+	stream.extend(test_frame_bytes(END_RESPONSE, &[]));
+	stream.extend(test_frame_bytes(200, &[7; 5]));
+
+	// Fed in pieces smaller than a header
+	let mut reader = FrameReader::default();
+	let mut frames = Vec::new();
+	for chunk in stream.chunks(5) {
+		reader.extend(chunk);
+		while let Some(frame) = reader.next_frame().unwrap() {
+			frames.push(frame);
+		}
+	}
+
+	assert_eq!(3, frames.len());
+	assert_eq!(Ok(Some(PeerMessage::ExchangePublicPeers(vec![
+		Ipv4Addr::new(127, 0, 0, 1),
+		Ipv4Addr::new(10, 0, 0, 2),
+		Ipv4Addr::new(0, 0, 0, 0),
+		Ipv4Addr::new(192, 168, 1, 1),
+	]))), PeerMessage::parse(&frames[0]));
+	assert_eq!(Ok(Some(PeerMessage::EndResponse)), PeerMessage::parse(&frames[1]));
+	assert_eq!(vec![7; 5], frames[2].payload);
+	assert_eq!(Ok(None), PeerMessage::parse(&frames[2]));
+
+	// A size smaller than the header breaks the framing
+	let mut reader = FrameReader::default();
+	reader.extend(&[4, 0, 0, 0, 0, 0, 0, 1]);
+	assert_eq!(Err(DecodeError::InvalidSize(4)), reader.next_frame().map(|frame| frame.is_some()));
+}
+
+#[test]
+fn test_parse_broadcast_message() {
+	let mut payload = vec![0u8; Message::SIZE + size_of::<Signature>()];
+	payload[..8].copy_from_slice(&5u64.to_le_bytes());
+	payload[32..40].copy_from_slice(&9u64.to_le_bytes());
+	*payload.last_mut().unwrap() = 1;
+
+	let mut reader = FrameReader::default();
+	reader.extend(&test_frame_bytes(BROADCAST_MESSAGE, &payload));
+	let frame = reader.next_frame().unwrap().unwrap();
+	assert_eq!(Ok(Some(PeerMessage::BroadcastMessage {
+		source_public_key: [5, 0, 0, 0],
+		destination_public_key: [9, 0, 0, 0],
+		signature: [0, 0, 0, 0, 0, 0, 0, 1 << 56],
+	})), PeerMessage::parse(&frame));
+
+	let short_frame = Frame { header: frame.header, payload: payload[..10].to_vec() };
+	assert!(PeerMessage::parse(&short_frame).is_err());
+}
@@ -0,0 +1,194 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use tokio::time::Instant;
+use lib::config::SubmitMode;
+use crate::submission::retry_delay;
+
+/// Counters of a peer, shared with the status line
+#[derive(Debug, Default)]
+pub struct PeerStats {
+	/// Solutions written to the peer
+	pub sent: AtomicUsize,
+	pub failures: AtomicUsize,
+	pub is_down: AtomicBool,
+}
+
+#[derive(Debug)]
+pub struct Peer {
+	pub addr: String,
+	pub stats: Arc<PeerStats>,
+	consecutive_failures: u32,
+	/// A failed peer is only a fallback until then
+	retry_at: Instant,
+}
+
+impl Peer {
+	pub fn new(addr: String) -> Self {
+		Peer {
+			addr,
+			stats: Arc::new(PeerStats::default()),
+			consecutive_failures: 0,
+			retry_at: Instant::now(),
+		}
+	}
+
+	pub fn is_available(&self, now: Instant) -> bool {
+		self.retry_at <= now
+	}
+
+	pub fn record_success(&mut self, solutions: usize) {
+		self.consecutive_failures = 0;
+		self.retry_at = Instant::now();
+		self.stats.sent.fetch_add(solutions, Ordering::Relaxed);
+		self.stats.is_down.store(false, Ordering::Relaxed);
+	}
+
+	/// The connection to the peer is restored, it need not wait for its retry time
+	pub fn record_connected(&mut self) {
+		self.consecutive_failures = 0;
+		self.retry_at = Instant::now();
+		self.stats.is_down.store(false, Ordering::Relaxed);
+	}
+
+	pub fn record_failure(&mut self) {
+		self.consecutive_failures += 1;
+		self.retry_at = Instant::now() + retry_delay(self.consecutive_failures);
+		self.stats.failures.fetch_add(1, Ordering::Relaxed);
+		self.stats.is_down.store(true, Ordering::Relaxed);
+	}
+}
+
+/// Where and how the solutions are sent, it can change while mining
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerConfig {
+	pub addrs: Vec<String>,
+	pub mode: SubmitMode,
+}
+
+#[derive(Debug)]
+pub struct PeerSet {
+	peers: Vec<Peer>,
+	mode: SubmitMode,
+	next_peer: usize,
+}
+
+impl PeerSet {
+	pub fn new(addrs: &[String], mode: SubmitMode) -> Self {
+		assert!(!addrs.is_empty(), "at least one peer is required");
+
+		PeerSet {
+			peers: addrs.iter().cloned().map(Peer::new).collect(),
+			mode,
+			next_peer: 0,
+		}
+	}
+
+	/// Switches to new addresses and mode, the peers which stay keep their state and counters
+	pub fn update(&mut self, addrs: &[String], mode: SubmitMode) {
+		assert!(!addrs.is_empty(), "at least one peer is required");
+
+		let mut peers = std::mem::take(&mut self.peers);
+		self.peers = addrs.iter().map(|addr| match peers.iter().position(|peer| peer.addr == *addr) {
+			Some(idx) => peers.swap_remove(idx),
+			None => Peer::new(addr.clone()),
+		}).collect();
+		self.mode = mode;
+		self.next_peer = 0;
+	}
+
+	pub fn mode(&self) -> SubmitMode {
+		self.mode
+	}
+
+	pub fn peer(&self, idx: usize) -> &Peer {
+		&self.peers[idx]
+	}
+
+	pub fn peer_mut(&mut self, idx: usize) -> &mut Peer {
+		&mut self.peers[idx]
+	}
+
+	pub fn addrs(&self) -> Vec<String> {
+		self.peers.iter().map(|peer| peer.addr.clone()).collect()
+	}
+
+	pub fn stats(&self) -> Vec<(String, Arc<PeerStats>)> {
+		self.peers.iter().map(|peer| (peer.addr.clone(), peer.stats.clone())).collect()
+	}
+
+	/// Indexes of the peers for the next submission.
+	/// Round-robin: the available peers starting from the next one in turn, then the failed ones by their retry time.
+	/// Broadcast: every available peer, or every peer when none is available
+	pub fn targets(&mut self, now: Instant) -> Vec<usize> {
+		let (available, mut failed): (Vec<usize>, Vec<usize>) = (0..self.peers.len())
+			.map(|offset| (self.next_peer + offset) % self.peers.len())
+			.partition(|&idx| self.peers[idx].is_available(now));
+
+		match self.mode {
+			SubmitMode::RoundRobin => {
+				self.next_peer = (self.next_peer + 1) % self.peers.len();
+				failed.sort_by_key(|&idx| self.peers[idx].retry_at);
+
+				available.into_iter().chain(failed).collect()
+			}
+			SubmitMode::Broadcast if available.is_empty() => failed,
+			SubmitMode::Broadcast => available,
+		}
+	}
+}
+
+#[cfg(test)]
+fn test_addrs(count: usize) -> Vec<String> {
+	(0..count).map(|idx| format!("127.0.0.{idx}:21841")).collect()
+}
+
+#[test]
+fn test_round_robin_targets() {
+	let mut peers = PeerSet::new(&test_addrs(3), SubmitMode::RoundRobin);
+	let now = Instant::now();
+
+	assert_eq!(vec![0, 1, 2], peers.targets(now));
+	assert_eq!(vec![1, 2, 0], peers.targets(now));
+
+	// Failed peers go last, the one failing longest at the very end
+	peers.peer_mut(0).record_failure();
+	peers.peer_mut(0).record_failure();
+	peers.peer_mut(2).record_failure();
+	assert_eq!(vec![1, 2, 0], peers.targets(Instant::now()));
+	assert!(peers.peer(2).stats.is_down.load(Ordering::Relaxed));
+
+	peers.peer_mut(2).record_success(1);
+	assert_eq!(vec![1, 2, 0], peers.targets(Instant::now()));
+	assert!(!peers.peer(2).stats.is_down.load(Ordering::Relaxed));
+	assert_eq!(1, peers.peer(2).stats.sent.load(Ordering::Relaxed));
+	assert_eq!(2, peers.peer(0).stats.failures.load(Ordering::Relaxed));
+}
+
+#[test]
+fn test_broadcast_targets() {
+	let mut peers = PeerSet::new(&test_addrs(3), SubmitMode::Broadcast);
+	assert_eq!(vec![0, 1, 2], peers.targets(Instant::now()));
+
+	peers.peer_mut(1).record_failure();
+	assert_eq!(vec![0, 2], peers.targets(Instant::now()));
+
+	peers.peer_mut(0).record_failure();
+	peers.peer_mut(2).record_failure();
+	assert_eq!(vec![0, 1, 2], peers.targets(Instant::now()));
+}
+
+#[test]
+fn test_update_peers() {
+	let mut peers = PeerSet::new(&test_addrs(3), SubmitMode::RoundRobin);
+	peers.peer_mut(2).record_failure();
+	let stats = peers.peer(2).stats.clone();
+
+	let addrs = vec![test_addrs(3)[2].clone(), "127.0.0.9:21841".to_string()];
+	peers.update(&addrs, SubmitMode::Broadcast);
+
+	assert_eq!(addrs, peers.addrs());
+	assert_eq!(SubmitMode::Broadcast, peers.mode());
+	assert!(Arc::ptr_eq(&stats, &peers.peer(0).stats));
+	assert!(!peers.peer(0).is_available(Instant::now()));
+	assert_eq!(vec![1], peers.targets(Instant::now()));
+}
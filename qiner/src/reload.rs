@@ -0,0 +1,202 @@
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+use lib::config::{Config, ConfigSources, RawConfig};
+use lib::env_names::{
+	ENV_NUMBER_OF_THREADS,
+	ENV_PEERS,
+	ENV_RANDOM_SEED,
+	ENV_RUST_LOG,
+	ENV_SOLUTION_THRESHOLD,
+	ENV_SUBMIT_MODE,
+};
+use tokio::sync::watch;
+use crate::entropy::new_worker_nonce_source;
+use crate::logger;
+use crate::miner::{Miner, Workers};
+use crate::peers::PeerConfig;
+
+/// How often the config files are checked for changes
+pub const RELOAD_INTERVAL: Duration = Duration::from_secs(2);
+
+/// The settings applied while mining, every other one takes a restart
+pub const LIVE_SETTINGS: [&str; 6] = [
+	ENV_NUMBER_OF_THREADS,
+	ENV_PEERS,
+	ENV_SUBMIT_MODE,
+	ENV_RANDOM_SEED,
+	ENV_SOLUTION_THRESHOLD,
+	ENV_RUST_LOG,
+];
+
+/// Applies the changed settings to the running miner
+pub struct LiveConfig {
+	/// What the miner runs with, the settings waiting for a restart keep their old value
+	config: Config,
+	miner: Arc<Miner>,
+	workers: Arc<Mutex<Workers>>,
+	peer_configs: watch::Sender<PeerConfig>,
+}
+
+impl LiveConfig {
+	pub fn new(config: Config, miner: Arc<Miner>, workers: Arc<Mutex<Workers>>) -> Self {
+		let peer_configs = watch::Sender::new(PeerConfig { addrs: config.peers.clone(), mode: config.submit_mode });
+
+		LiveConfig { config, miner, workers, peer_configs }
+	}
+
+	/// Follow it with [`crate::submission::Submitter::follow_peers`]
+	pub fn subscribe_peers(&self) -> watch::Receiver<PeerConfig> {
+		self.peer_configs.subscribe()
+	}
+
+	/// Applies what can change while mining and reports the rest, an invalid configuration changes nothing.
+	/// Blocks while stopped workers finish their iteration, so it needs a multi-threaded runtime
+	pub fn apply(&mut self, raw: &RawConfig) {
+		let new_config = match Config::from_raw(raw) {
+			Ok(config) => config,
+			Err(err) => {
+				log::error!("The changed configuration is not applied: {err}");
+				return;
+			}
+		};
+
+		let (live, restart): (Vec<&str>, Vec<&str>) = self.config.changed_settings(&new_config).into_iter()
+			.partition(|name| LIVE_SETTINGS.contains(name));
+		if !restart.is_empty() {
+			log::warn!("Changed settings which take a restart: {}", restart.join(", "));
+		}
+		if live.is_empty() {
+			return;
+		}
+		log::info!("Applying the changed settings: {}", live.join(", "));
+
+		if new_config.log_filter != self.config.log_filter {
+			logger::set_filter(new_config.log_filter.as_deref().unwrap_or_default());
+			self.config.log_filter = new_config.log_filter;
+		}
+
+		if new_config.peers != self.config.peers || new_config.submit_mode != self.config.submit_mode {
+			self.peer_configs.send_replace(PeerConfig { addrs: new_config.peers.clone(), mode: new_config.submit_mode });
+			self.config.peers = new_config.peers;
+			self.config.submit_mode = new_config.submit_mode;
+		}
+
+		if new_config.number_of_threads != self.config.number_of_threads {
+			let (nonce_source, mining_seed) = (self.config.nonce_source, self.config.mining_seed);
+			let mut workers = self.workers.lock().unwrap();
+			let result = tokio::task::block_in_place(|| workers.resize(new_config.number_of_threads, |idx| new_worker_nonce_source(nonce_source, mining_seed, idx)));
+			match result {
+				Ok(()) => log::info!("Number of threads: {} -> {}", self.config.number_of_threads, new_config.number_of_threads),
+				Err(err) => log::error!("Failed to start more threads: {err}, mining with {}", workers.len()),
+			}
+			self.config.number_of_threads = workers.len();
+		}
+
+		self.miner.update_epoch(&new_config.random_seed, new_config.solution_threshold);
+		self.config.random_seed = new_config.random_seed;
+		self.config.solution_threshold = new_config.solution_threshold;
+	}
+}
+
+/// Modification time and size of every file, `None` for a missing one
+fn get_file_states(files: &[PathBuf]) -> Vec<Option<(SystemTime, u64)>> {
+	files.iter()
+		.map(|file| fs::metadata(file).and_then(|metadata| Ok((metadata.modified()?, metadata.len()))).ok())
+		.collect()
+}
+
+/// Reads the settings again whenever a file of `sources` changes and passes them to `on_change`.
+/// A file which fails to load is reported and skipped until it changes again. Runs until it is dropped
+pub async fn watch_config(sources: ConfigSources, interval: Duration, mut on_change: impl FnMut(RawConfig)) {
+	let files = sources.files();
+	let mut file_states = get_file_states(&files);
+
+	loop {
+		tokio::time::sleep(interval).await;
+
+		let current_file_states = get_file_states(&files);
+		if current_file_states == file_states {
+			continue;
+		}
+		file_states = current_file_states;
+
+		match sources.load() {
+			Ok(raw) => on_change(raw),
+			Err(err) => log::error!("Failed to reload the configuration, keeping the current one: {err}"),
+		}
+	}
+}
+
+#[tokio::test]
+async fn test_watch_config() {
+	use lib::env_names::ENV_SOLUTION_THRESHOLD;
+	use crate::test_utils::TestFile;
+
+	let config_file = TestFile::new("watch.toml");
+	fs::write(config_file.path(), "solution_threshold = 20\n").unwrap();
+	let sources = ConfigSources::new(Some(config_file.path()), None, RawConfig::default(), RawConfig::default());
+
+	let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+	let watcher = tokio::spawn(watch_config(sources, Duration::from_millis(10), move |raw| sender.send(raw).unwrap()));
+	tokio::time::sleep(Duration::from_millis(50)).await;
+	assert!(receiver.try_recv().is_err());
+
+	// A broken file is skipped, the next change is picked up
+	fs::write(config_file.path(), "solution_threshold = [").unwrap();
+	tokio::time::sleep(Duration::from_millis(50)).await;
+	assert!(receiver.try_recv().is_err());
+
+	fs::write(config_file.path(), "solution_threshold = 25\n").unwrap();
+	let raw = tokio::time::timeout(Duration::from_secs(5), receiver.recv()).await.unwrap().unwrap();
+	assert_eq!(Some("25"), raw.get(ENV_SOLUTION_THRESHOLD));
+
+	watcher.abort();
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_live_config() {
+	use lib::env_names::{ENV_ID, ENV_SERVER_IP, ENV_VERSION};
+	use crate::journal::Journal;
+	use crate::test_utils::TestFile;
+	use crate::submission::submission_queue;
+	use crate::worker_state::WorkerPool;
+
+	let mut raw = RawConfig::default();
+	raw.set(ENV_NUMBER_OF_THREADS, "1");
+	raw.set(ENV_ID, crate::bench::BENCH_ID);
+	raw.set(ENV_SERVER_IP, "127.0.0.1");
+	raw.set(ENV_VERSION, "1.142.1");
+	raw.set(ENV_RANDOM_SEED, crate::bench::BENCH_RANDOM_SEED.map(|item| item.to_string()).join(","));
+	raw.set(ENV_SOLUTION_THRESHOLD, "1000");
+	let config = Config::from_raw(&raw).unwrap();
+
+	let journal_file = TestFile::new("live-config.journal");
+	let (solution_sender, _receiver) = submission_queue(Arc::new(Journal::open(journal_file.path()).unwrap()));
+	let miner = Arc::new(Miner::with_identities(&config.identities, config.id_schedule, &config.random_seed, config.solution_threshold));
+	let nonce_sources = vec![new_worker_nonce_source(config.nonce_source, Some(1), 0).unwrap()];
+	let workers = Arc::new(Mutex::new(Miner::run(&miner, nonce_sources, &Arc::new(WorkerPool::new(false)), &[], solution_sender)));
+	let mut live_config = LiveConfig::new(config, miner.clone(), workers.clone());
+	let peer_configs = live_config.subscribe_peers();
+
+	// A broken configuration changes nothing
+	raw.set(ENV_NUMBER_OF_THREADS, "0");
+	live_config.apply(&raw);
+	assert_eq!(1, workers.lock().unwrap().len());
+
+	raw.set(ENV_NUMBER_OF_THREADS, "2");
+	raw.set(ENV_PEERS, "127.0.0.2:2000");
+	raw.set(ENV_SOLUTION_THRESHOLD, "2000");
+	raw.set(ENV_ID, "BZBQFLLBNCXEMGLOBHUVFTLUPLVCPQUASSILFABOFFBCADQSSUPNWLZBQEXK");
+	live_config.apply(&raw);
+
+	assert_eq!(2, workers.lock().unwrap().len());
+	assert_eq!(vec!["127.0.0.1:21841", "127.0.0.2:2000"], peer_configs.borrow().addrs);
+	assert_eq!(2000, miner.get_solution_threshold());
+	// The ID takes a restart
+	assert_ne!(live_config.config.id, Config::from_raw(&raw).unwrap().id);
+
+	miner.stop();
+	assert_eq!(0, workers.lock().unwrap().join());
+}
@@ -0,0 +1,165 @@
+use std::mem::zeroed;
+use crate::worker_state::AlignedBuffer;
+use lib::types::{
+	MiningItemData,
+	MiningData,
+	NeuronLink,
+	NeuronLink64,
+	NeuronValue,
+	Nonce64,
+	PublicKey64,
+	Seed,
+	Seed64,
+	MINING_DATA_LENGTH,
+	NEURON_MOD_BITS,
+	NUMBER_OF_NEURONS,
+	NUMBER_OF_NEURONS_64,
+};
+
+/// Neuron buffers of one worker, about 36 MB
+#[derive(Debug)]
+pub struct NeuronData {
+	neuron_links: AlignedBuffer<NeuronLink64>,
+	neuron_values: AlignedBuffer<NeuronValue>,
+}
+
+impl NeuronData {
+	/// On Linux the buffers are not touched here, so on NUMA machines their pages are placed
+	/// on the node of the thread that scores first
+	pub fn new(huge_pages: bool) -> Self {
+		NeuronData {
+			neuron_links: AlignedBuffer::zeroed(NUMBER_OF_NEURONS_64 * 2, huge_pages),
+			neuron_values: AlignedBuffer::zeroed(NUMBER_OF_NEURONS, huge_pages),
+		}
+	}
+
+	pub fn reset(&mut self) {
+		self.neuron_values.fill(NeuronValue::MAX);
+	}
+
+	pub fn allocated_size(&self) -> usize {
+		self.neuron_links.allocated_size() + self.neuron_values.allocated_size()
+	}
+
+	/// What [`NeuronData::allocated_size`] is, without allocating
+	pub fn size_for(huge_pages: bool) -> usize {
+		AlignedBuffer::<NeuronLink64>::size_for(NUMBER_OF_NEURONS_64 * 2, huge_pages) + AlignedBuffer::<NeuronValue>::size_for(NUMBER_OF_NEURONS, huge_pages)
+	}
+
+	#[cfg(test)]
+	pub(crate) fn links_ptr(&self) -> *const NeuronLink64 {
+		self.neuron_links.as_ptr()
+	}
+}
+
+impl Default for NeuronData {
+	fn default() -> Self {
+		NeuronData::new(false)
+	}
+}
+
+/// Mining data the scores of an epoch are checked against
+pub fn get_mining_data(random_seed: &Seed) -> MiningData {
+	let random_seed = get_random_seed_64(random_seed);
+
+	// Zeroed mining data
+	let mut mining_data: MiningData;
+	unsafe {
+		mining_data = zeroed::<MiningData>();
+	}
+
+	crate::math::random_64(&random_seed, &random_seed, &mut mining_data);
+
+	mining_data
+}
+
+fn get_random_seed_64(seed: &Seed) -> Seed64 {
+	let seed_64: Seed64;
+	unsafe {
+		seed_64 = std::mem::transmute::<Seed, Seed64>(*seed);
+	}
+
+	seed_64
+}
+
+/// Score of a nonce. Allocates the neuron buffers, use [`score_with_buffers`] in loops
+pub fn score(public_key: &PublicKey64, nonce: &Nonce64, mining_data: &MiningData) -> usize {
+	score_with_buffers(public_key, nonce, mining_data, &mut NeuronData::default())
+}
+
+/// Score of a nonce. The result does not depend on what `neuron_data` held before
+pub fn score_with_buffers(public_key: &PublicKey64, nonce: &Nonce64, mining_data: &MiningData, neuron_data: &mut NeuronData) -> usize {
+	crate::math::random_64(public_key, nonce, &mut neuron_data.neuron_links);
+
+	for idx in 0..NUMBER_OF_NEURONS_64 {
+		neuron_data.neuron_links[idx] &= NEURON_MOD_BITS;
+		neuron_data.neuron_links[NUMBER_OF_NEURONS_64 + idx] &= NEURON_MOD_BITS;
+	}
+
+	neuron_data.reset();
+
+	let mut limit = MINING_DATA_LENGTH;
+	let mut score: usize = 0;
+
+	loop {
+		let prev_value0 = neuron_data.neuron_values[NUMBER_OF_NEURONS - 1];
+		let prev_value1 = neuron_data.neuron_values[NUMBER_OF_NEURONS - 2];
+
+		for idx in 0..NUMBER_OF_NEURONS_64 {
+			let idx_left = idx * 2; //[j][0]
+			let idx_right = idx * 2 + 1; // [j][1]
+			let value_idx = idx * 2;
+
+			let nv_l0 = (neuron_data.neuron_links[idx_left] as NeuronLink) as usize; // neuronValues[neuronLinks[j][0]]
+			let nv_r0 = ((neuron_data.neuron_links[idx_left] >> NeuronLink::BITS) as NeuronLink) as usize; // neuronValues[neuronLinks[j][1]]
+
+			let nv_l1 = (neuron_data.neuron_links[idx_right] as NeuronLink) as usize; // neuronValues[neuronLinks[j + 1][0]]
+			let nv_r1 = ((neuron_data.neuron_links[idx_right] >> NeuronLink::BITS) as NeuronLink) as usize; // neuronValues[neuronLinks[j + 1][1]]
+
+			let and_result0 = neuron_data.neuron_values[nv_l0] & neuron_data.neuron_values[nv_r0];
+			let and_result1 = neuron_data.neuron_values[nv_l1] & neuron_data.neuron_values[nv_r1];
+			neuron_data.neuron_values[value_idx] = !(and_result0);
+			neuron_data.neuron_values[value_idx + 1] = !(and_result1);
+		}
+
+		let current_value0 = neuron_data.neuron_values[NUMBER_OF_NEURONS - 1];
+		let current_value1 = neuron_data.neuron_values[NUMBER_OF_NEURONS - 2];
+
+		let data_of_mining = mining_data[score >> 6];
+		let is_bit_set = ((data_of_mining >> (score & 63) as MiningItemData) & 1) as u8;
+		if current_value0 != prev_value0 && current_value1 == prev_value1 {
+			if is_bit_set == 0 {
+				break;
+			}
+
+			score += 1;
+		} else if current_value1 != prev_value1 && current_value0 == prev_value0 {
+			if is_bit_set == 1 {
+				break;
+			}
+
+			score += 1;
+		} else {
+			limit -= 1;
+
+			if limit == 0 {
+				break;
+			}
+		}
+	}
+
+	score
+}
+
+#[test]
+fn test_score_does_not_depend_on_buffers() {
+	let public_key = crate::bench::get_bench_public_key();
+	let mining_data = get_mining_data(&crate::bench::BENCH_RANDOM_SEED);
+	let nonce = crate::converters::get_64_from_hex("81f1e22ffc6ff067289dd2b5a78108754f56f02c21fa1abcd63a10afdaa3d230").unwrap();
+
+	let mut dirty_neuron_data = NeuronData::default();
+	dirty_neuron_data.neuron_values.fill(0);
+
+	assert_eq!(2, score(&public_key, &nonce, &mining_data));
+	assert_eq!(2, score_with_buffers(&public_key, &nonce, &mining_data, &mut dirty_neuron_data));
+}
@@ -0,0 +1,220 @@
+pub mod fourq;
+
+use std::fmt;
+use std::fmt::Formatter;
+use k12::digest::{ExtendableOutputReset, Update};
+use k12::KangarooTwelve;
+use lib::config::SigningSeed;
+use lib::types::{PublicKey64, Signature};
+use crate::signing::fourq::{scalar_from_bytes, scalar_from_canonical_bytes, scalar_mul, scalar_sub_mod, scalar_to_bytes, Bytes32, Point, Scalar, COFACTOR};
+
+pub type Digest = [u8; 32];
+pub type SignatureBytes = [u8; 64];
+
+pub fn k12(data: &[u8], output: &mut [u8]) {
+	let mut kangaroo_twelve = KangarooTwelve::default();
+	kangaroo_twelve.update(data);
+	kangaroo_twelve.finalize_xof_reset_into(output);
+}
+
+/// The K12 digest of a message, which is what gets signed
+pub fn get_digest(data: &[u8]) -> Digest {
+	let mut digest = Digest::default();
+	k12(data, &mut digest);
+
+	digest
+}
+
+pub fn get_public_key_64(bytes: &Bytes32) -> PublicKey64 {
+	let mut public_key = PublicKey64::default();
+	public_key.iter_mut().zip(bytes.chunks_exact(8)).for_each(|(item, chunk)| *item = u64::from_le_bytes(chunk.try_into().unwrap()));
+
+	public_key
+}
+
+pub fn get_public_key_bytes(public_key: &PublicKey64) -> Bytes32 {
+	let mut bytes = Bytes32::default();
+	bytes.chunks_exact_mut(8).zip(public_key).for_each(|(chunk, item)| chunk.copy_from_slice(&item.to_le_bytes()));
+
+	bytes
+}
+
+pub fn get_signature_64(bytes: &SignatureBytes) -> Signature {
+	let mut signature = Signature::default();
+	signature.iter_mut().zip(bytes.chunks_exact(8)).for_each(|(item, chunk)| *item = u64::from_le_bytes(chunk.try_into().unwrap()));
+
+	signature
+}
+
+pub fn get_signature_bytes(signature: &Signature) -> SignatureBytes {
+	let mut bytes = [0u8; 64];
+	bytes.chunks_exact_mut(8).zip(signature).for_each(|(chunk, item)| chunk.copy_from_slice(&item.to_le_bytes()));
+
+	bytes
+}
+
+/// SchnorrQ key pair of a Qubic identity, derived from its seed the way Qubic wallets do:
+/// subseed = K12(seed letters as 0..25), private key = K12(subseed), public key = private key * G
+#[derive(Clone)]
+pub struct KeyPair {
+	subseed: [u8; 32],
+	private_key: Scalar,
+	public_key: Bytes32,
+}
+
+// The secrets stay out of the logs
+impl fmt::Debug for KeyPair {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		f.debug_struct("KeyPair").field("public_key", &hex::encode(self.public_key)).finish_non_exhaustive()
+	}
+}
+
+impl KeyPair {
+	/// The seed is checked as [`SigningSeed::parse`] does
+	pub fn from_seed(seed: &str) -> Result<Self, String> {
+		Ok(KeyPair::from_signing_seed(&SigningSeed::parse(seed)?))
+	}
+
+	pub fn from_signing_seed(seed: &SigningSeed) -> Self {
+		let seed_values = seed.expose().bytes().map(|letter| letter - b'a').collect::<Vec<u8>>();
+		let mut subseed = [0u8; 32];
+		k12(&seed_values, &mut subseed);
+
+		KeyPair::from_subseed(subseed)
+	}
+
+	pub fn from_subseed(subseed: [u8; 32]) -> Self {
+		let mut private_key = Bytes32::default();
+		k12(&subseed, &mut private_key);
+		let private_key = scalar_from_bytes(&private_key);
+
+		KeyPair {
+			subseed,
+			private_key,
+			public_key: Point::generator().mul(&private_key).encode(),
+		}
+	}
+
+	pub fn public_key(&self) -> PublicKey64 {
+		get_public_key_64(&self.public_key)
+	}
+
+	/// SchnorrQ with K12: r = K12(K12(subseed)[32..64] || digest), R = r * G, h = K12(R || public key || digest),
+	/// s = r - h * private key. The signature is R || s
+	pub fn sign(&self, digest: &Digest) -> SignatureBytes {
+		let mut k = [0u8; 64];
+		k12(&self.subseed, &mut k);
+
+		let mut nonce_input = [0u8; 64];
+		nonce_input[..32].copy_from_slice(&k[32..]);
+		nonce_input[32..].copy_from_slice(digest);
+		let mut r = [0u8; 64];
+		k12(&nonce_input, &mut r);
+		let r = scalar_from_bytes(&r);
+
+		let mut signature = [0u8; 64];
+		signature[..32].copy_from_slice(&Point::generator().mul(&r).encode());
+		let h = get_challenge(&signature[..32], &self.public_key, digest);
+		signature[32..].copy_from_slice(&scalar_to_bytes(&scalar_sub_mod(&r, &scalar_mul(&h, &self.private_key))));
+
+		signature
+	}
+
+	/// Diffie-Hellman agreement as Qubic's getSharedKey: the y-coordinate of private key * cofactor * A,
+	/// A being the other public key. The other side gets the same from its private key and our public key.
+	/// `None` when the public key is no point of the curve or the result is the identity
+	pub fn get_shared_key(&self, public_key: &PublicKey64) -> Option<Bytes32> {
+		let public_key_point = Point::decode(&get_public_key_bytes(public_key))?;
+		let shared_point = public_key_point.mul(&COFACTOR).mul(&self.private_key);
+		if shared_point.is_identity() {
+			return None;
+		}
+
+		Some(shared_point.encode_y())
+	}
+}
+
+/// h = K12(R || public key || digest), its first 32 Bytes modulo the group order
+fn get_challenge(encoded_r: &[u8], public_key: &Bytes32, digest: &Digest) -> Scalar {
+	let mut challenge_input = [0u8; 96];
+	challenge_input[..32].copy_from_slice(encoded_r);
+	challenge_input[32..64].copy_from_slice(public_key);
+	challenge_input[64..].copy_from_slice(digest);
+	let mut h = [0u8; 64];
+	k12(&challenge_input, &mut h);
+
+	scalar_from_bytes(&h)
+}
+
+/// Checks that s * G + h * A encodes to R
+pub fn verify(public_key: &PublicKey64, digest: &Digest, signature: &SignatureBytes) -> bool {
+	let public_key = get_public_key_bytes(public_key);
+	let Some(public_key_point) = Point::decode(&public_key) else {
+		return false;
+	};
+	let Some(s) = scalar_from_canonical_bytes(signature[32..].try_into().unwrap()) else {
+		return false;
+	};
+
+	let h = get_challenge(&signature[..32], &public_key, digest);
+	let r = Point::generator().mul(&s).add(&public_key_point.mul(&h));
+
+	r.encode() == signature[..32]
+}
+
+#[cfg(test)]
+const TEST_SEED: &str = "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+
+#[test]
+fn test_key_pair_from_seed() {
+	assert_eq!(Err("has 3 characters instead of 55".to_string()), KeyPair::from_seed("abc").map(|key_pair| key_pair.public_key()));
+	assert_eq!(Err("may only have lowercase letters a-z".to_string()), KeyPair::from_seed(&TEST_SEED.replacen('a', "A", 1)).map(|key_pair| key_pair.public_key()));
+
+	// The identity Qubic wallets show for this seed
+	let key_pair = KeyPair::from_seed(TEST_SEED).unwrap();
+	let mut id = [0u8; 60];
+	lib::converters::get_id_from_public_key_64(&key_pair.public_key(), &mut id);
+	assert_eq!(b"BZBQFLLBNCXEMGLOBHUVFTLUPLVCPQUASSILFABOFFBCADQSSUPNWLZBQEXK", &id);
+	assert_ne!(key_pair.public_key(), KeyPair::from_seed(&TEST_SEED.replacen('a', "b", 1)).unwrap().public_key());
+	assert!(Point::decode(&key_pair.public_key).is_some());
+	assert!(!format!("{key_pair:?}").contains(&hex::encode(key_pair.subseed)));
+}
+
+#[test]
+fn test_sign_and_verify() {
+	let key_pair = KeyPair::from_seed(TEST_SEED).unwrap();
+	let digest = get_digest(b"solution");
+	let signature = key_pair.sign(&digest);
+
+	assert_eq!(signature, key_pair.sign(&digest));
+	assert!(verify(&key_pair.public_key(), &digest, &signature));
+
+	// Pinned, so that a change of the arithmetic shows up
+	assert_eq!(
+		"7395cf554d3ced671d83704ad203c732670aebeb926398428d28edd495e4208a974b3d7c53ac368615d8fc87699db661d2a7a91e28bd26df388c80cc21b51100",
+		hex::encode(key_pair.sign(&[0; 32])),
+	);
+	assert_eq!(signature, get_signature_bytes(&get_signature_64(&signature)));
+
+	assert!(!verify(&key_pair.public_key(), &get_digest(b"other solution"), &signature));
+	let other_key_pair = KeyPair::from_seed(&TEST_SEED.replacen('a', "z", 1)).unwrap();
+	assert!(!verify(&other_key_pair.public_key(), &digest, &signature));
+
+	let mut tampered = signature;
+	tampered[40] ^= 1;
+	assert!(!verify(&key_pair.public_key(), &digest, &tampered));
+}
+
+#[test]
+fn test_shared_key() {
+	let key_pair = KeyPair::from_seed(TEST_SEED).unwrap();
+	let other_key_pair = KeyPair::from_seed(&TEST_SEED.replacen('a', "z", 1)).unwrap();
+
+	let shared_key = key_pair.get_shared_key(&other_key_pair.public_key()).unwrap();
+	assert_eq!(Some(shared_key), other_key_pair.get_shared_key(&key_pair.public_key()));
+	assert_ne!(shared_key, key_pair.get_shared_key(&key_pair.public_key()).unwrap());
+	assert_eq!(0, shared_key[31] & 0x80);
+
+	// [1, 0, 0, 0] encodes the identity, which agrees on no key
+	assert_eq!(None, key_pair.get_shared_key(&[1, 0, 0, 0]));
+}
@@ -0,0 +1,407 @@
+//! The FourQ curve as used by Qubic's SchnorrQ signatures: the twisted Edwards curve
+//! `-x^2 + y^2 = 1 + d x^2 y^2` over GF(p^2), p = 2^127 - 1, with the encoding of FourQlib.
+//!
+//! Straightforward arithmetic without the endomorphisms of FourQlib. The field and scalar operations
+//! and the scalar multiplication do not branch on secret data, point decoding does as it only sees public keys
+
+/// p = 2^127 - 1
+const P: u128 = (1 << 127) - 1;
+
+/// Order of the prime subgroup generated by [`Point::generator`], little-endian limbs
+const ORDER: Scalar = [0x2FB2540EC7768CE7, 0xDFBD004DFE0F7999, 0xF05397829CBC14E5, 0x0029CBC14E5E0A72];
+
+/// The curve has 392 times as many points as the prime subgroup
+pub const COFACTOR: Scalar = [392, 0, 0, 0];
+
+const PARAMETER_D: Fp2 = Fp2(0x00000000000000E40000000000000142, 0x5E472F846657E0FCB3821488F1FC0C8D);
+
+const GENERATOR_X: Fp2 = Fp2(0x1A3472237C2FB305286592AD7B3833AA, 0x1E1F553F2878AA9C96869FB360AC77F6);
+const GENERATOR_Y: Fp2 = Fp2(0x0E3FEE9BA120785AB924A2462BCBB287, 0x6E1C4AF8630E024249A7C344844C8B5C);
+
+/// Encoded point or scalar
+pub type Bytes32 = [u8; 32];
+
+/// Integer modulo the group order, little-endian limbs
+pub type Scalar = [u64; 4];
+
+/// Bit 126 of x0, or of x1 when x0 is zero
+fn get_x_sign(x: Fp2) -> u128 {
+	let sign_source = if x.0 == 0 { x.1 } else { x.0 };
+
+	(sign_source >> 126) & 1
+}
+
+#[inline]
+fn select_u128(mask: u128, if_set: u128, if_clear: u128) -> u128 {
+	(if_set & mask) | (if_clear & !mask)
+}
+
+/// Reduces a value below 2^128 to [0, p)
+#[inline]
+fn fp_reduce(value: u128) -> u128 {
+	let value = (value & P) + (value >> 127);
+	let (reduced, borrow) = value.overflowing_sub(P);
+
+	select_u128((borrow as u128).wrapping_neg(), value, reduced)
+}
+
+#[inline]
+fn fp_add(a: u128, b: u128) -> u128 {
+	fp_reduce(a + b)
+}
+
+#[inline]
+fn fp_sub(a: u128, b: u128) -> u128 {
+	fp_reduce(a + (P - b))
+}
+
+#[inline]
+fn fp_mul(a: u128, b: u128) -> u128 {
+	let (a0, a1) = (a as u64 as u128, a >> 64);
+	let (b0, b1) = (b as u64 as u128, b >> 64);
+
+	let low = a0 * b0;
+	let middle = a0 * b1 + a1 * b0;
+	let (low, carry) = low.overflowing_add(middle << 64);
+	let high = a1 * b1 + (middle >> 64) + carry as u128;
+
+	// 2^128 = 2 (mod p)
+	fp_reduce(fp_reduce(low) + 2 * high)
+}
+
+#[inline]
+fn fp_square_times(mut a: u128, times: u32) -> u128 {
+	for _ in 0..times {
+		a = fp_mul(a, a);
+	}
+
+	a
+}
+
+/// a^(p - 2) = a^(2^127 - 3)
+fn fp_invert(a: u128) -> u128 {
+	// a^(2^125 - 1)
+	let mut power = a;
+	for _ in 1..125 {
+		power = fp_mul(fp_mul(power, power), a);
+	}
+
+	// a^(2^127 - 4) * a
+	fp_mul(fp_square_times(power, 2), a)
+}
+
+/// a^((p + 1) / 4) = a^(2^125), a root when `a` is a square
+fn fp_sqrt(a: u128) -> Option<u128> {
+	let root = fp_square_times(a, 125);
+
+	(fp_mul(root, root) == a).then_some(root)
+}
+
+/// a0 + a1 * i with i^2 = -1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fp2(u128, u128);
+
+impl Fp2 {
+	const ZERO: Fp2 = Fp2(0, 0);
+	const ONE: Fp2 = Fp2(1, 0);
+
+	fn add(self, other: Fp2) -> Fp2 {
+		Fp2(fp_add(self.0, other.0), fp_add(self.1, other.1))
+	}
+
+	fn sub(self, other: Fp2) -> Fp2 {
+		Fp2(fp_sub(self.0, other.0), fp_sub(self.1, other.1))
+	}
+
+	fn neg(self) -> Fp2 {
+		Fp2::ZERO.sub(self)
+	}
+
+	fn mul(self, other: Fp2) -> Fp2 {
+		Fp2(
+			fp_sub(fp_mul(self.0, other.0), fp_mul(self.1, other.1)),
+			fp_add(fp_mul(self.0, other.1), fp_mul(self.1, other.0)),
+		)
+	}
+
+	fn square(self) -> Fp2 {
+		self.mul(self)
+	}
+
+	fn invert(self) -> Fp2 {
+		let norm_inverse = fp_invert(fp_add(fp_mul(self.0, self.0), fp_mul(self.1, self.1)));
+
+		Fp2(fp_mul(self.0, norm_inverse), fp_sub(0, fp_mul(self.1, norm_inverse)))
+	}
+
+	fn sqrt(self) -> Option<Fp2> {
+		let half = fp_invert(2);
+		let root = if self.1 == 0 {
+			match fp_sqrt(self.0) {
+				Some(root) => Fp2(root, 0),
+				None => Fp2(0, fp_sqrt(fp_sub(0, self.0))?),
+			}
+		} else {
+			let norm_root = fp_sqrt(fp_add(fp_mul(self.0, self.0), fp_mul(self.1, self.1)))?;
+			let x0 = fp_sqrt(fp_mul(fp_add(self.0, norm_root), half))
+				.or_else(|| fp_sqrt(fp_mul(fp_sub(self.0, norm_root), half)))?;
+
+			Fp2(x0, fp_mul(self.1, fp_invert(fp_add(x0, x0))))
+		};
+
+		(root.square() == self).then_some(root)
+	}
+
+	fn select(mask: u128, if_set: Fp2, if_clear: Fp2) -> Fp2 {
+		Fp2(select_u128(mask, if_set.0, if_clear.0), select_u128(mask, if_set.1, if_clear.1))
+	}
+}
+
+/// Point in extended twisted Edwards coordinates: x = X/Z, y = Y/Z, T = XY/Z
+#[derive(Debug, Clone, Copy)]
+pub struct Point {
+	x: Fp2,
+	y: Fp2,
+	z: Fp2,
+	t: Fp2,
+}
+
+impl Point {
+	pub const IDENTITY: Point = Point { x: Fp2::ZERO, y: Fp2::ONE, z: Fp2::ONE, t: Fp2::ZERO };
+
+	pub fn generator() -> Point {
+		Point { x: GENERATOR_X, y: GENERATOR_Y, z: Fp2::ONE, t: GENERATOR_X.mul(GENERATOR_Y) }
+	}
+
+	/// Complete addition for a = -1, it holds for doubling and the identity too
+	pub fn add(&self, other: &Point) -> Point {
+		let a = self.y.sub(self.x).mul(other.y.sub(other.x));
+		let b = self.y.add(self.x).mul(other.y.add(other.x));
+		let c = self.t.mul(PARAMETER_D.add(PARAMETER_D)).mul(other.t);
+		let d = self.z.add(self.z).mul(other.z);
+		let (e, f, g, h) = (b.sub(a), d.sub(c), d.add(c), b.add(a));
+
+		Point { x: e.mul(f), y: g.mul(h), z: f.mul(g), t: e.mul(h) }
+	}
+
+	/// `scalar` times the point, the same operations whatever the bits of the scalar
+	pub fn mul(&self, scalar: &Scalar) -> Point {
+		let mut result = Point::IDENTITY;
+		for bit_idx in (0..256).rev() {
+			result = result.add(&result);
+			let sum = result.add(self);
+			let mask = (((scalar[bit_idx / 64] >> (bit_idx % 64)) & 1) as u128).wrapping_neg();
+
+			result = Point {
+				x: Fp2::select(mask, sum.x, result.x),
+				y: Fp2::select(mask, sum.y, result.y),
+				z: Fp2::select(mask, sum.z, result.z),
+				t: Fp2::select(mask, sum.t, result.t),
+			};
+		}
+
+		result
+	}
+
+	pub fn is_on_curve(&self) -> bool {
+		let (x, y) = self.affine();
+		let (x2, y2) = (x.square(), y.square());
+
+		y2.sub(x2) == Fp2::ONE.add(PARAMETER_D.mul(x2).mul(y2))
+	}
+
+	pub fn is_identity(&self) -> bool {
+		let (x, y) = self.affine();
+
+		x == Fp2::ZERO && y == Fp2::ONE
+	}
+
+	fn affine(&self) -> (Fp2, Fp2) {
+		let z_inverse = self.z.invert();
+
+		(self.x.mul(z_inverse), self.y.mul(z_inverse))
+	}
+
+	/// FourQlib encoding: y0 and y1 in little-endian 16 Bytes each, the top bit holds the sign of x,
+	/// bit 126 of x0 or of x1 when x0 is zero
+	pub fn encode(&self) -> Bytes32 {
+		let (x, y) = self.affine();
+		let sign = get_x_sign(x);
+
+		let mut bytes = Bytes32::default();
+		bytes[..16].copy_from_slice(&y.0.to_le_bytes());
+		bytes[16..].copy_from_slice(&y.1.to_le_bytes());
+		bytes[31] |= (sign as u8) << 7;
+
+		bytes
+	}
+
+	/// y0 and y1 without the sign of x, what FourQlib's secret agreement outputs
+	pub fn encode_y(&self) -> Bytes32 {
+		let mut bytes = self.encode();
+		bytes[31] &= 0x7F;
+
+		bytes
+	}
+
+	/// `None` for bytes which are no encoded point of the curve
+	pub fn decode(bytes: &Bytes32) -> Option<Point> {
+		let y0 = u128::from_le_bytes(bytes[..16].try_into().unwrap());
+		let y1 = u128::from_le_bytes(bytes[16..].try_into().unwrap());
+		let sign = y1 >> 127;
+		let y = Fp2(y0, y1 & P);
+		if y0 >= P || y.1 >= P {
+			return None;
+		}
+
+		// x^2 = (y^2 - 1) / (d y^2 + 1)
+		let y2 = y.square();
+		let mut x = y2.sub(Fp2::ONE).mul(PARAMETER_D.mul(y2).add(Fp2::ONE).invert()).sqrt()?;
+		if get_x_sign(x) != sign {
+			x = x.neg();
+		}
+
+		let point = Point { x, y, z: Fp2::ONE, t: x.mul(y) };
+		(point.encode() == *bytes).then_some(point)
+	}
+}
+
+/// a - b, and whether it borrowed
+fn scalar_sub(a: &Scalar, b: &Scalar) -> (Scalar, bool) {
+	let mut result = Scalar::default();
+	let mut borrow = false;
+	for idx in 0..4 {
+		let (value, borrow_a) = a[idx].overflowing_sub(b[idx]);
+		let (value, borrow_b) = value.overflowing_sub(borrow as u64);
+		result[idx] = value;
+		borrow = borrow_a | borrow_b;
+	}
+
+	(result, borrow)
+}
+
+fn scalar_select(mask: u64, if_set: &Scalar, if_clear: &Scalar) -> Scalar {
+	let mut result = Scalar::default();
+	for idx in 0..4 {
+		result[idx] = (if_set[idx] & mask) | (if_clear[idx] & !mask);
+	}
+
+	result
+}
+
+/// Remainder of the little-endian `limbs` by the group order, bit by bit
+fn reduce_limbs(limbs: &[u64]) -> Scalar {
+	let mut remainder = Scalar::default();
+	for bit_idx in (0..limbs.len() * 64).rev() {
+		// The remainder stays below the order, under 2^247 even doubled
+		remainder[3] = (remainder[3] << 1) | (remainder[2] >> 63);
+		remainder[2] = (remainder[2] << 1) | (remainder[1] >> 63);
+		remainder[1] = (remainder[1] << 1) | (remainder[0] >> 63);
+		remainder[0] = (remainder[0] << 1) | ((limbs[bit_idx / 64] >> (bit_idx % 64)) & 1);
+
+		let (reduced, borrow) = scalar_sub(&remainder, &ORDER);
+		remainder = scalar_select((borrow as u64).wrapping_neg(), &remainder, &reduced);
+	}
+
+	remainder
+}
+
+/// The first 32 Bytes read as a little-endian integer, modulo the group order
+pub fn scalar_from_bytes(bytes: &[u8]) -> Scalar {
+	let mut limbs = Scalar::default();
+	limbs.iter_mut().zip(bytes[..32].chunks_exact(8)).for_each(|(limb, chunk)| *limb = u64::from_le_bytes(chunk.try_into().unwrap()));
+
+	reduce_limbs(&limbs)
+}
+
+pub fn scalar_to_bytes(scalar: &Scalar) -> Bytes32 {
+	let mut bytes = Bytes32::default();
+	bytes.chunks_exact_mut(8).zip(scalar).for_each(|(chunk, limb)| chunk.copy_from_slice(&limb.to_le_bytes()));
+
+	bytes
+}
+
+/// `None` for bytes not below the group order
+pub fn scalar_from_canonical_bytes(bytes: &Bytes32) -> Option<Scalar> {
+	let mut limbs = Scalar::default();
+	limbs.iter_mut().zip(bytes.chunks_exact(8)).for_each(|(limb, chunk)| *limb = u64::from_le_bytes(chunk.try_into().unwrap()));
+
+	scalar_sub(&limbs, &ORDER).1.then_some(limbs)
+}
+
+pub fn scalar_mul(a: &Scalar, b: &Scalar) -> Scalar {
+	let mut product = [0u64; 8];
+	for (a_idx, a_limb) in a.iter().enumerate() {
+		let mut carry = 0u128;
+		for (b_idx, b_limb) in b.iter().enumerate() {
+			let value = *a_limb as u128 * *b_limb as u128 + product[a_idx + b_idx] as u128 + carry;
+			product[a_idx + b_idx] = value as u64;
+			carry = value >> 64;
+		}
+		product[a_idx + 4] = carry as u64;
+	}
+
+	reduce_limbs(&product)
+}
+
+/// a - b modulo the group order, both below it
+pub fn scalar_sub_mod(a: &Scalar, b: &Scalar) -> Scalar {
+	let (difference, borrow) = scalar_sub(a, b);
+	let order_if_borrowed = scalar_select((borrow as u64).wrapping_neg(), &ORDER, &[0; 4]);
+
+	// Adding the order back wraps around 2^256 to the positive remainder
+	let mut result = Scalar::default();
+	let mut carry = 0u128;
+	for idx in 0..4 {
+		let value = difference[idx] as u128 + order_if_borrowed[idx] as u128 + carry;
+		result[idx] = value as u64;
+		carry = value >> 64;
+	}
+
+	result
+}
+
+#[test]
+fn test_field_arithmetic() {
+	assert_eq!(0, fp_reduce(P));
+	assert_eq!(1, fp_mul(P - 1, P - 1));
+	assert_eq!(1, fp_mul(fp_invert(12345), 12345));
+	assert_eq!(Some(9), fp_sqrt(81).map(|root| root.min(P - root)));
+
+	let a = Fp2(0x1234_5678_9abc_def0_1122_3344_5566_7788, 0x0fed_cba9_8765_4321_0102_0304_0506_0708);
+	assert_eq!(Fp2::ONE, a.mul(a.invert()));
+	let root = a.square().sqrt().unwrap();
+	assert!(root == a || root == a.neg());
+	assert_eq!(Fp2(P - 1, 0), Fp2(0, 1).square());
+}
+
+#[test]
+fn test_curve_parameters() {
+	let generator = Point::generator();
+	assert!(generator.is_on_curve());
+	assert!(generator.mul(&ORDER).is_identity());
+	assert!(!generator.mul(&[1, 0, 0, 0]).is_identity());
+
+	let point = generator.mul(&[0x0123_4567_89ab_cdef, 42, 0, 7]);
+	assert!(point.is_on_curve());
+	let decoded = Point::decode(&point.encode()).unwrap();
+	assert_eq!(point.encode(), decoded.encode());
+	assert!(decoded.is_on_curve());
+}
+
+#[test]
+fn test_scalar_arithmetic() {
+	assert_eq!([0; 4], reduce_limbs(&ORDER));
+	assert_eq!([5, 0, 0, 0], reduce_limbs(&[ORDER[0] + 5, ORDER[1], ORDER[2], ORDER[3]]));
+
+	let minus_one = scalar_sub_mod(&[0; 4], &[1, 0, 0, 0]);
+	assert_eq!(scalar_sub(&ORDER, &[1, 0, 0, 0]).0, minus_one);
+	assert_eq!([1, 0, 0, 0], scalar_mul(&minus_one, &minus_one));
+	assert_eq!([6, 0, 0, 0], scalar_mul(&[2, 0, 0, 0], &[3, 0, 0, 0]));
+
+	// (a * b) G = a (b G)
+	let generator = Point::generator();
+	let (a, b) = ([7, 0, 9, 0], [0, 3, 0, 11]);
+	assert_eq!(generator.mul(&scalar_mul(&a, &b)).encode(), generator.mul(&b).mul(&a).encode());
+	assert!(scalar_from_canonical_bytes(&scalar_to_bytes(&ORDER)).is_none());
+}
@@ -0,0 +1,779 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc, watch};
+use tokio::sync::mpsc::error::SendError;
+use tokio::task::JoinSet;
+use tokio::time::Instant;
+use lib::config::SubmitMode;
+use lib::types::network::Protocol;
+use lib::types::network::protocols::BROADCAST_MESSAGE;
+use lib::types::Signature;
+use crate::converters::{get_hex_from_64, get_id_string};
+use crate::entropy::NonceSource;
+use crate::journal::{Journal, SolutionState};
+use crate::miner::{Epoch, Solution};
+use crate::network::Packet;
+use crate::network::connection::{ConnectionEvent, ConnectionManager, ConnectionState};
+use crate::network::frame::{PeerMessage, ReceiveStats};
+use crate::peers::{PeerConfig, PeerSet, PeerStats};
+use crate::signing::KeyPair;
+#[cfg(test)]
+use crate::test_utils::test_solution;
+
+/// Solutions which may wait in the channel between the workers and the submitter
+pub const SUBMISSION_QUEUE_CAPACITY: usize = 1024;
+
+pub const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(1);
+pub const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// Sent solutions waiting for a peer to relay them back, the oldest are given up on beyond it
+const MAX_UNACKNOWLEDGED: usize = 1024;
+
+/// Creates the channel between the mining threads and the [`Submitter`]
+pub fn submission_queue(journal: Arc<Journal>) -> (SolutionSender, mpsc::Receiver<Solution>) {
+	let (sender, receiver) = mpsc::channel(SUBMISSION_QUEUE_CAPACITY);
+
+	(SolutionSender { journal, sender }, receiver)
+}
+
+/// Delay before the next attempt after `attempts` failed ones
+pub fn retry_delay(attempts: u32) -> Duration {
+	2u32.checked_pow(attempts.saturating_sub(1))
+		.and_then(|factor| INITIAL_RETRY_DELAY.checked_mul(factor))
+		.unwrap_or(MAX_RETRY_DELAY)
+		.min(MAX_RETRY_DELAY)
+}
+
+#[derive(Debug, Default)]
+pub struct SubmissionStats {
+	/// Solutions handed over to the submitter
+	pub queued: AtomicUsize,
+	/// Solutions written to at least one peer
+	pub sent: AtomicUsize,
+	pub failed_attempts: AtomicUsize,
+	/// Solutions waiting for the next attempt
+	pub pending: AtomicUsize,
+	/// Solutions of a previous random seed, dropped unsent
+	pub discarded: AtomicUsize,
+	/// Solutions no packet could be built for, dropped unsent
+	pub dropped: AtomicUsize,
+	/// Sent solutions a peer relayed back
+	pub acknowledged: AtomicUsize,
+}
+
+/// Counters of a peer for the status line
+#[derive(Debug, Clone)]
+pub struct PeerStatus {
+	pub addr: String,
+	pub stats: Arc<PeerStats>,
+	/// What was read from the peer
+	pub received: Arc<ReceiveStats>,
+	pub connection: watch::Receiver<ConnectionState>,
+}
+
+/// Worker side of the submission queue
+#[derive(Debug, Clone)]
+pub struct SolutionSender {
+	journal: Arc<Journal>,
+	sender: mpsc::Sender<Solution>,
+}
+
+impl SolutionSender {
+	/// Records the solution as found and queues it, blocking while the queue is full.
+	/// Only for the mining threads, it panics in async code
+	pub fn submit(&self, solution: Solution) -> Result<(), SendError<Solution>> {
+		if let Err(err) = self.journal.record(SolutionState::Found, &solution) {
+			log::error!("Failed to write the solution {} to {}: {err}", get_hex_from_64(&solution.nonce), self.journal.path().display());
+		}
+
+		self.sender.blocking_send(solution)
+	}
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PendingSolution {
+	solution: Solution,
+	attempts: u32,
+	next_attempt: Instant,
+}
+
+/// Owns every solution from the moment it leaves the queue until it is written to a peer
+pub struct Submitter {
+	peers: PeerSet,
+	connections: ConnectionManager,
+	connection_events: broadcast::Receiver<ConnectionEvent>,
+	protocol: Protocol,
+	journal: Arc<Journal>,
+	receiver: mpsc::Receiver<Solution>,
+	pending: VecDeque<PendingSolution>,
+	/// Sent solutions by the signature of their packet, until a peer relays the packet back
+	unacknowledged: VecDeque<(Signature, Solution)>,
+	stats: Arc<SubmissionStats>,
+	packet_entropy: Box<dyn NonceSource>,
+	/// Identity signing the packets, they are anonymous without it
+	signer: Option<KeyPair>,
+	/// Solutions of another random seed than the current one are discarded
+	epochs: Option<watch::Receiver<Arc<Epoch>>>,
+	/// New peers replace the current ones
+	peer_configs: Option<watch::Receiver<PeerConfig>>,
+	peer_status: watch::Sender<Vec<PeerStatus>>,
+}
+
+impl Submitter {
+	/// Connects to the peers, must be called within a tokio runtime
+	pub fn new(peers: PeerSet, protocol: Protocol, journal: Arc<Journal>, receiver: mpsc::Receiver<Solution>, packet_entropy: Box<dyn NonceSource>, signer: Option<KeyPair>) -> Self {
+		let connections = ConnectionManager::new(&peers.addrs());
+		let connection_events = connections.subscribe();
+
+		let submitter = Submitter {
+			peers,
+			connections,
+			connection_events,
+			protocol,
+			journal,
+			receiver,
+			pending: VecDeque::new(),
+			unacknowledged: VecDeque::new(),
+			stats: Arc::new(SubmissionStats::default()),
+			packet_entropy,
+			signer,
+			epochs: None,
+			peer_configs: None,
+			peer_status: watch::Sender::new(Vec::new()),
+		};
+		submitter.publish_peer_status();
+
+		submitter
+	}
+
+	/// Drops the solutions of the previous random seed on every epoch switch, the nodes reject them anyway
+	pub fn follow_epochs(&mut self, epochs: watch::Receiver<Arc<Epoch>>) {
+		self.epochs = Some(epochs);
+		self.discard_stale_solutions();
+	}
+
+	/// Switches to the peers of every new config, the remaining peers keep their connection and counters
+	pub fn follow_peers(&mut self, peer_configs: watch::Receiver<PeerConfig>) {
+		self.peer_configs = Some(peer_configs);
+		self.update_peers();
+	}
+
+	/// Notified whenever the peers change
+	pub fn subscribe_peer_status(&self) -> watch::Receiver<Vec<PeerStatus>> {
+		self.peer_status.subscribe()
+	}
+
+	pub fn stats(&self) -> &Arc<SubmissionStats> {
+		&self.stats
+	}
+
+	pub fn peer_stats(&self) -> Vec<(String, Arc<PeerStats>)> {
+		self.peers.stats()
+	}
+
+	pub fn connections(&self) -> &ConnectionManager {
+		&self.connections
+	}
+
+	pub fn pending_solutions(&self) -> impl Iterator<Item = &Solution> {
+		self.pending.iter().map(|pending| &pending.solution)
+	}
+
+	/// Queues a solution for an immediate attempt, e.g. one replayed from the journal.
+	/// A solution which is pending already is not queued twice
+	pub fn enqueue(&mut self, solution: Solution) {
+		if self.pending.iter().any(|pending| pending.solution == solution) {
+			return;
+		}
+		if self.is_stale(&solution) {
+			log::info!("Discarding the solution {}, it was found for the previous random seed", get_hex_from_64(&solution.nonce));
+			self.stats.discarded.fetch_add(1, Ordering::Relaxed);
+			return;
+		}
+
+		self.pending.push_back(PendingSolution {
+			solution,
+			attempts: 0,
+			next_attempt: Instant::now(),
+		});
+		self.stats.queued.fetch_add(1, Ordering::Relaxed);
+		self.stats.pending.store(self.pending.len(), Ordering::Relaxed);
+	}
+
+	/// Sends the solutions as they arrive and retries the failed ones with an exponential backoff.
+	/// Ends when the channel is closed and nothing is pending.
+	/// Cancel safe: a solution leaves the submitter only once it is written to a peer
+	pub async fn run(&mut self) {
+		let mut is_open = true;
+		while is_open || !self.pending.is_empty() {
+			let next_attempt = self.pending.iter().map(|pending| pending.next_attempt).min();
+
+			tokio::select!(
+				received = self.receiver.recv(), if is_open => match received {
+					Some(solution) => self.enqueue(solution),
+					None => is_open = false,
+				},
+				Ok(event) = self.connection_events.recv() => self.on_connection_event(event),
+				is_switched = watch_changed(&mut self.epochs) => if is_switched {
+					self.discard_stale_solutions();
+				} else {
+					self.epochs = None;
+				},
+				is_changed = watch_changed(&mut self.peer_configs) => if is_changed {
+					self.update_peers();
+				} else {
+					self.peer_configs = None;
+				},
+				_ = tokio::time::sleep_until(next_attempt.unwrap_or_else(Instant::now)), if next_attempt.is_some() => {
+					let _ = self.send_due(Instant::now()).await;
+				}
+			);
+		}
+	}
+
+	fn is_stale(&self, solution: &Solution) -> bool {
+		self.epochs.as_ref().is_some_and(|epochs| epochs.borrow().random_seed != solution.random_seed)
+	}
+
+	fn discard_stale_solutions(&mut self) {
+		let Some(epochs) = &mut self.epochs else {
+			return;
+		};
+		let random_seed = epochs.borrow_and_update().random_seed;
+
+		let pending = self.pending.len();
+		self.pending.retain(|pending| pending.solution.random_seed == random_seed);
+		let discarded = pending - self.pending.len();
+		if discarded > 0 {
+			log::warn!("Discarded {discarded} pending solutions of the previous random seed");
+			self.stats.discarded.fetch_add(discarded, Ordering::Relaxed);
+			self.stats.pending.store(self.pending.len(), Ordering::Relaxed);
+		}
+	}
+
+	fn update_peers(&mut self) {
+		let Some(peer_configs) = &mut self.peer_configs else {
+			return;
+		};
+		let PeerConfig { addrs, mode } = peer_configs.borrow_and_update().clone();
+		if addrs == self.peers.addrs() && mode == self.peers.mode() {
+			return;
+		}
+
+		self.peers.update(&addrs, mode);
+		self.connections.set_addrs(&addrs);
+		self.publish_peer_status();
+		log::info!("Peers: {} ({})", addrs.join(", "), mode.as_str());
+
+		// The new peers may take the solutions the old ones failed to
+		let now = Instant::now();
+		self.pending.iter_mut().for_each(|pending| pending.next_attempt = pending.next_attempt.min(now));
+	}
+
+	fn publish_peer_status(&self) {
+		let peer_status = self.peers.stats().into_iter().enumerate().map(|(idx, (addr, stats))| PeerStatus {
+			addr,
+			stats,
+			received: self.connections.receive_stats(idx).clone(),
+			connection: self.connections.subscribe_state(idx),
+		}).collect();
+
+		self.peer_status.send_replace(peer_status);
+	}
+
+	/// A restored connection makes its peer available again and the pending solutions due at once
+	fn on_connection_event(&mut self, event: ConnectionEvent) {
+		match event {
+			ConnectionEvent::Connected { addr } => {
+				if let Some(idx) = (0..self.connections.len()).find(|&idx| self.connections.addr(idx) == addr) {
+					self.peers.peer_mut(idx).record_connected();
+				}
+
+				let now = Instant::now();
+				self.pending.iter_mut().for_each(|pending| pending.next_attempt = pending.next_attempt.min(now));
+			}
+			// A node greets every new connection with its public peers
+			ConnectionEvent::Received { addr, message: PeerMessage::ExchangePublicPeers(public_peers) } => {
+				let public_peers = public_peers.iter().map(|ip| ip.to_string()).collect::<Vec<String>>();
+				log::info!("{addr} answered as a Qubic node, its public peers: {}", public_peers.join(", "));
+			}
+			// A node relays the broadcast messages it checked to its peers, the sender included
+			ConnectionEvent::Received { addr, message: PeerMessage::BroadcastMessage { signature, .. } } => self.acknowledge(&addr, &signature),
+			_ => {}
+		}
+	}
+
+	fn acknowledge(&mut self, addr: &str, signature: &Signature) {
+		let Some(position) = self.unacknowledged.iter().position(|(sent_signature, _)| sent_signature == signature) else {
+			return;
+		};
+		let (_, solution) = self.unacknowledged.remove(position).unwrap();
+
+		log::info!("{addr} relayed the solution {}", get_hex_from_64(&solution.nonce));
+		self.stats.acknowledged.fetch_add(1, Ordering::Relaxed);
+		if let Err(err) = self.journal.record(SolutionState::Acknowledged, &solution) {
+			log::error!("Failed to mark the solution {} as acknowledged in the journal: {err}", get_hex_from_64(&solution.nonce));
+		}
+	}
+
+	/// Takes the solutions from the channel until `done` resolves, so a mining thread blocked on a full
+	/// channel gets to finish while nothing runs [`Submitter::run`] anymore
+	pub async fn drain_until<T>(&mut self, done: impl Future<Output = T>) -> T {
+		tokio::pin!(done);
+		loop {
+			tokio::select!(
+				output = &mut done => return output,
+				Some(solution) = self.receiver.recv() => self.enqueue(solution),
+			);
+		}
+	}
+
+	/// Takes what is left in the channel and makes one last attempt for every pending solution,
+	/// regardless of the backoff. Returns the number of solutions still unsent
+	pub async fn flush(&mut self, timeout: Duration) -> usize {
+		self.receiver.close();
+		while let Ok(solution) = self.receiver.try_recv() {
+			self.enqueue(solution);
+		}
+
+		if !self.pending.is_empty() {
+			self.connections.reconnect();
+			let now = Instant::now();
+			self.pending.iter_mut().for_each(|pending| pending.next_attempt = now);
+
+			if tokio::time::timeout(timeout, self.send_due(now)).await.is_err() {
+				log::error!("Sending the last solutions took longer than {} s", timeout.as_secs());
+			}
+		}
+
+		self.pending.len()
+	}
+
+	/// Sends every solution whose next attempt is due at `now` in one write.
+	/// On success they are marked as sent in the journal, on failure their backoff grows.
+	/// A solution whose packet can not be built is dropped at once, see [`SolutionState::Dropped`]
+	async fn send_due(&mut self, now: Instant) -> io::Result<usize> {
+		let result = self.write_due(now).await;
+		match &result {
+			Ok(sent) if sent.is_empty() => {}
+			Ok(sent) => {
+				self.pending.retain(|pending| pending.next_attempt > now);
+				self.stats.sent.fetch_add(sent.len(), Ordering::Relaxed);
+
+				for (solution, signature) in sent {
+					if let Err(err) = self.journal.record(SolutionState::Sent, solution) {
+						log::error!("Failed to mark the solution {} as sent in the journal: {err}", get_hex_from_64(&solution.nonce));
+					}
+					self.unacknowledged.push_back((*signature, *solution));
+				}
+				while self.unacknowledged.len() > MAX_UNACKNOWLEDGED {
+					self.unacknowledged.pop_front();
+				}
+			}
+			Err(err) => {
+				self.stats.failed_attempts.fetch_add(1, Ordering::Relaxed);
+				let mut failed = 0;
+				for pending in self.pending.iter_mut().filter(|pending| pending.next_attempt <= now) {
+					pending.attempts += 1;
+					pending.next_attempt = Instant::now() + retry_delay(pending.attempts);
+					failed += 1;
+					log::debug!("Solution {} failed {} times", get_hex_from_64(&pending.solution.nonce), pending.attempts);
+				}
+				log::warn!("Failed to send {failed} solutions: {err}, they are retried later");
+			}
+		}
+		self.stats.pending.store(self.pending.len(), Ordering::Relaxed);
+
+		result.map(|sent| sent.len())
+	}
+
+	/// The solutions written with the signatures of their packets
+	async fn write_due(&mut self, now: Instant) -> io::Result<Vec<(Solution, Signature)>> {
+		let due_solutions = self.pending.iter()
+			.filter(|pending| pending.next_attempt <= now)
+			.map(|pending| pending.solution)
+			.collect::<Vec<Solution>>();
+		if due_solutions.is_empty() {
+			return Ok(Vec::new());
+		}
+
+		// A solution without a packet would fail every attempt, it is dropped and the others go on
+		let mut data_for_send = Vec::with_capacity(due_solutions.len() * Packet::SIZE);
+		let mut due = Vec::with_capacity(due_solutions.len());
+		let mut dropped = Vec::new();
+		for solution in &due_solutions {
+			match Packet::new(&BROADCAST_MESSAGE, &self.protocol, &solution.public_key, &solution.nonce, self.signer.as_ref(), self.packet_entropy.as_mut()) {
+				Ok(packet) => {
+					packet.encode(&mut data_for_send);
+					due.push((*solution, packet.get_signature()));
+				}
+				Err(err) => {
+					log::error!("Dropping the solution {} for {}: {err}", get_hex_from_64(&solution.nonce), get_id_string(&solution.public_key));
+					if let Err(err) = self.journal.record(SolutionState::Dropped, solution) {
+						log::error!("Failed to mark the solution {} as dropped in the journal: {err}", get_hex_from_64(&solution.nonce));
+					}
+					dropped.push(*solution);
+				}
+			}
+		}
+		if !dropped.is_empty() {
+			self.pending.retain(|pending| !dropped.contains(&pending.solution));
+			self.stats.dropped.fetch_add(dropped.len(), Ordering::Relaxed);
+		}
+		if due.is_empty() {
+			return Ok(due);
+		}
+
+		log::info!("TCP: will be sent {} packets({} Bytes)", due.len(), data_for_send.len());
+		let data_for_send = Arc::<[u8]>::from(data_for_send);
+		let targets = self.peers.targets(now);
+		let mut last_error = None;
+
+		match self.peers.mode() {
+			SubmitMode::RoundRobin => {
+				for idx in targets {
+					let result = self.connections.send(idx, data_for_send.clone()).await;
+					let peer = self.peers.peer_mut(idx);
+					match result {
+						Ok(()) => {
+							log::info!("Sent {} solutions to {}", due.len(), peer.addr);
+							peer.record_success(due.len());
+
+							return Ok(due);
+						}
+						Err(err) => {
+							log::warn!("Failed to send to {}: {err}, trying the next peer", peer.addr);
+							peer.record_failure();
+							last_error = Some(err);
+						}
+					}
+				}
+			}
+			SubmitMode::Broadcast => {
+				let mut writes = JoinSet::new();
+				for idx in targets {
+					let write = self.connections.send(idx, data_for_send.clone());
+					writes.spawn(async move { (idx, write.await) });
+				}
+
+				let mut is_delivered = false;
+				while let Some(joined) = writes.join_next().await {
+					let (idx, result) = joined.map_err(io::Error::other)?;
+					let peer = self.peers.peer_mut(idx);
+					match result {
+						Ok(()) => {
+							log::info!("Sent {} solutions to {}", due.len(), peer.addr);
+							peer.record_success(due.len());
+							is_delivered = true;
+						}
+						Err(err) => {
+							log::warn!("Failed to send to {}: {err}", peer.addr);
+							peer.record_failure();
+							last_error = Some(err);
+						}
+					}
+				}
+
+				if is_delivered {
+					return Ok(due);
+				}
+			}
+		}
+
+		Err(last_error.unwrap_or_else(|| io::Error::other("no peer to send to")))
+	}
+}
+
+/// Resolves on the next change, `false` once the sender is gone. Never without a receiver
+async fn watch_changed<T>(receiver: &mut Option<watch::Receiver<T>>) -> bool {
+	match receiver {
+		Some(receiver) => receiver.changed().await.is_ok(),
+		None => std::future::pending().await,
+	}
+}
+
+#[cfg(test)]
+fn test_submitter(name: &str, addrs: &[String], mode: SubmitMode) -> (Submitter, SolutionSender, crate::test_utils::TestFile) {
+	let journal_file = crate::test_utils::TestFile::new(&format!("{name}.journal"));
+	let journal = Arc::new(Journal::open(journal_file.path()).unwrap());
+	let (solution_sender, receiver) = submission_queue(journal.clone());
+	let submitter = Submitter::new(PeerSet::new(addrs, mode), 142, journal, receiver, Box::new(crate::entropy::ChaChaSource::from_seed([0; 32])), None);
+
+	(submitter, solution_sender, journal_file)
+}
+
+#[test]
+fn test_retry_delay() {
+	assert_eq!(Duration::from_secs(1), retry_delay(1));
+	assert_eq!(Duration::from_secs(2), retry_delay(2));
+	assert_eq!(Duration::from_secs(32), retry_delay(6));
+	assert_eq!(MAX_RETRY_DELAY, retry_delay(7));
+	assert_eq!(MAX_RETRY_DELAY, retry_delay(u32::MAX));
+}
+
+#[tokio::test]
+async fn test_submitter_keeps_solutions_until_sent() {
+	let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+	let addr = listener.local_addr().unwrap();
+	drop(listener);
+
+	let (mut submitter, _solution_sender, journal_file) = test_submitter("keep", &[addr.to_string()], SubmitMode::RoundRobin);
+	submitter.enqueue(test_solution(1));
+	submitter.enqueue(test_solution(2));
+	submitter.enqueue(test_solution(1));
+
+	// Nobody listens, the solutions wait for the next attempt
+	assert!(submitter.send_due(Instant::now()).await.is_err());
+	assert_eq!(2, submitter.pending_solutions().count());
+	assert!(submitter.pending.iter().all(|pending| pending.attempts == 1));
+	assert_eq!(0, submitter.send_due(Instant::now()).await.unwrap_or_default());
+
+	let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+	let receive = tokio::spawn(async move {
+		let (mut stream, _) = listener.accept().await.unwrap();
+		let mut data = Vec::new();
+		tokio::io::AsyncReadExt::read_to_end(&mut stream, &mut data).await.unwrap();
+		data.len()
+	});
+
+	assert_eq!(0, submitter.flush(Duration::from_secs(5)).await);
+	assert_eq!(2, submitter.stats().sent.load(Ordering::Relaxed));
+	assert_eq!(0, submitter.stats().pending.load(Ordering::Relaxed));
+
+	// The connection stays open until the submitter is dropped
+	drop(submitter);
+	assert_eq!(2 * Packet::SIZE, receive.await.unwrap());
+
+	let states = Journal::replay(journal_file.path()).unwrap().into_iter().map(|entry| entry.state).collect::<Vec<_>>();
+	assert_eq!(vec![SolutionState::Sent, SolutionState::Sent], states);
+}
+
+#[tokio::test]
+async fn test_submitter_discards_stale_solutions() {
+	let (mut submitter, _solution_sender, _journal_file) = test_submitter("stale", &["127.0.0.1:1".to_string()], SubmitMode::RoundRobin);
+	submitter.enqueue(test_solution(1));
+
+	let (epoch_sender, epochs) = watch::channel(Arc::new(Epoch::new(&[1; 32], 30)));
+	submitter.follow_epochs(epochs);
+	submitter.enqueue(test_solution(2));
+	submitter.enqueue(Solution { random_seed: [3; 32], ..test_solution(3) });
+	assert_eq!(2, submitter.pending_solutions().count());
+	assert_eq!(1, submitter.stats().discarded.load(Ordering::Relaxed));
+
+	// Only the threshold changes, the solutions stay valid
+	epoch_sender.send(Arc::new(Epoch::new(&[1; 32], 40))).unwrap();
+	submitter.discard_stale_solutions();
+	assert_eq!(2, submitter.pending_solutions().count());
+
+	epoch_sender.send(Arc::new(Epoch::new(&[3; 32], 40))).unwrap();
+	submitter.discard_stale_solutions();
+	assert_eq!(0, submitter.pending_solutions().count());
+	assert_eq!(3, submitter.stats().discarded.load(Ordering::Relaxed));
+	assert_eq!(0, submitter.stats().pending.load(Ordering::Relaxed));
+
+	submitter.enqueue(Solution { random_seed: [3; 32], ..test_solution(3) });
+	assert_eq!(1, submitter.pending_solutions().count());
+}
+
+#[test]
+fn test_submitter_receives_from_workers() {
+	let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+	let listener = runtime.block_on(tokio::net::TcpListener::bind("127.0.0.1:0")).unwrap();
+	let _runtime_guard = runtime.enter();
+	let (mut submitter, solution_sender, journal_file) = test_submitter("receive", &[listener.local_addr().unwrap().to_string()], SubmitMode::RoundRobin);
+
+	// The workers submit from their own threads
+	std::thread::spawn(move || {
+		solution_sender.submit(test_solution(3)).unwrap();
+		solution_sender.submit(test_solution(4)).unwrap();
+	}).join().unwrap();
+
+	runtime.block_on(async {
+		let accept = tokio::spawn(async move { listener.accept().await.unwrap() });
+		submitter.run().await;
+		accept.await.unwrap();
+	});
+
+	assert_eq!(2, submitter.stats().queued.load(Ordering::Relaxed));
+	assert_eq!(2, submitter.stats().sent.load(Ordering::Relaxed));
+	assert_eq!(0, submitter.pending_solutions().count());
+
+	let states = Journal::replay(journal_file.path()).unwrap().into_iter().map(|entry| entry.state).collect::<Vec<_>>();
+	assert_eq!(vec![SolutionState::Sent, SolutionState::Sent], states);
+}
+
+#[test]
+fn test_submitter_drains_while_workers_stop() {
+	let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+	let _runtime_guard = runtime.enter();
+	let (addr, _) = runtime.block_on(test_peer(false));
+	let journal_file = crate::test_utils::TestFile::new("drain.journal");
+	let journal = Arc::new(Journal::open(journal_file.path()).unwrap());
+
+	// A channel for one, so the worker blocks on its second solution until the submitter takes the first
+	let (sender, receiver) = mpsc::channel(1);
+	let solution_sender = SolutionSender { journal: journal.clone(), sender };
+	let mut submitter = Submitter::new(PeerSet::new(&[addr], SubmitMode::RoundRobin), 142, journal, receiver, Box::new(crate::entropy::ChaChaSource::from_seed([0; 32])), None);
+	let worker = std::thread::spawn(move || {
+		solution_sender.submit(test_solution(1)).unwrap();
+		solution_sender.submit(test_solution(2)).unwrap();
+	});
+
+	let joined = runtime.block_on(submitter.drain_until(tokio::task::spawn_blocking(move || worker.join())));
+	assert!(joined.unwrap().is_ok());
+	assert_eq!(2, runtime.block_on(submitter.flush(Duration::ZERO)));
+}
+
+#[cfg(test)]
+async fn test_peer(is_listening: bool) -> (String, Option<tokio::task::JoinHandle<usize>>) {
+	let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+	let addr = listener.local_addr().unwrap().to_string();
+	if !is_listening {
+		return (addr, None);
+	}
+
+	let receive = tokio::spawn(async move {
+		let (mut stream, _) = listener.accept().await.unwrap();
+		let mut data = Vec::new();
+		tokio::io::AsyncReadExt::read_to_end(&mut stream, &mut data).await.unwrap();
+		data.len()
+	});
+
+	(addr, Some(receive))
+}
+
+#[tokio::test]
+async fn test_submitter_fails_over() {
+	let (down_addr, _) = test_peer(false).await;
+	let (up_addr, receive) = test_peer(true).await;
+
+	let (mut submitter, _solution_sender, _journal_file) = test_submitter("failover", &[down_addr, up_addr], SubmitMode::RoundRobin);
+	submitter.enqueue(test_solution(1));
+
+	assert_eq!(1, submitter.send_due(Instant::now()).await.unwrap());
+
+	let stats = submitter.peer_stats();
+	assert_eq!(1, stats[0].1.failures.load(Ordering::Relaxed));
+	assert!(stats[0].1.is_down.load(Ordering::Relaxed));
+	assert_eq!(1, stats[1].1.sent.load(Ordering::Relaxed));
+
+	drop(submitter);
+	assert_eq!(Packet::SIZE, receive.unwrap().await.unwrap());
+}
+
+#[tokio::test]
+async fn test_submitter_broadcasts() {
+	let (first_addr, first_receive) = test_peer(true).await;
+	let (second_addr, second_receive) = test_peer(true).await;
+	let (down_addr, _) = test_peer(false).await;
+
+	let (mut submitter, _solution_sender, _journal_file) = test_submitter("broadcast", &[first_addr, second_addr, down_addr], SubmitMode::Broadcast);
+	submitter.enqueue(test_solution(1));
+	submitter.enqueue(test_solution(2));
+
+	assert_eq!(2, submitter.send_due(Instant::now()).await.unwrap());
+
+	let stats = submitter.peer_stats();
+	assert_eq!(2, stats[0].1.sent.load(Ordering::Relaxed));
+	assert_eq!(2, stats[1].1.sent.load(Ordering::Relaxed));
+	assert_eq!(1, stats[2].1.failures.load(Ordering::Relaxed));
+	assert_eq!(2, submitter.stats().sent.load(Ordering::Relaxed));
+
+	drop(submitter);
+	assert_eq!(2 * Packet::SIZE, first_receive.unwrap().await.unwrap());
+	assert_eq!(2 * Packet::SIZE, second_receive.unwrap().await.unwrap());
+}
+
+#[tokio::test]
+async fn test_submitter_follows_peers() {
+	let (down_addr, _) = test_peer(false).await;
+	let (up_addr, receive) = test_peer(true).await;
+
+	let (mut submitter, solution_sender, _journal_file) = test_submitter("peers", std::slice::from_ref(&down_addr), SubmitMode::RoundRobin);
+	let peer_status = submitter.subscribe_peer_status();
+	let (peer_config_sender, peer_configs) = watch::channel(PeerConfig { addrs: vec![down_addr], mode: SubmitMode::RoundRobin });
+	submitter.follow_peers(peer_configs);
+	submitter.enqueue(test_solution(1));
+	assert!(submitter.send_due(Instant::now()).await.is_err());
+
+	// The failed solution goes to the new peer at once
+	peer_config_sender.send(PeerConfig { addrs: vec![up_addr.clone()], mode: SubmitMode::Broadcast }).unwrap();
+	drop(solution_sender);
+	tokio::time::timeout(Duration::from_millis(900), submitter.run()).await.unwrap();
+
+	assert_eq!(up_addr, peer_status.borrow()[0].addr);
+	assert_eq!(1, peer_status.borrow()[0].stats.sent.load(Ordering::Relaxed));
+	assert_eq!(SubmitMode::Broadcast, submitter.peers.mode());
+
+	drop(submitter);
+	assert_eq!(Packet::SIZE, receive.unwrap().await.unwrap());
+}
+
+#[tokio::test]
+async fn test_submitter_drops_unbuildable_solutions() {
+	let (addr, receive) = test_peer(true).await;
+
+	let (mut submitter, _solution_sender, journal_file) = test_submitter("drop", &[addr], SubmitMode::RoundRobin);
+	submitter.signer = Some(KeyPair::from_seed("bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb").unwrap());
+	let computor_public_key = KeyPair::from_seed("ccccccccccccccccccccccccccccccccccccccccccccccccccccccc").unwrap().public_key();
+	// No key is shared with the identity point, its packet can not be signed
+	submitter.enqueue(Solution { public_key: [1, 0, 0, 0], ..test_solution(1) });
+	submitter.enqueue(Solution { public_key: computor_public_key, ..test_solution(2) });
+
+	assert_eq!(1, submitter.send_due(Instant::now()).await.unwrap());
+	assert_eq!(0, submitter.pending_solutions().count());
+	assert_eq!(1, submitter.stats().sent.load(Ordering::Relaxed));
+	assert_eq!(1, submitter.stats().dropped.load(Ordering::Relaxed));
+	assert_eq!(0, submitter.stats().failed_attempts.load(Ordering::Relaxed));
+
+	// Nothing is left to retry
+	assert_eq!(0, submitter.send_due(Instant::now() + MAX_RETRY_DELAY).await.unwrap());
+
+	drop(submitter);
+	assert_eq!(Packet::SIZE, receive.unwrap().await.unwrap());
+
+	let states = Journal::replay(journal_file.path()).unwrap().into_iter().map(|entry| entry.state).collect::<Vec<_>>();
+	assert_eq!(vec![SolutionState::Dropped, SolutionState::Sent], states);
+}
+
+#[tokio::test]
+async fn test_submitter_records_relayed_solutions() {
+	use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+	let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+	let (mut submitter, _solution_sender, journal_file) = test_submitter("relay", &[listener.local_addr().unwrap().to_string()], SubmitMode::RoundRobin);
+	submitter.enqueue(test_solution(1));
+	submitter.enqueue(test_solution(2));
+
+	// The node relays a message of someone else, then the first packet with the dejavu it set
+	let node = tokio::spawn(async move {
+		let (mut stream, _) = listener.accept().await.unwrap();
+		let mut packets = vec![0u8; 2 * Packet::SIZE];
+		stream.read_exact(&mut packets).await.unwrap();
+		let mut relayed = packets[..Packet::SIZE].to_vec();
+		relayed[4..7].copy_from_slice(&[1, 2, 3]);
+
+		let mut foreign = relayed.clone();
+		foreign[Packet::SIZE - 1] ^= 1;
+		stream.write_all(&foreign).await.unwrap();
+		stream.write_all(&relayed).await.unwrap();
+		stream
+	});
+
+	assert_eq!(2, submitter.send_due(Instant::now()).await.unwrap());
+	assert_eq!(2, submitter.unacknowledged.len());
+	while submitter.unacknowledged.len() == 2 {
+		let event = tokio::time::timeout(Duration::from_secs(5), submitter.connection_events.recv()).await.unwrap().unwrap();
+		submitter.on_connection_event(event);
+	}
+	node.await.unwrap();
+
+	assert_eq!(1, submitter.stats().acknowledged.load(Ordering::Relaxed));
+	assert_eq!(test_solution(2), submitter.unacknowledged[0].1);
+	let states = Journal::replay(journal_file.path()).unwrap().into_iter().map(|entry| (entry.nonce[0], entry.state)).collect::<Vec<_>>();
+	assert_eq!(vec![(1, SolutionState::Acknowledged), (2, SolutionState::Sent)], states);
+}
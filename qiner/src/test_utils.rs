@@ -0,0 +1,37 @@
+//! Fixtures shared by the tests of the modules
+
+use std::path::{Path, PathBuf};
+use crate::miner::Solution;
+
+/// A file of a test in the temporary directory, deleted when the guard is dropped
+pub struct TestFile(PathBuf);
+
+impl TestFile {
+	/// `name` tells the tests apart and holds the extension, e.g. `replay.journal`
+	pub fn new(name: &str) -> Self {
+		let path = std::env::temp_dir().join(format!("qiner-{}-{name}", std::process::id()));
+		let _ = std::fs::remove_file(&path);
+
+		TestFile(path)
+	}
+
+	pub fn path(&self) -> &Path {
+		&self.0
+	}
+}
+
+impl Drop for TestFile {
+	fn drop(&mut self) {
+		let _ = std::fs::remove_file(&self.0);
+	}
+}
+
+/// A solution of the random seed `[1; 32]`, told apart from the others by `nonce`
+pub fn test_solution(nonce: u64) -> Solution {
+	Solution {
+		random_seed: [1; 32],
+		public_key: [1, 2, 3, 4],
+		nonce: [nonce, u64::MAX, 0, 5],
+		score: 30,
+	}
+}
@@ -0,0 +1,256 @@
+use std::alloc::{handle_alloc_error, Layout};
+use std::fmt::{Debug, Formatter};
+use std::fmt;
+use std::mem::size_of;
+use std::ops::{Deref, DerefMut};
+use std::ptr::NonNull;
+use std::slice;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use lib::types::Nonce64;
+use crate::score::NeuronData;
+
+pub const PAGE_SIZE: usize = 4 * 1024;
+pub const HUGE_PAGE_SIZE: usize = 2 * 1024 * 1024;
+
+/// Integers, for which all-zero bytes are a valid value
+pub trait Zeroable: Copy {}
+
+impl Zeroable for u8 {}
+impl Zeroable for u16 {}
+impl Zeroable for u32 {}
+impl Zeroable for u64 {}
+
+/// Zeroed heap buffer aligned to a page, or to a huge page when huge pages are requested.
+/// On Linux it is an anonymous mapping whose pages are only placed when first written
+pub struct AlignedBuffer<T: Zeroable> {
+	ptr: NonNull<T>,
+	len: usize,
+	layout: Layout,
+}
+
+// The buffer owns its memory like a `Box<[T]>` does
+unsafe impl<T: Zeroable + Send> Send for AlignedBuffer<T> {}
+unsafe impl<T: Zeroable + Sync> Sync for AlignedBuffer<T> {}
+
+impl<T: Zeroable> AlignedBuffer<T> {
+	pub fn zeroed(len: usize, huge_pages: bool) -> Self {
+		let layout = get_layout::<T>(len, huge_pages);
+
+		let Some(ptr) = NonNull::new(allocate_zeroed(layout, huge_pages) as *mut T) else {
+			handle_alloc_error(layout);
+		};
+
+		AlignedBuffer { ptr, len, layout }
+	}
+
+	/// What [`AlignedBuffer::allocated_size`] of a buffer of `len` items is
+	pub fn size_for(len: usize, huge_pages: bool) -> usize {
+		get_layout::<T>(len, huge_pages).size()
+	}
+
+	/// Allocated bytes, including the padding up to the alignment
+	pub fn allocated_size(&self) -> usize {
+		self.layout.size()
+	}
+}
+
+impl<T: Zeroable> Deref for AlignedBuffer<T> {
+	type Target = [T];
+
+	fn deref(&self) -> &[T] {
+		unsafe { slice::from_raw_parts(self.ptr.as_ptr(), self.len) }
+	}
+}
+
+impl<T: Zeroable> DerefMut for AlignedBuffer<T> {
+	fn deref_mut(&mut self) -> &mut [T] {
+		unsafe { slice::from_raw_parts_mut(self.ptr.as_ptr(), self.len) }
+	}
+}
+
+impl<T: Zeroable> Drop for AlignedBuffer<T> {
+	fn drop(&mut self) {
+		unsafe { deallocate(self.ptr.as_ptr() as *mut u8, self.layout) }
+	}
+}
+
+impl<T: Zeroable> Debug for AlignedBuffer<T> {
+	fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+		f.debug_struct("AlignedBuffer").field("len", &self.len).field("align", &self.layout.align()).finish()
+	}
+}
+
+fn get_layout<T>(len: usize, huge_pages: bool) -> Layout {
+	let align = if huge_pages { HUGE_PAGE_SIZE } else { PAGE_SIZE };
+	let size = (len * size_of::<T>()).max(1).next_multiple_of(align);
+
+	Layout::from_size_align(size, align).unwrap()
+}
+
+/// Maps more than asked and unmaps the ends, so that the mapping starts on the alignment.
+/// Huge pages are advised before anything touches the memory, otherwise it is faulted in as small pages
+#[cfg(target_os = "linux")]
+fn allocate_zeroed(layout: Layout, huge_pages: bool) -> *mut u8 {
+	let padding = layout.align() - PAGE_SIZE;
+	let mapped_size = layout.size() + padding;
+	let mapped = unsafe {
+		libc::mmap(std::ptr::null_mut(), mapped_size, libc::PROT_READ | libc::PROT_WRITE, libc::MAP_PRIVATE | libc::MAP_ANONYMOUS, -1, 0)
+	};
+	if mapped == libc::MAP_FAILED {
+		return std::ptr::null_mut();
+	}
+
+	let mapped = mapped as usize;
+	let start = mapped.next_multiple_of(layout.align());
+	let end = start + layout.size();
+	unsafe {
+		if start > mapped {
+			libc::munmap(mapped as *mut libc::c_void, start - mapped);
+		}
+		if mapped + mapped_size > end {
+			libc::munmap(end as *mut libc::c_void, mapped + mapped_size - end);
+		}
+	}
+
+	if huge_pages && unsafe { libc::madvise(start as *mut libc::c_void, layout.size(), libc::MADV_HUGEPAGE) } != 0 {
+		log::warn!("Huge pages are not available: {}", std::io::Error::last_os_error());
+	}
+
+	start as *mut u8
+}
+
+#[cfg(target_os = "linux")]
+unsafe fn deallocate(ptr: *mut u8, layout: Layout) {
+	libc::munmap(ptr as *mut libc::c_void, layout.size());
+}
+
+#[cfg(not(target_os = "linux"))]
+fn allocate_zeroed(layout: Layout, huge_pages: bool) -> *mut u8 {
+	if huge_pages {
+		log::warn!("Huge pages are only supported on Linux");
+	}
+
+	unsafe { std::alloc::alloc_zeroed(layout) }
+}
+
+#[cfg(not(target_os = "linux"))]
+unsafe fn deallocate(ptr: *mut u8, layout: Layout) {
+	std::alloc::dealloc(ptr, layout);
+}
+
+/// Everything a worker needs for one round of `find_solution`
+#[derive(Debug)]
+pub struct WorkerState {
+	pub neuron_data: NeuronData,
+	pub nonce: Nonce64,
+}
+
+impl WorkerState {
+	pub fn new(huge_pages: bool) -> Self {
+		WorkerState {
+			neuron_data: NeuronData::new(huge_pages),
+			nonce: Nonce64::default(),
+		}
+	}
+
+	pub fn allocated_size(&self) -> usize {
+		self.neuron_data.allocated_size()
+	}
+}
+
+/// Worker states, which workers take on start and give back on stop.
+/// A state is allocated by the first worker taking it, so its pages are placed on the NUMA node of that worker
+#[derive(Debug)]
+pub struct WorkerPool {
+	free: Mutex<Vec<WorkerState>>,
+	huge_pages: bool,
+	allocated_size: AtomicUsize,
+}
+
+impl WorkerPool {
+	pub fn new(huge_pages: bool) -> Self {
+		WorkerPool {
+			free: Mutex::new(Vec::new()),
+			huge_pages,
+			allocated_size: AtomicUsize::new(0),
+		}
+	}
+
+	/// Takes a free state, a new one is allocated when the pool is empty
+	pub fn acquire(&self) -> WorkerState {
+		if let Some(state) = self.free.lock().unwrap().pop() {
+			return state;
+		}
+
+		let state = WorkerState::new(self.huge_pages);
+		self.allocated_size.fetch_add(state.allocated_size(), Ordering::Relaxed);
+
+		state
+	}
+
+	pub fn release(&self, state: WorkerState) {
+		self.free.lock().unwrap().push(state);
+	}
+
+	/// Bytes allocated for all the states, free or in use
+	pub fn allocated_size(&self) -> usize {
+		self.allocated_size.load(Ordering::Relaxed)
+	}
+
+	/// Bytes of one state
+	pub fn state_size(&self) -> usize {
+		NeuronData::size_for(self.huge_pages)
+	}
+
+	pub fn is_huge_pages(&self) -> bool {
+		self.huge_pages
+	}
+}
+
+#[test]
+fn test_aligned_buffer() {
+	let mut buffer = AlignedBuffer::<u64>::zeroed(1000, false);
+
+	assert_eq!(0, buffer.as_ptr() as usize % PAGE_SIZE);
+	assert_eq!(2 * PAGE_SIZE, buffer.allocated_size());
+	assert_eq!(2 * PAGE_SIZE, AlignedBuffer::<u64>::size_for(1000, false));
+	assert!(buffer.iter().all(|item| *item == 0));
+
+	buffer[999] = u64::MAX;
+	assert_eq!(u64::MAX, buffer[999]);
+}
+
+#[test]
+fn test_worker_pool_reuses_states() {
+	let pool = WorkerPool::new(false);
+	assert_eq!(0, pool.allocated_size());
+
+	let mut state = pool.acquire();
+	let allocated_size = pool.allocated_size();
+	assert_eq!(pool.state_size(), allocated_size);
+	state.nonce = [1; 4];
+	let links_ptr = state.neuron_data.links_ptr();
+	pool.release(state);
+
+	let state = pool.acquire();
+	assert_eq!(links_ptr, state.neuron_data.links_ptr());
+	assert_eq!(allocated_size, pool.allocated_size());
+	assert_eq!([1; 4], state.nonce);
+
+	let _second_state = pool.acquire();
+	assert_eq!(2 * allocated_size, pool.allocated_size());
+}
+
+#[test]
+fn test_huge_page_aligned_buffer() {
+	let mut buffer = AlignedBuffer::<u32>::zeroed(HUGE_PAGE_SIZE / 4 + 1, true);
+
+	assert_eq!(0, buffer.as_ptr() as usize % HUGE_PAGE_SIZE);
+	assert_eq!(2 * HUGE_PAGE_SIZE, buffer.allocated_size());
+	assert!(buffer.iter().all(|item| *item == 0));
+
+	let last = buffer.len() - 1;
+	buffer[last] = u32::MAX;
+	assert_eq!(u32::MAX, buffer[last]);
+}
@@ -0,0 +1,125 @@
+//! Pins the nonces and scores of the deterministic mode, a change in them changes which solutions are found
+
+use lib::config::{parse_identities, IdSchedule};
+use lib::types::Nonce64;
+use qiner::bench::{get_bench_public_key, BENCH_ID, BENCH_RANDOM_SEED};
+use qiner::converters::{get_64_from_hex, get_hex_from_64};
+use qiner::entropy::{ChaChaSource, NonceSource};
+use qiner::miner::Miner;
+use qiner::score::NeuronData;
+
+const MINING_SEED: u64 = 42;
+
+fn new_miner(solution_threshold: usize) -> Miner {
+	Miner::new(get_bench_public_key(), &BENCH_RANDOM_SEED, solution_threshold)
+}
+
+/// Nonces of a worker stream with their scores
+fn worker_scores(miner: &Miner, worker_idx: usize, iterations: usize) -> Vec<(String, usize)> {
+	let mut nonce_source = ChaChaSource::for_worker(MINING_SEED, worker_idx);
+	let mut neuron_data = NeuronData::default();
+
+	(0..iterations).map(|_| {
+		let mut nonce = Nonce64::default();
+		nonce_source.fill(&mut nonce).unwrap();
+		let score = miner.compute_score(0, &nonce, &mut neuron_data);
+
+		(get_hex_from_64(&nonce), score)
+	}).collect()
+}
+
+#[test]
+fn test_first_worker_scores() {
+	let expected = vec![
+		("21e29de20570e528b2f1c68b62aa9452d2dce06fcfb85a5b3558cc35c554c59b".to_string(), 0),
+		("0cb65d38ddea1d250ca32540a422096d0f9456a24acb04f943eae2391f8cd4ba".to_string(), 0),
+		("81f1e22ffc6ff067289dd2b5a78108754f56f02c21fa1abcd63a10afdaa3d230".to_string(), 2),
+	];
+
+	assert_eq!(expected, worker_scores(&new_miner(0), 0, 3));
+}
+
+#[test]
+fn test_second_worker_scores() {
+	let expected = vec![
+		("ccc9cd472a81802c25b868c86c246749527d067ffd122952fa21925cd4cfe45e".to_string(), 0),
+		("7717b458020e3d252cd69a305f0bd12e9f7db8aff03ec0278584a77c5daf77bb".to_string(), 1),
+	];
+
+	assert_eq!(expected, worker_scores(&new_miner(0), 1, 2));
+}
+
+#[test]
+fn test_high_scores() {
+	// The best ones of the first 3000 nonces of the first worker, the iteration is in the comment
+	let expected = [
+		("f6510c31514c48eb169d225eb44c37b2c2fdf83c46115ee847ede144a994a48d", 9), // 17
+		("970ae6ca9ea4c25193c680e1622636095e1882062ff583ffd649e9a489c81976", 8), // 760
+		("d88ef032ce872c3772ca8938179d407a4962f87201cfe5992a4de4358f376e8e", 9), // 1310
+		("b4377ce5c954fd964e04395d7aaec2459041e0825d3c3a0a67ba1e06556da742", 11), // 1314
+		("49c2a813975b77794bb174888abc97739e8dab1cb5f779d6564fbed2721ccc3e", 11), // 1660
+	];
+	let miner = new_miner(0);
+	let mut neuron_data = NeuronData::default();
+
+	for (nonce, score) in expected {
+		let nonce = get_64_from_hex::<4>(nonce).unwrap();
+		assert_eq!(score, miner.compute_score(0, &nonce, &mut neuron_data), "{}", get_hex_from_64(&nonce));
+	}
+}
+
+#[test]
+fn test_find_solution_is_reproducible() {
+	let miner = new_miner(2);
+	let mut nonce_source = ChaChaSource::for_worker(MINING_SEED, 0);
+	let mut neuron_data = NeuronData::default();
+	let mut nonce = Nonce64::default();
+
+	let mut find_score = || miner.find_solution(0, &mut nonce, &mut neuron_data, &mut nonce_source).unwrap().map(|solution| solution.score);
+
+	assert_eq!(None, find_score());
+	assert_eq!(None, find_score());
+	assert_eq!(Some(2), find_score());
+	assert_eq!("81f1e22ffc6ff067289dd2b5a78108754f56f02c21fa1abcd63a10afdaa3d230", get_hex_from_64(&nonce));
+}
+
+#[test]
+fn test_epoch_switch_changes_scores() {
+	let miner = new_miner(0);
+	let expected = worker_scores(&miner, 0, 3);
+
+	// Only the threshold changes, the scores stay
+	assert!(miner.update_epoch(&BENCH_RANDOM_SEED, 1));
+	assert_eq!(expected, worker_scores(&miner, 0, 3));
+	assert!(!miner.update_epoch(&BENCH_RANDOM_SEED, 1));
+
+	let mut random_seed = BENCH_RANDOM_SEED;
+	random_seed[8] = 1;
+	assert!(miner.update_epoch(&random_seed, 1));
+	assert_eq!(random_seed, miner.get_random_seed());
+	assert_ne!(expected, worker_scores(&miner, 0, 3));
+
+	// Back at the old seed, the old scores again
+	assert!(miner.update_epoch(&BENCH_RANDOM_SEED, 1));
+	assert_eq!(expected, worker_scores(&miner, 0, 3));
+}
+
+#[test]
+fn test_solutions_are_tagged_with_their_id() {
+	const SECOND_ID: &str = "BZBQFLLBNCXEMGLOBHUVFTLUPLVCPQUASSILFABOFFBCADQSSUPNWLZBQEXK";
+	let identities = parse_identities(&format!("{BENCH_ID},{SECOND_ID}:2")).unwrap();
+	let miner = Miner::with_identities(&identities, IdSchedule::RoundRobin, &BENCH_RANDOM_SEED, 0);
+	let mut neuron_data = NeuronData::default();
+	let mut nonce = Nonce64::default();
+
+	let mut nonce_source = ChaChaSource::for_worker(MINING_SEED, 0);
+	let solution = miner.find_solution(1, &mut nonce, &mut neuron_data, &mut nonce_source).unwrap().unwrap();
+	assert_eq!("21e29de20570e528b2f1c68b62aa9452d2dce06fcfb85a5b3558cc35c554c59b", get_hex_from_64(&solution.nonce));
+	assert_eq!(identities[1].public_key, solution.public_key);
+	assert_eq!(solution.score, miner.compute_score(1, &solution.nonce, &mut neuron_data));
+	// The first ID scores as if it were mined alone
+	assert_eq!(0, miner.compute_score(0, &solution.nonce, &mut neuron_data));
+
+	let mut picker = miner.new_identity_picker();
+	assert_eq!(vec![1, 0, 1], (0..3).map(|_| picker.next(0, 1)).collect::<Vec<_>>());
+}
@@ -0,0 +1,229 @@
+//! Runs the miner binary against the mock node, the whole way from a found solution to a node which checks it
+
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::time::Duration;
+use lib::env_names::{
+	ENV_CONFIG,
+	ENV_ID,
+	ENV_JOURNAL_FILE,
+	ENV_MINING_SEED,
+	ENV_NUMBER_OF_THREADS,
+	ENV_PEERS,
+	ENV_RANDOM_SEED,
+	ENV_SERVER_IP,
+	ENV_SIGNING_SEED,
+	ENV_SOLUTION_THRESHOLD,
+	ENV_VERSION,
+};
+use lib::random_seed::parse_random_seed;
+use lib::types::network::protocols::END_RESPONSE;
+use mock_node::{FailureMode, MockNode, NodeConfig, Verdict};
+use qiner::network::RequestResponseHeader;
+use qiner::signing::KeyPair;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpSocket;
+
+/// The ID of this seed is mined for, so the node can decrypt the signed packets as its computor
+const COMPUTOR_SEED: &str = "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa";
+const ID: &str = "BZBQFLLBNCXEMGLOBHUVFTLUPLVCPQUASSILFABOFFBCADQSSUPNWLZBQEXK";
+const SIGNING_SEED: &str = "bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb";
+const RANDOM_SEED: &str = "1,0,233,9,136,69,43,139,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0";
+/// Low enough for a solution every few iterations
+const SOLUTION_THRESHOLD: usize = 2;
+
+const PACKET_TIMEOUT: Duration = Duration::from_secs(120);
+const JOURNAL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+async fn start_node(failure: FailureMode) -> MockNode {
+	let config = NodeConfig {
+		random_seed: parse_random_seed(RANDOM_SEED).unwrap(),
+		solution_threshold: SOLUTION_THRESHOLD,
+		computor: Some(KeyPair::from_seed(COMPUTOR_SEED).unwrap()),
+		failure,
+	};
+
+	MockNode::start("127.0.0.1:0".parse().unwrap(), config).await.unwrap()
+}
+
+/// A journal file of a test in the temporary directory, deleted when the guard is dropped
+struct TestJournalFile(PathBuf);
+
+impl TestJournalFile {
+	fn new(name: &str) -> Self {
+		let path = std::env::temp_dir().join(format!("qiner-{}-{name}.journal", std::process::id()));
+		let _ = std::fs::remove_file(&path);
+
+		TestJournalFile(path)
+	}
+
+	fn path(&self) -> &Path {
+		&self.0
+	}
+}
+
+impl Drop for TestJournalFile {
+	fn drop(&mut self) {
+		let _ = std::fs::remove_file(&self.0);
+	}
+}
+
+/// Waits until a line of the journal has the state, the node relays every checked packet back
+async fn wait_for_journal_state(journal: &Path, state: &str, timeout: Duration) -> bool {
+	let deadline = tokio::time::Instant::now() + timeout;
+	while tokio::time::Instant::now() < deadline {
+		let lines = std::fs::read_to_string(journal).unwrap_or_default();
+		if lines.lines().any(|line| line.split_whitespace().nth(1) == Some(state)) {
+			return true;
+		}
+		tokio::time::sleep(JOURNAL_POLL_INTERVAL).await;
+	}
+
+	false
+}
+
+/// The miner configured through the environment only, with the node as its single peer
+fn qiner_command(node_addr: SocketAddr, journal: &Path) -> Command {
+	let mut command = Command::new(env!("CARGO_BIN_EXE_qiner"));
+	command
+		.env_remove(ENV_CONFIG)
+		.env_remove(ENV_SERVER_IP)
+		.env(ENV_ID, ID)
+		.env(ENV_PEERS, node_addr.to_string())
+		.env(ENV_VERSION, "1.142.1")
+		.env(ENV_RANDOM_SEED, RANDOM_SEED)
+		.env(ENV_SOLUTION_THRESHOLD, SOLUTION_THRESHOLD.to_string())
+		.env(ENV_NUMBER_OF_THREADS, "1")
+		.env(ENV_MINING_SEED, "42")
+		.env(ENV_JOURNAL_FILE, journal)
+		.stdout(Stdio::null())
+		.stderr(Stdio::null());
+
+	command
+}
+
+/// Kills the miner when a test fails before stopping it
+struct MinerProcess(Option<Child>);
+
+impl MinerProcess {
+	/// Sends SIGTERM and waits for the miner to flush and exit
+	#[cfg(target_os = "linux")]
+	async fn stop(mut self) -> ExitStatus {
+		let mut child = self.0.take().unwrap();
+		unsafe { libc::kill(child.id() as libc::pid_t, libc::SIGTERM) };
+
+		tokio::task::spawn_blocking(move || child.wait()).await.unwrap().unwrap()
+	}
+}
+
+impl Drop for MinerProcess {
+	fn drop(&mut self) {
+		if let Some(child) = &mut self.0 {
+			let _ = child.kill();
+			let _ = child.wait();
+		}
+	}
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_send_test_packet_to_mock_node() {
+	let node = start_node(FailureMode::None).await;
+	let journal = TestJournalFile::new("send-test-packet");
+
+	let mut command = qiner_command(node.local_addr(), journal.path());
+	// The zero nonce scores 3 here, which reaches the threshold of the mining tests
+	command.env(ENV_SIGNING_SEED, SIGNING_SEED).env(ENV_SOLUTION_THRESHOLD, "22").arg("send-test-packet");
+	let status = tokio::task::spawn_blocking(move || command.status()).await.unwrap().unwrap();
+	assert!(status.success());
+
+	// Signed, so only readable as the computor
+	let received = node.wait_for_packets(1, PACKET_TIMEOUT).await;
+	assert_eq!(1, received.len());
+	assert_eq!(KeyPair::from_seed(SIGNING_SEED).unwrap().public_key(), received[0].packet.get_message().get_source_public_key());
+	assert_eq!(Some([0; 4]), received[0].nonce.map(|(nonce, _)| nonce));
+	assert_ne!(Verdict::InvalidSignature, received[0].verdict);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_send_test_packet_refuses_a_solution() {
+	let node = start_node(FailureMode::None).await;
+	let journal = TestJournalFile::new("send-test-solution");
+
+	// Every nonce reaches a zero threshold, the zero one too
+	let mut command = qiner_command(node.local_addr(), journal.path());
+	command.env(ENV_SOLUTION_THRESHOLD, "0").arg("send-test-packet");
+	let status = tokio::task::spawn_blocking(move || command.status()).await.unwrap().unwrap();
+
+	assert!(!status.success());
+	assert_eq!(0, node.connections());
+	assert!(node.received().is_empty());
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test(flavor = "multi_thread")]
+async fn test_mine_to_mock_node() {
+	let node = start_node(FailureMode::None).await;
+	let journal = TestJournalFile::new("mine");
+
+	let miner = MinerProcess(Some(qiner_command(node.local_addr(), journal.path()).spawn().unwrap()));
+	let received = node.wait_for_packets(2, PACKET_TIMEOUT).await;
+	let acknowledged = wait_for_journal_state(journal.path(), "acknowledged", PACKET_TIMEOUT).await;
+	let status = miner.stop().await;
+
+	assert!(received.len() >= 2);
+	assert!(acknowledged);
+	assert!(received.iter().all(|received| received.verdict == Verdict::Solution), "{received:?}");
+	assert!(status.success(), "{status}");
+}
+
+#[cfg(target_os = "linux")]
+#[tokio::test(flavor = "multi_thread")]
+async fn test_mine_rides_out_node_failures() {
+	let node = start_node(FailureMode::Refuse).await;
+	let journal = TestJournalFile::new("node-failures");
+
+	let miner = MinerProcess(Some(qiner_command(node.local_addr(), journal.path()).spawn().unwrap()));
+	tokio::time::sleep(Duration::from_secs(3)).await;
+	assert_eq!(0, node.connections());
+
+	// Every connection is reset before a packet is read
+	node.set_failure(FailureMode::Reset);
+	tokio::time::sleep(Duration::from_secs(5)).await;
+	assert!(node.received().is_empty());
+
+	// The solutions found meanwhile go out once the miner is connected again
+	node.set_failure(FailureMode::None);
+	let received = node.wait_for_packets(2, PACKET_TIMEOUT).await;
+	let status = miner.stop().await;
+
+	assert!(node.connections() >= 2);
+	assert!(received.len() >= 2);
+	assert!(received.iter().all(|received| received.verdict == Verdict::Solution), "{received:?}");
+	assert!(status.success(), "{status}");
+}
+
+/// Writes one frame the node skips, far more than the buffers of both sides hold, `false` on a timeout
+async fn write_large_frame(node: &MockNode, timeout: Duration) -> bool {
+	let socket = TcpSocket::new_v4().unwrap();
+	socket.set_send_buffer_size(4 * 1024).unwrap();
+	let mut stream = socket.connect(node.local_addr()).await.unwrap();
+
+	let size = 64 * 1024;
+	let mut data = Vec::with_capacity(size);
+	RequestResponseHeader::new(&END_RESPONSE, &size, &0).encode(&mut data);
+	data.resize(size, 0);
+
+	tokio::time::timeout(timeout, stream.write_all(&data)).await.is_ok_and(|written| written.is_ok())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_slow_read_runs_into_the_write_timeout() {
+	let node = start_node(FailureMode::None).await;
+	assert!(write_large_frame(&node, Duration::from_secs(10)).await);
+
+	node.set_failure(FailureMode::SlowRead(Duration::from_secs(1)));
+	assert!(!write_large_frame(&node, Duration::from_secs(2)).await);
+	assert_eq!(2, node.connections());
+	assert!(node.received().is_empty());
+}